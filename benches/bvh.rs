@@ -0,0 +1,159 @@
+// compares tracing rays through the flattened `Bvh` with the tree of `Arc` nodes it replaced,
+// and both builders. run with `cargo bench --bench bvh`
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use raytracing_2_rs::aabb::AABB;
+use raytracing_2_rs::bvh::{Bvh, BvhBuilder};
+use raytracing_2_rs::material::Lambertian;
+use raytracing_2_rs::sphere::Sphere;
+use raytracing_2_rs::utils::INFINITY;
+use raytracing_2_rs::{Color, HitRecord, Hittable, Interval, Point3, RandomSampler, Ray, Sampler};
+use std::sync::Arc;
+
+// the hierarchy as it was before flattening, kept as the baseline: every node is an `Arc`, a
+// single object fills both children, and nodes split in half along a random axis
+struct ArcBvhNode {
+    left: Arc<dyn Hittable>,
+    right: Arc<dyn Hittable>,
+    bbox: AABB,
+}
+
+impl ArcBvhNode {
+    fn new(objects: &[Arc<dyn Hittable>], sampler: &mut RandomSampler) -> Self {
+        match objects.len() {
+            1 => Self {
+                left: objects[0].clone(),
+                right: objects[0].clone(),
+                bbox: objects[0].bounding_box(),
+            },
+            2 => Self {
+                left: objects[0].clone(),
+                right: objects[1].clone(),
+                bbox: AABB::merge(&objects[0].bounding_box(), &objects[1].bounding_box()),
+            },
+            _ => {
+                let axis = sampler.random_usize(0, 3) as u32;
+                let mut objects = objects.to_vec();
+                objects.sort_by(|a, b| {
+                    let a = a.bounding_box().axis(axis).min;
+                    let b = b.bounding_box().axis(axis).min;
+                    a.total_cmp(&b)
+                });
+                let mid = objects.len() / 2;
+                let left = Self::new(&objects[..mid], sampler);
+                let right = Self::new(&objects[mid..], sampler);
+                let bbox = AABB::merge(&left.bbox, &right.bbox);
+                Self {
+                    left: Arc::new(left),
+                    right: Arc::new(right),
+                    bbox,
+                }
+            }
+        }
+    }
+}
+
+impl Hittable for ArcBvhNode {
+    fn bounding_box(&self) -> AABB {
+        self.bbox.clone()
+    }
+
+    fn hit(&self, ray: &Ray, ray_t: &mut Interval, sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        if !self.bbox.hit(ray, ray_t.clone()) {
+            return None;
+        }
+        let hit_left = self.left.hit(ray, ray_t, sampler);
+        let mut new_interval = Interval {
+            min: ray_t.min,
+            max: match hit_left {
+                Some(ref rec) => rec.t,
+                None => ray_t.max,
+            },
+        };
+        let hit_right = self.right.hit(ray, &mut new_interval, sampler);
+        match (hit_left, hit_right) {
+            (Some(rec), None) => Some(rec),
+            (_, Some(rec)) => Some(rec),
+            _ => None,
+        }
+    }
+}
+
+// spheres of varied sizes scattered through a 100 unit cube
+fn spheres(count: usize, sampler: &mut RandomSampler) -> Vec<Arc<dyn Hittable>> {
+    let material = Arc::new(Lambertian::new_from_color(Color::new(0.5, 0.5, 0.5)));
+    (0..count)
+        .map(|_| {
+            let center = Point3::new(
+                sampler.random_f64_range(-50.0, 50.0),
+                sampler.random_f64_range(-50.0, 50.0),
+                sampler.random_f64_range(-50.0, 50.0),
+            );
+            let radius = sampler.random_f64_range(0.2, 2.0);
+            Arc::new(Sphere::new(center, radius, material.clone())) as Arc<dyn Hittable>
+        })
+        .collect()
+}
+
+// rays from outside the cube towards points inside it
+fn rays(count: usize, sampler: &mut RandomSampler) -> Vec<Ray> {
+    (0..count)
+        .map(|_| {
+            let origin = Point3::new(
+                sampler.random_f64_range(-100.0, 100.0),
+                sampler.random_f64_range(-100.0, 100.0),
+                -150.0,
+            );
+            let target = Point3::new(
+                sampler.random_f64_range(-50.0, 50.0),
+                sampler.random_f64_range(-50.0, 50.0),
+                sampler.random_f64_range(-50.0, 50.0),
+            );
+            Ray::new(origin.clone(), &target - &origin, 0.0)
+        })
+        .collect()
+}
+
+fn trace(world: &dyn Hittable, rays: &[Ray], sampler: &mut RandomSampler) -> usize {
+    rays.iter()
+        .filter(|ray| {
+            world
+                .hit(ray, &mut Interval::new(0.001, INFINITY), sampler)
+                .is_some()
+        })
+        .count()
+}
+
+fn bench_traversal(c: &mut Criterion) {
+    let mut sampler = RandomSampler::new(0);
+    let objects = spheres(10_000, &mut sampler);
+    let rays = rays(10_000, &mut sampler);
+    let mut group = c.benchmark_group("trace 10000 rays, 10000 spheres");
+    let tree = ArcBvhNode::new(&objects, &mut RandomSampler::new(1));
+    group.bench_function("arc tree", |b| {
+        b.iter(|| trace(black_box(&tree), &rays, &mut sampler))
+    });
+    for builder in [BvhBuilder::Sah, BvhBuilder::Median] {
+        let flat = Bvh::with_builder(&objects, builder);
+        group.bench_function(format!("flat, {}", builder), |b| {
+            b.iter(|| trace(black_box(&flat), &rays, &mut sampler))
+        });
+    }
+    group.finish();
+}
+
+fn bench_build(c: &mut Criterion) {
+    let objects = spheres(10_000, &mut RandomSampler::new(0));
+    let mut group = c.benchmark_group("build over 10000 spheres");
+    group.bench_function("arc tree", |b| {
+        b.iter(|| ArcBvhNode::new(black_box(&objects), &mut RandomSampler::new(1)))
+    });
+    for builder in [BvhBuilder::Sah, BvhBuilder::Median] {
+        group.bench_function(format!("{}", builder), |b| {
+            b.iter(|| Bvh::with_builder(black_box(&objects), builder))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_traversal, bench_build);
+criterion_main!(benches);
@@ -4,6 +4,7 @@ use crate::Ray;
 use crate::Vec3;
 use std::ops;
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, Default)]
 pub struct AABB {
     pub x: Interval,
@@ -69,6 +70,26 @@ impl AABB {
         }
     }
 
+    // 0 for an empty box
+    pub fn surface_area(&self) -> f64 {
+        let (x, y, z) = (self.x.size(), self.y.size(), self.z.size());
+        if x < 0.0 || y < 0.0 || z < 0.0 {
+            return 0.0;
+        }
+        2.0 * (x * y + y * z + z * x)
+    }
+
+    pub fn longest_axis(&self) -> u32 {
+        let (x, y, z) = (self.x.size(), self.y.size(), self.z.size());
+        if x >= y && x >= z {
+            0
+        } else if y >= z {
+            1
+        } else {
+            2
+        }
+    }
+
     pub fn hit(&self, ray: &Ray, mut ray_t: Interval) -> bool {
         if !Self::hit_helper(self.axis(0), ray.orig.x(), ray.dir.x(), &mut ray_t)
             || !Self::hit_helper(self.axis(1), ray.orig.y(), ray.dir.y(), &mut ray_t)
@@ -128,8 +149,8 @@ impl ops::Add<&Vec3> for &AABB {
     fn add(self, rhs: &Vec3) -> Self::Output {
         AABB {
             x: &self.x + rhs.x(),
-            y: &self.x + rhs.y(),
-            z: &self.x + rhs.z(),
+            y: &self.y + rhs.y(),
+            z: &self.z + rhs.z(),
         }
     }
 }
@@ -138,8 +159,8 @@ impl ops::Add<&Vec3> for AABB {
     fn add(self, rhs: &Vec3) -> Self::Output {
         AABB {
             x: &self.x + rhs.x(),
-            y: &self.x + rhs.y(),
-            z: &self.x + rhs.z(),
+            y: &self.y + rhs.y(),
+            z: &self.z + rhs.z(),
         }
     }
 }
@@ -0,0 +1,157 @@
+// Adaptive sampling: spend the sample budget where the image is still noisy.
+//
+// Every pixel first gets `min_samples` samples. After that a pixel only gets more while the
+// estimated error of its value is above `threshold` somewhere in its 3x3 neighbourhood, so a lucky
+// estimate does not stop a pixel in a noisy area, and never more than `max_samples`.
+use crate::color::luminance;
+use crate::{Color, Framebuffer};
+
+// luminance below which pixels are judged by their absolute instead of relative error, so noise in
+// near-black areas does not soak up the budget
+const DARK_LUMINANCE: f64 = 0.05;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdaptiveSampling {
+    pub min_samples: u32, // samples every pixel gets
+    pub max_samples: u32, // samples no pixel gets more of
+    pub threshold: f64,   // relative error below which a pixel is considered converged
+}
+
+impl AdaptiveSampling {
+    // for every pixel, whether it should get more samples
+    pub fn needs_samples(&self, framebuffer: &Framebuffer) -> Vec<bool> {
+        let (width, height) = (framebuffer.width(), framebuffer.height());
+        let errors = error_map(framebuffer);
+        let mut needs_samples = Vec::with_capacity(errors.len());
+        for j in 0..height {
+            for i in 0..width {
+                let count = framebuffer.sample_count(i, j);
+                needs_samples.push(if count < self.min_samples {
+                    true
+                } else if count >= self.max_samples {
+                    false
+                } else {
+                    let neighbours = (j.saturating_sub(1)..(j + 2).min(height)).flat_map(|y| {
+                        (i.saturating_sub(1)..(i + 2).min(width))
+                            .map(move |x| y as usize * width as usize + x as usize)
+                    });
+                    neighbours
+                        .map(|index| errors[index])
+                        .any(|error| error > self.threshold)
+                });
+            }
+        }
+        needs_samples
+    }
+}
+
+// relative standard error of every pixel, row by row from the top left; infinite for pixels with
+// fewer than two samples
+pub fn error_map(framebuffer: &Framebuffer) -> Vec<f64> {
+    let mut errors = Vec::new();
+    for j in 0..framebuffer.height() {
+        for i in 0..framebuffer.width() {
+            let mean = luminance(&framebuffer.pixel(i, j));
+            errors.push(framebuffer.variance(i, j).sqrt() / mean.max(DARK_LUMINANCE));
+        }
+    }
+    errors
+}
+
+// false-color image of the number of samples of every pixel
+pub fn sample_count_heatmap(framebuffer: &Framebuffer) -> Framebuffer {
+    let mut counts = Vec::new();
+    for j in 0..framebuffer.height() {
+        for i in 0..framebuffer.width() {
+            counts.push(framebuffer.sample_count(i, j) as f64);
+        }
+    }
+    heatmap(&counts, framebuffer.width(), framebuffer.height())
+}
+
+// false-color image of the relative error of every pixel
+pub fn error_heatmap(framebuffer: &Framebuffer) -> Framebuffer {
+    heatmap(
+        &error_map(framebuffer),
+        framebuffer.width(),
+        framebuffer.height(),
+    )
+}
+
+// map values from 0 to the largest finite value onto a blue, cyan, green, yellow, red ramp,
+// infinite values are red
+fn heatmap(values: &[f64], width: u32, height: u32) -> Framebuffer {
+    const RAMP: [(f64, f64, f64); 5] = [
+        (0.0, 0.0, 0.3),
+        (0.0, 0.6, 1.0),
+        (0.1, 0.9, 0.1),
+        (1.0, 0.9, 0.0),
+        (1.0, 0.0, 0.0),
+    ];
+    let max = values
+        .iter()
+        .copied()
+        .filter(|v| v.is_finite())
+        .fold(0.0, f64::max);
+    let mut image = Framebuffer::new(width, height);
+    for (index, &value) in values.iter().enumerate() {
+        let t = if max > 0.0 {
+            (value / max).min(1.0)
+        } else {
+            0.0
+        };
+        let position = t * (RAMP.len() - 1) as f64;
+        let k = (position as usize).min(RAMP.len() - 2);
+        let f = position - k as f64;
+        let (a, b) = (RAMP[k], RAMP[k + 1]);
+        let color = Color::new(
+            a.0 + (b.0 - a.0) * f,
+            a.1 + (b.1 - a.1) * f,
+            a.2 + (b.2 - a.2) * f,
+        );
+        image.set_pixel(index as u32 % width, index as u32 / width, color);
+    }
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_needs_samples() {
+        let adaptive = AdaptiveSampling {
+            min_samples: 4,
+            max_samples: 16,
+            threshold: 0.1,
+        };
+        let mut framebuffer = Framebuffer::new(4, 1);
+        for i in 0..4 {
+            // four samples of luminance 1, without any variance
+            framebuffer.add_samples(i, 0, &Color::new(4.0, 4.0, 4.0), 4);
+            framebuffer.add_luminance_sq(i, 0, 4.0);
+        }
+        // pixel 3 gets four more samples, one of luminance 4 and three black ones, which also
+        // keeps its neighbour going
+        framebuffer.add_samples(3, 0, &Color::new(4.0, 4.0, 4.0), 4);
+        framebuffer.add_luminance_sq(3, 0, 16.0);
+        assert_eq!(
+            adaptive.needs_samples(&framebuffer),
+            vec![false, false, true, true]
+        );
+
+        let mut fresh = Framebuffer::new(1, 1);
+        assert_eq!(adaptive.needs_samples(&fresh), vec![true]);
+        fresh.add_samples(0, 0, &Color::new(16.0, 0.0, 0.0), 16);
+        assert_eq!(adaptive.needs_samples(&fresh), vec![false]);
+    }
+
+    #[test]
+    fn test_heatmap() {
+        let image = heatmap(&[0.0, 2.0, 4.0, f64::INFINITY], 2, 2);
+        assert_eq!(image.pixel(0, 0), Color::new(0.0, 0.0, 0.3));
+        assert_eq!(image.pixel(1, 0), Color::new(0.1, 0.9, 0.1));
+        assert_eq!(image.pixel(0, 1), Color::new(1.0, 0.0, 0.0));
+        assert_eq!(image.pixel(1, 1), Color::new(1.0, 0.0, 0.0));
+    }
+}
@@ -0,0 +1,156 @@
+// Animated cameras and frame sequences.
+//
+// Camera keyframes set any of `look_from`, `look_at`, `vfov`, `focus_dist` and `defocus_angle` at
+// a scene time. Every setting is interpolated linearly between the keyframes that set it and held
+// before the first and after the last, so settings can be keyed independently. Frame `n` of an
+// animation opens its shutter at `n / fps` and keeps it open for `shutter_angle / 360` of a frame,
+// like a film camera's rotary shutter.
+use crate::output;
+use crate::{Point3, Vec3};
+
+pub const DEFAULT_FPS: f64 = 24.0;
+pub const DEFAULT_SHUTTER_ANGLE: f64 = 180.0;
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CameraKeyframe {
+    pub time: f64,
+    pub look_from: Option<Point3>,
+    pub look_at: Option<Point3>,
+    pub vfov: Option<f64>,
+    pub focus_dist: Option<f64>,
+    pub defocus_angle: Option<f64>,
+}
+
+// the value of the setting `get` at `time`, none if no keyframe sets it. `keyframes` are sorted
+// by time
+pub fn interpolate<T, F>(keyframes: &[CameraKeyframe], time: f64, get: F) -> Option<T>
+where
+    T: Clone + Lerp,
+    F: Fn(&CameraKeyframe) -> Option<&T>,
+{
+    let mut before: Option<(f64, &T)> = None;
+    for keyframe in keyframes {
+        let Some(value) = get(keyframe) else {
+            continue;
+        };
+        if keyframe.time > time {
+            return Some(match before {
+                Some((t0, v0)) => v0.lerp(value, (time - t0) / (keyframe.time - t0)),
+                None => value.clone(),
+            });
+        }
+        before = Some((keyframe.time, value));
+    }
+    before.map(|(_, value)| value.clone())
+}
+
+pub trait Lerp {
+    fn lerp(&self, other: &Self, t: f64) -> Self;
+}
+
+impl Lerp for f64 {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        (1.0 - t) * self + t * other
+    }
+}
+
+impl Lerp for Vec3 {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        (1.0 - t) * self + t * other
+    }
+}
+
+// how an animation is cut into frames
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Animation {
+    pub fps: f64,
+    pub shutter_angle: f64, // in degrees, 360 keeps the shutter open all frame
+    pub frames: Option<(u32, u32)>, // first and last frame to render
+}
+
+impl Default for Animation {
+    fn default() -> Self {
+        Self {
+            fps: DEFAULT_FPS,
+            shutter_angle: DEFAULT_SHUTTER_ANGLE,
+            frames: None,
+        }
+    }
+}
+
+impl Animation {
+    // scene times the shutter opens and closes at for `frame`
+    pub fn shutter(&self, frame: u32) -> (f64, f64) {
+        let open = frame as f64 / self.fps;
+        (open, open + self.shutter_angle / 360.0 / self.fps)
+    }
+}
+
+// the output path of `frame`: a run of `#` in `path` becomes the zero-padded frame number, or
+// without one the number is added before the extension
+pub fn frame_path(path: &str, frame: u32) -> String {
+    if let Some(start) = path.find('#') {
+        let width = path[start..].chars().take_while(|&c| c == '#').count();
+        return format!(
+            "{}{:0width$}{}",
+            &path[..start],
+            frame,
+            &path[start + width..],
+            width = width
+        );
+    }
+    output::with_suffix(path, &format!("_{:04}", frame))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interpolate() {
+        let keyframes = [
+            CameraKeyframe {
+                time: 0.0,
+                vfov: Some(40.0),
+                look_from: Some(Point3::new(0.0, 0.0, 0.0)),
+                ..Default::default()
+            },
+            CameraKeyframe {
+                time: 1.0,
+                look_from: Some(Point3::new(10.0, 0.0, 0.0)),
+                ..Default::default()
+            },
+            CameraKeyframe {
+                time: 2.0,
+                vfov: Some(80.0),
+                ..Default::default()
+            },
+        ];
+        let vfov = |time| interpolate(&keyframes, time, |k| k.vfov.as_ref());
+        let look_from = |time| interpolate(&keyframes, time, |k| k.look_from.as_ref());
+        assert_eq!(vfov(-1.0), Some(40.0));
+        assert_eq!(vfov(0.5), Some(50.0));
+        assert_eq!(vfov(3.0), Some(80.0));
+        assert_eq!(look_from(0.25), Some(Point3::new(2.5, 0.0, 0.0)));
+        assert_eq!(look_from(1.5), Some(Point3::new(10.0, 0.0, 0.0)));
+        assert_eq!(
+            interpolate(&keyframes, 0.5, |k| k.focus_dist.as_ref()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_frames() {
+        let animation = Animation {
+            fps: 25.0,
+            shutter_angle: 90.0,
+            frames: None,
+        };
+        let (open, close) = animation.shutter(50);
+        assert!((open - 2.0).abs() < 1e-12 && (close - 2.01).abs() < 1e-12);
+
+        assert_eq!(frame_path("images/spin.png", 7), "images/spin_0007.png");
+        assert_eq!(frame_path("out/f###.exr", 12), "out/f012.exr");
+        assert_eq!(frame_path("out.d/frame", 3), "out.d/frame_0003");
+    }
+}
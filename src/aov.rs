@@ -0,0 +1,248 @@
+// Arbitrary output variables: what the camera rays hit first, for compositing.
+//
+// The passes are recorded while rendering, from the first hits of the camera rays of the image's
+// own samples, and like the image averaged over however many samples a pixel got. The ID passes
+// are the exception: they keep the ID of a pixel's first sample that hits something so edges
+// never blend two IDs. Object IDs number the `[[objects]]` entries of a scene file from 1, material IDs the
+// materials in the order they are defined, and 0 means none.
+use crate::aabb::AABB;
+use crate::hittables::HitRecord;
+use crate::traits::{Material, ScatterInfo};
+use crate::{Color, Hittable, Interval, Point3, Ray, Sampler, Vec3};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aov {
+    Depth,      // distance to the first hit, infinite where nothing was hit
+    Normal,     // world space shading normal, facing the camera
+    Albedo,     // color of the material's texture
+    Uv,         // texture coordinates in red and green
+    ObjectId,   // in all three channels
+    MaterialId, // in all three channels
+    Emission,   // light emitted by the surfaces seen directly
+}
+
+pub const AOV_NAMES: [&str; 7] = [
+    "depth",
+    "normal",
+    "albedo",
+    "uv",
+    "object_id",
+    "material_id",
+    "emission",
+];
+
+impl Aov {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::Uv => "uv",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+            Aov::Emission => "emission",
+        }
+    }
+
+    // the channels of the pass in a multi-channel EXR file, taken from red, green and blue
+    pub fn channels(&self) -> &'static [&'static str] {
+        match self {
+            Aov::Depth => &["Z"],
+            Aov::Normal => &["X", "Y", "Z"],
+            Aov::Uv => &["U", "V"],
+            Aov::ObjectId | Aov::MaterialId => &["id"],
+            Aov::Albedo | Aov::Emission => &["R", "G", "B"],
+        }
+    }
+
+    // whether the pass keeps the first sample that hits instead of averaging
+    pub fn is_id(&self) -> bool {
+        matches!(self, Aov::ObjectId | Aov::MaterialId)
+    }
+
+    // the value of the pass for a camera ray that hit `rec`
+    pub fn value(&self, ray: &Ray, rec: &HitRecord) -> Color {
+        match self {
+            Aov::Depth => {
+                let depth = rec.t * ray.dir.length();
+                Color::new(depth, depth, depth)
+            }
+            Aov::Normal => rec.normal.clone(),
+            Aov::Albedo => rec.material.albedo(rec.u, rec.v, &rec.point),
+            Aov::Uv => Color::new(rec.u, rec.v, 0.0),
+            Aov::ObjectId => {
+                let id = rec.object_id as f64;
+                Color::new(id, id, id)
+            }
+            Aov::MaterialId => {
+                let id = rec.material.id() as f64;
+                Color::new(id, id, id)
+            }
+            Aov::Emission => rec.material.emitted(rec.u, rec.v, &rec.point),
+        }
+    }
+}
+
+impl fmt::Display for Aov {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Aov {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "depth" => Ok(Aov::Depth),
+            "normal" => Ok(Aov::Normal),
+            "albedo" => Ok(Aov::Albedo),
+            "uv" => Ok(Aov::Uv),
+            "object_id" => Ok(Aov::ObjectId),
+            "material_id" => Ok(Aov::MaterialId),
+            "emission" => Ok(Aov::Emission),
+            _ => Err(format!(
+                "unknown AOV `{}`, expected one of: {}",
+                s,
+                AOV_NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+// an object that stamps its ID on every hit, for the object ID pass
+pub struct IdObject {
+    object: Arc<dyn Hittable>,
+    id: u32,
+}
+
+impl IdObject {
+    pub fn new(object: Arc<dyn Hittable>, id: u32) -> Self {
+        Self { object, id }
+    }
+}
+
+impl Hittable for IdObject {
+    fn hit(&self, ray: &Ray, ray_t: &mut Interval, sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        let rec = self.object.hit(ray, ray_t, sampler)?;
+        Some(HitRecord {
+            object_id: self.id,
+            ..rec
+        })
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.object.bounding_box()
+    }
+
+    fn is_emitter(&self) -> bool {
+        self.object.is_emitter()
+    }
+
+    fn emitters(&self) -> Vec<Arc<dyn Hittable>> {
+        self.object.emitters()
+    }
+
+    fn pdf_value(&self, ray: &Ray, sampler: &mut dyn Sampler) -> f64 {
+        self.object.pdf_value(ray, sampler)
+    }
+
+    fn random_direction(&self, origin: &Point3, time: f64, sampler: &mut dyn Sampler) -> Vec3 {
+        self.object.random_direction(origin, time, sampler)
+    }
+}
+
+// a material with an ID for the material ID pass, otherwise the same as the material it wraps
+pub struct IdMaterial {
+    material: Arc<dyn Material>,
+    id: u32,
+}
+
+impl IdMaterial {
+    pub fn new(material: Arc<dyn Material>, id: u32) -> Self {
+        Self { material, id }
+    }
+}
+
+impl Material for IdMaterial {
+    fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.material.emitted(u, v, p)
+    }
+
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterInfo> {
+        self.material.scatter(ray_in, rec, sampler)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn is_specular(&self) -> bool {
+        self.material.is_specular()
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        self.material.eval(ray_in, rec, direction)
+    }
+
+    fn pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        self.material.pdf(ray_in, rec, direction)
+    }
+
+    fn albedo(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.material.albedo(u, v, p)
+    }
+
+    fn id(&self) -> u32 {
+        self.id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{DiffuseLight, Lambertian};
+    use crate::sphere::Sphere;
+    use crate::utils::INFINITY;
+    use crate::RandomSampler;
+
+    #[test]
+    fn test_passes() {
+        for name in AOV_NAMES {
+            assert_eq!(name.parse::<Aov>().unwrap().name(), name);
+        }
+        assert!("position".parse::<Aov>().is_err());
+
+        let red = Arc::new(Lambertian::new_from_color(Color::new(0.9, 0.1, 0.1)));
+        let material = Arc::new(IdMaterial::new(red, 3));
+        let sphere = Arc::new(Sphere::new(Point3::new(0.0, 0.0, -5.0), 1.0, material));
+        let object = IdObject::new(sphere, 7);
+        let ray = Ray::new(Point3::default(), Vec3::new(0.0, 0.0, -2.0), 0.0);
+        let mut sampler = RandomSampler::new(0);
+        let rec = object
+            .hit(&ray, &mut Interval::new(0.001, INFINITY), &mut sampler)
+            .unwrap();
+        assert_eq!(Aov::Depth.value(&ray, &rec), Color::new(4.0, 4.0, 4.0));
+        assert_eq!(Aov::Normal.value(&ray, &rec), Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(Aov::Albedo.value(&ray, &rec), Color::new(0.9, 0.1, 0.1));
+        assert_eq!(Aov::ObjectId.value(&ray, &rec), Color::new(7.0, 7.0, 7.0));
+        assert_eq!(Aov::MaterialId.value(&ray, &rec), Color::new(3.0, 3.0, 3.0));
+        assert_eq!(Aov::Emission.value(&ray, &rec), Color::default());
+
+        let light = DiffuseLight::new_from_color(Color::new(4.0, 4.0, 4.0));
+        let light = IdMaterial::new(Arc::new(light), 1);
+        assert!(light.is_emissive());
+        assert_eq!(
+            light.emitted(0.0, 0.0, &Point3::default()),
+            Color::new(4.0, 4.0, 4.0)
+        );
+    }
+}
@@ -0,0 +1,211 @@
+// The shape of the lens opening, which is the shape out of focus highlights take.
+//
+// A circular aperture samples the unit disk like the thin lens camera always has. A polygonal one
+// has straight blades, with corners on the unit circle. An aperture mask is a grayscale image
+// spread over the square around the unit disk, where white lets light through and black blocks
+// it; lens positions are drawn in proportion to its brightness.
+use crate::checkpoint::Fingerprint;
+use crate::utils::{degrees_to_radians, sample_cdf, PI};
+use crate::Result;
+use crate::{Sampler, Vec3};
+use image::io::Reader as ImageReader;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Default)]
+pub enum Aperture {
+    #[default]
+    Circle,
+    Polygon {
+        blades: u32,   // at least 3
+        rotation: f64, // in degrees, counterclockwise from a corner pointing up
+    },
+    Mask(Arc<ApertureMask>),
+}
+
+impl Aperture {
+    // a random point of the aperture, in the unit disk or the square around it
+    pub fn sample(&self, sampler: &mut dyn Sampler) -> (f64, f64) {
+        match self {
+            Aperture::Circle => {
+                let p = Vec3::random_in_unit_disk(sampler);
+                (p.x(), p.y())
+            }
+            Aperture::Polygon { blades, rotation } => {
+                // pick the triangle between the center and one blade, then a point inside it
+                let (u, v) = sampler.get_2d();
+                let n = *blades as f64;
+                let k = (u * n).floor().min(n - 1.0);
+                let u = u * n - k;
+                let corner = |k: f64| {
+                    let angle = PI / 2.0 + degrees_to_radians(*rotation) + 2.0 * PI * k / n;
+                    (angle.cos(), angle.sin())
+                };
+                let (a, b) = (corner(k), corner(k + 1.0));
+                let s = u.sqrt();
+                (
+                    s * ((1.0 - v) * a.0 + v * b.0),
+                    s * ((1.0 - v) * a.1 + v * b.1),
+                )
+            }
+            Aperture::Mask(mask) => {
+                let (u, v) = sampler.get_2d();
+                mask.sample(u, v)
+            }
+        }
+    }
+}
+
+impl PartialEq for Aperture {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Aperture::Circle, Aperture::Circle) => true,
+            (
+                Aperture::Polygon { blades, rotation },
+                Aperture::Polygon {
+                    blades: other_blades,
+                    rotation: other_rotation,
+                },
+            ) => blades == other_blades && rotation == other_rotation,
+            (Aperture::Mask(mask), Aperture::Mask(other)) => Arc::ptr_eq(mask, other),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Aperture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Aperture::Circle => f.write_str("circle"),
+            Aperture::Polygon { blades, rotation } => {
+                write!(f, "{} blades, rotated {} degrees", blades, rotation)
+            }
+            Aperture::Mask(mask) => write!(f, "mask {}", mask.name),
+        }
+    }
+}
+
+// the brightness of an image as a distribution over the aperture
+#[derive(Debug)]
+pub struct ApertureMask {
+    name: String,
+    width: usize,
+    height: usize,
+    row_cdf: Vec<f64>,    // height + 1 entries from 0 to 1
+    column_cdf: Vec<f64>, // width + 1 entries from 0 to 1 for every row
+}
+
+impl ApertureMask {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let image = ImageReader::open(path)?.decode()?.to_luma8();
+        let weights = image.pixels().map(|p| p.0[0] as f64).collect();
+        let name = path.display().to_string();
+        Self::new(
+            name,
+            image.width() as usize,
+            image.height() as usize,
+            weights,
+        )
+    }
+
+    // hash of the distribution the mask was turned into, the name alone does not tell whether the
+    // file changed
+    pub fn fingerprint(&self) -> u64 {
+        let mut fingerprint = Fingerprint::default();
+        fingerprint.add_u64(self.width as u64);
+        for &c in self.row_cdf.iter().chain(&self.column_cdf) {
+            fingerprint.add_f64(c);
+        }
+        fingerprint.finish()
+    }
+
+    // `weights` are the row by row transmittances of a width by height mask
+    pub fn new(name: String, width: usize, height: usize, weights: Vec<f64>) -> Result<Self> {
+        if width == 0 || height == 0 || weights.len() != width * height {
+            return Err("an aperture mask needs one weight per pixel".into());
+        }
+        let mut row_cdf = vec![0.0; height + 1];
+        let mut column_cdf = vec![0.0; (width + 1) * height];
+        for (j, row) in weights.chunks(width).enumerate() {
+            let cdf = &mut column_cdf[j * (width + 1)..(j + 1) * (width + 1)];
+            for (i, weight) in row.iter().enumerate() {
+                cdf[i + 1] = cdf[i] + weight.max(0.0);
+            }
+            let total = cdf[width];
+            if total > 0.0 {
+                cdf.iter_mut().for_each(|c| *c /= total);
+            }
+            row_cdf[j + 1] = row_cdf[j] + total;
+        }
+        let total = row_cdf[height];
+        if total <= 0.0 {
+            return Err("an aperture mask must not be black everywhere".into());
+        }
+        row_cdf.iter_mut().for_each(|c| *c /= total);
+        Ok(Self {
+            name,
+            width,
+            height,
+            row_cdf,
+            column_cdf,
+        })
+    }
+
+    // invert the cumulative distributions of the rows and of the pixels in the row, the
+    // remainders of `u` and `v` place the point inside the pixel
+    fn sample(&self, u: f64, v: f64) -> (f64, f64) {
+        let (row, y) = sample_cdf(&self.row_cdf, u);
+        let row_cdf = &self.column_cdf[row * (self.width + 1)..(row + 1) * (self.width + 1)];
+        let (column, x) = sample_cdf(row_cdf, v);
+        // the longer side of the image spans the aperture's diameter
+        let size = self.width.max(self.height) as f64;
+        (
+            (2.0 * (column as f64 + x) - self.width as f64) / size,
+            (self.height as f64 - 2.0 * (row as f64 + y)) / size,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RandomSampler;
+
+    #[test]
+    fn test_polygon() {
+        let hexagon = Aperture::Polygon {
+            blades: 6,
+            rotation: 0.0,
+        };
+        let mut sampler = RandomSampler::new(0);
+        let (mut near_top, mut near_side) = (0, 0);
+        for _ in 0..10000 {
+            let (x, y) = hexagon.sample(&mut sampler);
+            // inside the hexagon with corners at the top and bottom
+            let apothem = (PI / 6.0).cos();
+            assert!(x.abs() <= apothem + 1e-9, "{} {}", x, y);
+            assert!(x.abs() * 0.5 + y.abs() * apothem <= apothem + 1e-9);
+            near_top += (y > 0.95) as u32;
+            near_side += (x > apothem - 0.05) as u32;
+        }
+        // the corner is narrower than the flat side
+        assert!(near_top > 0 && near_top * 5 < near_side);
+    }
+
+    #[test]
+    fn test_mask() {
+        // only the top right quarter lets light through
+        let weights = vec![0.0, 1.0, 0.0, 0.0];
+        let mask = ApertureMask::new("quarter".to_string(), 2, 2, weights).unwrap();
+        let aperture = Aperture::Mask(Arc::new(mask));
+        let mut sampler = RandomSampler::new(0);
+        for _ in 0..1000 {
+            let (x, y) = aperture.sample(&mut sampler);
+            assert!((0.0..=1.0).contains(&x) && (0.0..=1.0).contains(&y));
+        }
+        assert!(ApertureMask::new("black".to_string(), 1, 1, vec![0.0]).is_err());
+        assert!(ApertureMask::new("short".to_string(), 2, 2, vec![1.0]).is_err());
+    }
+}
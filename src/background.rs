@@ -0,0 +1,353 @@
+// What rays that leave the scene see: a constant color, or an environment map of the light
+// arriving from every direction.
+//
+// An environment map is a lat-long (equirectangular) image in world space. Its columns go once
+// around the vertical axis with the middle of the image towards -z and +x a quarter of the width
+// to the right of it, its rows from straight up at the top to straight down at the bottom. A
+// rotation turns the map counterclockwise about +y. The environment is also a light: directions
+// are sampled in proportion to the luminance of the pixels times the solid angle they cover,
+// which shrinks towards the poles.
+use crate::aabb::AABB;
+use crate::checkpoint::Fingerprint;
+use crate::color::luminance;
+use crate::framebuffer::Framebuffer;
+use crate::hittables::HitRecord;
+use crate::output;
+use crate::utils::{degrees_to_radians, sample_cdf, PI};
+use crate::{Color, Hittable, Interval, Point3, Ray, Result, Sampler, Vec3};
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Background {
+    Color(Color),
+    Environment(Environment),
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Color(Color::default())
+    }
+}
+
+impl From<Color> for Background {
+    fn from(color: Color) -> Self {
+        Background::Color(color)
+    }
+}
+
+impl Background {
+    // the radiance arriving from `direction`
+    pub fn value(&self, direction: &Vec3) -> Color {
+        match self {
+            Background::Color(color) => color.clone(),
+            Background::Environment(environment) => environment.radiance(direction),
+        }
+    }
+}
+
+impl fmt::Display for Background {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Background::Color(color) => write!(f, "{}", color),
+            Background::Environment(environment) => write!(f, "{}", environment),
+        }
+    }
+}
+
+// an environment map placed around the scene
+#[derive(Debug, Clone)]
+pub struct Environment {
+    pub map: Arc<EnvironmentMap>,
+    pub rotation: f64,  // in degrees, counterclockwise about +y
+    pub intensity: f64, // the map's radiance is multiplied with
+}
+
+impl Environment {
+    pub fn new(map: Arc<EnvironmentMap>) -> Self {
+        Self {
+            map,
+            rotation: 0.0,
+            intensity: 1.0,
+        }
+    }
+
+    pub fn radiance(&self, direction: &Vec3) -> Color {
+        let (u, v) = self.map_coordinates(direction);
+        self.map.pixel(u, v) * self.intensity
+    }
+
+    // the density over solid angle with which `random_direction` picks `direction`
+    pub fn pdf(&self, direction: &Vec3) -> f64 {
+        let (u, v) = self.map_coordinates(direction);
+        let sin_theta = (PI * v).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        // the pixel's probability spread over its part of the sphere
+        let (width, height) = (self.map.width as f64, self.map.height as f64);
+        self.map.probability(u, v) * width * height / (2.0 * PI * PI * sin_theta)
+    }
+
+    // the map coordinates from 0 to 1 of the light arriving from `direction`
+    fn map_coordinates(&self, direction: &Vec3) -> (f64, f64) {
+        let d = direction.make_unit_vector();
+        let (sin, cos) = degrees_to_radians(self.rotation).sin_cos();
+        // undo the rotation of the map
+        let (x, z) = (cos * d.x() - sin * d.z(), cos * d.z() + sin * d.x());
+        let u = 0.5 + x.atan2(-z) / (2.0 * PI);
+        let v = d.y().clamp(-1.0, 1.0).acos() / PI;
+        (u, v)
+    }
+
+    fn direction_at(&self, u: f64, v: f64) -> Vec3 {
+        let (phi, theta) = ((u - 0.5) * 2.0 * PI, v * PI);
+        let (x, y, z) = (
+            theta.sin() * phi.sin(),
+            theta.cos(),
+            -theta.sin() * phi.cos(),
+        );
+        let (sin, cos) = degrees_to_radians(self.rotation).sin_cos();
+        Vec3::new(cos * x + sin * z, y, cos * z - sin * x)
+    }
+}
+
+impl PartialEq for Environment {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.map, &other.map)
+            && self.rotation == other.rotation
+            && self.intensity == other.intensity
+    }
+}
+
+impl fmt::Display for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "environment map {}, rotated {} degrees",
+            self.map.name, self.rotation
+        )?;
+        if self.intensity != 1.0 {
+            write!(f, ", intensity {}", self.intensity)?;
+        }
+        Ok(())
+    }
+}
+
+// as a light the environment is a sphere infinitely far away that no ray hits, only its
+// directions and their density are used
+impl Hittable for Environment {
+    fn hit(&self, _ray: &Ray, _ray_t: &mut Interval, _: &mut dyn Sampler) -> Option<HitRecord> {
+        None
+    }
+
+    fn bounding_box(&self) -> AABB {
+        AABB::default()
+    }
+
+    fn pdf_value(&self, ray: &Ray, _sampler: &mut dyn Sampler) -> f64 {
+        self.pdf(&ray.dir)
+    }
+
+    fn random_direction(&self, _origin: &Point3, _time: f64, sampler: &mut dyn Sampler) -> Vec3 {
+        let (u, v) = sampler.get_2d();
+        let (u, v) = self.map.sample(u, v);
+        self.direction_at(u, v)
+    }
+}
+
+// the pixels of a lat-long image and how bright they are as a distribution over the sphere
+#[derive(Debug)]
+pub struct EnvironmentMap {
+    name: String,
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    row_cdf: Vec<f64>,    // height + 1 entries from 0 to 1
+    column_cdf: Vec<f64>, // width + 1 entries from 0 to 1 for every row
+}
+
+impl EnvironmentMap {
+    // read a linear image: .hdr, .exr or .pfm
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let image = output::read_image(path)?;
+        Ok(Self::new(path.display().to_string(), &image))
+    }
+
+    pub fn new(name: String, image: &Framebuffer) -> Self {
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        let pixels = image.pixels();
+        // rows near the poles cover less of the sphere, a black map is sampled by solid angle
+        let row_weight = |j: usize| (PI * (j as f64 + 0.5) / height as f64).sin();
+        // a luminance that is not finite would turn the whole distribution into NaN, such pixels
+        // and negative ones are never sampled
+        let weight = |c: &Color| match luminance(c) {
+            l if l.is_finite() && l > 0.0 => l,
+            _ => 0.0,
+        };
+        let mut weights: Vec<f64> = pixels
+            .iter()
+            .enumerate()
+            .map(|(index, c)| weight(c) * row_weight(index / width))
+            .collect();
+        if !weights.iter().any(|&w| w > 0.0) {
+            weights = (0..width * height).map(|i| row_weight(i / width)).collect();
+        }
+
+        let mut row_cdf = vec![0.0; height + 1];
+        let mut column_cdf = vec![0.0; (width + 1) * height];
+        for (j, row) in weights.chunks(width).enumerate() {
+            let cdf = &mut column_cdf[j * (width + 1)..(j + 1) * (width + 1)];
+            for (i, weight) in row.iter().enumerate() {
+                cdf[i + 1] = cdf[i] + weight;
+            }
+            let total = cdf[width];
+            if total > 0.0 {
+                cdf.iter_mut().for_each(|c| *c /= total);
+            }
+            row_cdf[j + 1] = row_cdf[j] + total;
+        }
+        let total = row_cdf[height];
+        row_cdf.iter_mut().for_each(|c| *c /= total);
+        Self {
+            name,
+            width,
+            height,
+            pixels,
+            row_cdf,
+            column_cdf,
+        }
+    }
+
+    // hash of the pixels, the name alone does not tell whether the file changed
+    pub fn fingerprint(&self) -> u64 {
+        let mut fingerprint = Fingerprint::default();
+        fingerprint.add_u64(self.width as u64);
+        for c in &self.pixels {
+            fingerprint.add_f64(c.x()).add_f64(c.y()).add_f64(c.z());
+        }
+        fingerprint.finish()
+    }
+
+    // the pixel at map coordinates (u, v) from 0 to 1, and its column and row
+    fn index(&self, u: f64, v: f64) -> (usize, usize) {
+        let i = ((u * self.width as f64) as usize).min(self.width - 1);
+        let j = ((v * self.height as f64) as usize).min(self.height - 1);
+        (i, j)
+    }
+
+    fn pixel(&self, u: f64, v: f64) -> Color {
+        let (i, j) = self.index(u, v);
+        self.pixels[j * self.width + i].clone()
+    }
+
+    // the probability that `sample` picks the pixel at (u, v)
+    fn probability(&self, u: f64, v: f64) -> f64 {
+        let (i, j) = self.index(u, v);
+        let column_cdf = &self.column_cdf[j * (self.width + 1)..];
+        (self.row_cdf[j + 1] - self.row_cdf[j]) * (column_cdf[i + 1] - column_cdf[i])
+    }
+
+    // map coordinates in proportion to the weights of the pixels, uniform inside the pixel
+    fn sample(&self, u: f64, v: f64) -> (f64, f64) {
+        let (row, y) = sample_cdf(&self.row_cdf, u);
+        let row_cdf = &self.column_cdf[row * (self.width + 1)..(row + 1) * (self.width + 1)];
+        let (column, x) = sample_cdf(row_cdf, v);
+        (
+            (column as f64 + x) / self.width as f64,
+            (row as f64 + y) / self.height as f64,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RandomSampler;
+
+    // a dim map with a bright pixel at the horizon in the middle, towards -z
+    fn sun_map() -> Environment {
+        let mut image = Framebuffer::new(16, 8);
+        image.map_pixels(|_| Color::new(0.1, 0.1, 0.1));
+        image.set_pixel(8, 4, Color::new(1000.0, 1000.0, 1000.0));
+        Environment::new(Arc::new(EnvironmentMap::new("sun".to_string(), &image)))
+    }
+
+    #[test]
+    fn test_lookup() {
+        let mut environment = sun_map();
+        let towards_sun = Vec3::new(0.01, -0.01, -1.0);
+        assert_eq!(environment.radiance(&towards_sun).x(), 1000.0);
+        assert_eq!(environment.radiance(&Vec3::new(0.0, 0.0, 1.0)).x(), 0.1);
+        // a quarter turn counterclockwise seen from above moves -z to -x
+        environment.rotation = 90.0;
+        assert_eq!(
+            environment.radiance(&Vec3::new(-1.0, -0.01, -0.01)).x(),
+            1000.0
+        );
+        for (u, v) in [(0.1, 0.2), (0.6, 0.5), (0.95, 0.9)] {
+            let (su, sv) = environment.map_coordinates(&environment.direction_at(u, v));
+            assert!((su - u).abs() < 1e-9 && (sv - v).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_sampling() {
+        let environment = sun_map();
+        let mut sampler = RandomSampler::new(0);
+        let origin = Point3::default();
+        let mut towards_sun = 0;
+        let n = 10000;
+        for _ in 0..n {
+            let direction = environment.random_direction(&origin, 0.0, &mut sampler);
+            towards_sun += (environment.radiance(&direction).x() > 1.0) as u32;
+        }
+        // the sun has almost all of the luminance
+        assert!(towards_sun > n * 9 / 10, "{}", towards_sun);
+
+        // the density integrates to 1 over the sphere
+        let (columns, rows) = (64, 32);
+        let mut sum = 0.0;
+        for j in 0..rows {
+            for i in 0..columns {
+                let (u, v) = (
+                    (i as f64 + 0.5) / columns as f64,
+                    (j as f64 + 0.5) / rows as f64,
+                );
+                let solid_angle = 2.0 * PI * PI * (PI * v).sin() / (columns * rows) as f64;
+                sum += environment.pdf(&environment.direction_at(u, v)) * solid_angle;
+            }
+        }
+        assert!((sum - 1.0).abs() < 0.01, "{}", sum);
+    }
+
+    #[test]
+    fn test_non_finite_pixels() {
+        let mut image = Framebuffer::new(16, 8);
+        image.map_pixels(|_| Color::new(0.1, 0.1, 0.1));
+        image.set_pixel(
+            3,
+            2,
+            Color::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+        );
+        image.set_pixel(5, 6, Color::new(f64::NAN, 0.0, 0.0));
+        image.set_pixel(9, 4, Color::new(-1.0, -1.0, -1.0));
+        let map = EnvironmentMap::new("broken".to_string(), &image);
+        assert!(map
+            .row_cdf
+            .iter()
+            .chain(&map.column_cdf)
+            .all(|c| c.is_finite()));
+        assert_eq!(map.probability(3.5 / 16.0, 2.5 / 8.0), 0.0);
+
+        let environment = Environment::new(Arc::new(map));
+        let mut sampler = RandomSampler::new(0);
+        for _ in 0..1000 {
+            let direction = environment.random_direction(&Point3::default(), 0.0, &mut sampler);
+            assert_eq!(environment.radiance(&direction).x(), 0.1);
+            assert!(environment.pdf(&direction) > 0.0);
+        }
+    }
+}
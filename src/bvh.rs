@@ -1,15 +1,106 @@
+// Bounding volume hierarchy.
+//
+// A `BvhNode` tree is built first and then flattened into a `Bvh`: an array of compact nodes in
+// depth-first order, where the first child of a node directly follows it and leaves refer to a
+// range of one object array. `Bvh` walks the array with an explicit stack and visits the child on
+// the side the ray comes from first, so that closer hits shrink the ray early. The tree itself
+// still implements `Hittable`, to compare against.
+//
+// The default builder bins the centroids of the objects along the longest axis of their bounds
+// and splits where the surface area heuristic (SAH) expects the cheapest traversal, keeping up to
+// `MAX_LEAF_SIZE` objects in a leaf when splitting does not pay off. The median builder splits
+// every node in half along the same axis and is kept for comparison. Both are deterministic.
 use crate::aabb::AABB;
-use crate::utils::random_range;
-use crate::Hittable;
-use crate::{HitRecord, Hittables, Interval, Ray};
-use std::cmp::Ordering;
+use crate::hittables::emitters_of;
+use crate::{HitRecord, Hittables, Interval, Point3, Ray};
+use crate::{Hittable, Sampler};
+use std::fmt;
+use std::str::FromStr;
 use std::sync::Arc;
 
+const BINS: usize = 12;
+const MAX_LEAF_SIZE: usize = 4;
+// relative costs of visiting a node and of intersecting an object, for the SAH
+const TRAVERSAL_COST: f64 = 1.0;
+const INTERSECTION_COST: f64 = 1.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BvhBuilder {
+    #[default]
+    Sah,
+    Median,
+}
+
+pub const BVH_BUILDERS: [BvhBuilder; 2] = [BvhBuilder::Sah, BvhBuilder::Median];
+
+impl BvhBuilder {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BvhBuilder::Sah => "sah",
+            BvhBuilder::Median => "median",
+        }
+    }
+}
+
+impl fmt::Display for BvhBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for BvhBuilder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        BVH_BUILDERS
+            .iter()
+            .find(|builder| builder.name() == s)
+            .copied()
+            .ok_or_else(|| format!("unknown BVH builder `{}`", s))
+    }
+}
+
+// how good a tree is: the SAH cost estimates the work of tracing a random ray hitting the root,
+// in units of intersecting one object
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BvhStats {
+    pub nodes: usize,
+    pub leaves: usize,
+    pub objects: usize,
+    pub max_depth: usize,
+    pub sah_cost: f64,
+}
+
+impl fmt::Display for BvhStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} objects, {} nodes, {} leaves, depth {}, SAH cost {:.2}",
+            self.objects, self.nodes, self.leaves, self.max_depth, self.sah_cost
+        )
+    }
+}
+
 // bounding volume hierarchy
 pub struct BvhNode {
-    left: Arc<dyn Hittable>,
-    right: Arc<dyn Hittable>,
     bbox: AABB,
+    contents: Contents,
+}
+
+enum Contents {
+    Leaf(Vec<Arc<dyn Hittable>>),
+    Split {
+        axis: u32,
+        left: Box<BvhNode>,
+        right: Box<BvhNode>,
+    },
+}
+
+// an object with its bounds, while building
+struct Primitive {
+    object: Arc<dyn Hittable>,
+    bbox: AABB,
+    centroid: Point3,
 }
 
 impl BvhNode {
@@ -17,76 +108,500 @@ impl BvhNode {
         Self::new(&hittables.objects)
     }
 
-    pub fn new(src_objects: &Vec<Arc<dyn Hittable>>) -> Self {
-        //println!("creating BvhNode for {} hittables", src_objects.len());
-        let mut objects = src_objects
+    pub fn new(objects: &[Arc<dyn Hittable>]) -> Self {
+        Self::with_builder(objects, BvhBuilder::default())
+    }
+
+    pub fn with_builder(objects: &[Arc<dyn Hittable>], builder: BvhBuilder) -> Self {
+        assert!(!objects.is_empty(), "a BVH needs at least one object");
+        let mut primitives: Vec<Primitive> = objects
             .iter()
-            .map(|x| x.clone())
-            .collect::<Vec<Arc<dyn Hittable>>>();
-        match objects.len() {
-            1 => Self {
-                left: src_objects[0].clone(),
-                right: src_objects[0].clone(),
-                bbox: src_objects[0].bounding_box(),
-            },
-            2 => {
-                let left = objects[0].clone();
-                let right = objects[1].clone();
-                Self {
-                    left: left.clone(),
-                    right: right.clone(),
-                    bbox: AABB::merge(&left.bounding_box(), &right.bounding_box()),
+            .map(|object| {
+                let bbox = object.bounding_box();
+                let centroid = Point3::new(
+                    (bbox.x.min + bbox.x.max) / 2.0,
+                    (bbox.y.min + bbox.y.max) / 2.0,
+                    (bbox.z.min + bbox.z.max) / 2.0,
+                );
+                Primitive {
+                    object: object.clone(),
+                    bbox,
+                    centroid,
                 }
+            })
+            .collect();
+        Self::build(&mut primitives, builder)
+    }
+
+    fn build(primitives: &mut [Primitive], builder: BvhBuilder) -> Self {
+        let bbox = primitives
+            .iter()
+            .fold(AABB::default(), |bbox, p| AABB::merge(&bbox, &p.bbox));
+        let n = primitives.len();
+        let leaf = |primitives: &[Primitive], bbox: AABB| Self {
+            bbox,
+            contents: Contents::Leaf(primitives.iter().map(|p| p.object.clone()).collect()),
+        };
+        if n == 1 {
+            return leaf(primitives, bbox);
+        }
+
+        let centroid_bounds = primitives.iter().fold(AABB::default(), |bounds, p| {
+            AABB::merge(&bounds, &AABB::new(&p.centroid, &p.centroid))
+        });
+        let axis = centroid_bounds.longest_axis();
+        let (min, extent) = (
+            centroid_bounds.axis(axis).min,
+            centroid_bounds.axis(axis).size(),
+        );
+        let mid = if extent <= 0.0 {
+            // all centroids in one point, no axis separates them
+            if n <= MAX_LEAF_SIZE {
+                return leaf(primitives, bbox);
             }
-            _ => {
-                // randomly pick an axis to divide the space
-                let axis = random_range(0, 3);
-                objects.sort_by(|a, b| Self::box_compare(a.clone(), b.clone(), axis));
-                let mid = objects.len() / 2;
-                let left = Self::new(&objects[..mid].to_vec());
-                let right = Self::new(&objects[mid..].to_vec());
-                let bbox = AABB::merge(&left.bounding_box(), &right.bounding_box());
-                Self {
-                    left: Arc::new(left),
-                    right: Arc::new(right),
-                    bbox,
+            n / 2
+        } else {
+            match builder {
+                BvhBuilder::Median => {
+                    primitives.select_nth_unstable_by(n / 2, |a, b| {
+                        a.centroid[axis as usize].total_cmp(&b.centroid[axis as usize])
+                    });
+                    n / 2
+                }
+                BvhBuilder::Sah => {
+                    let bin_of = |p: &Primitive| {
+                        let offset = (p.centroid[axis as usize] - min) / extent;
+                        ((offset * BINS as f64) as usize).min(BINS - 1)
+                    };
+                    let mut bins: [(usize, Option<AABB>); BINS] = Default::default();
+                    for p in primitives.iter() {
+                        let bin = &mut bins[bin_of(p)];
+                        bin.0 += 1;
+                        bin.1 = Some(match &bin.1 {
+                            Some(bbox) => AABB::merge(bbox, &p.bbox),
+                            None => p.bbox.clone(),
+                        });
+                    }
+                    // SAH cost of splitting after every bin, from sweeps in both directions
+                    let sweep = |bins: &mut dyn Iterator<Item = &(usize, Option<AABB>)>| {
+                        let mut count = 0;
+                        let mut bounds: Option<AABB> = None;
+                        bins.map(|(n, bbox)| {
+                            count += n;
+                            if let Some(bbox) = bbox {
+                                bounds = Some(match &bounds {
+                                    Some(bounds) => AABB::merge(bounds, bbox),
+                                    None => bbox.clone(),
+                                });
+                            }
+                            count as f64 * bounds.as_ref().map_or(0.0, AABB::surface_area)
+                        })
+                        .collect::<Vec<f64>>()
+                    };
+                    let below = sweep(&mut bins[..BINS - 1].iter());
+                    let mut above = sweep(&mut bins[1..].iter().rev());
+                    above.reverse();
+                    let (split, cost) = (0..BINS - 1)
+                        .map(|i| {
+                            let cost = TRAVERSAL_COST
+                                + INTERSECTION_COST * (below[i] + above[i]) / bbox.surface_area();
+                            (i, cost)
+                        })
+                        .min_by(|a, b| a.1.total_cmp(&b.1))
+                        .unwrap();
+                    if n <= MAX_LEAF_SIZE && n as f64 * INTERSECTION_COST <= cost {
+                        return leaf(primitives, bbox);
+                    }
+                    partition(primitives, |p| bin_of(p) <= split)
                 }
             }
+        };
+        let (left, right) = primitives.split_at_mut(mid);
+        Self {
+            bbox,
+            contents: Contents::Split {
+                axis,
+                left: Box::new(Self::build(left, builder)),
+                right: Box::new(Self::build(right, builder)),
+            },
         }
     }
 
-    pub fn box_compare(
-        box_a: Arc<dyn Hittable>,
-        box_b: Arc<dyn Hittable>,
-        axis_index: u32,
-    ) -> Ordering {
-        let a = box_a.bounding_box().axis(axis_index).min;
-        let b = box_b.bounding_box().axis(axis_index).min;
-        a.total_cmp(&b)
+    pub fn stats(&self) -> BvhStats {
+        let mut stats = BvhStats {
+            nodes: 0,
+            leaves: 0,
+            objects: 0,
+            max_depth: 0,
+            sah_cost: 0.0,
+        };
+        self.add_stats(&mut stats, 1, self.bbox.surface_area());
+        stats
+    }
+
+    fn add_stats(&self, stats: &mut BvhStats, depth: usize, root_area: f64) {
+        stats.nodes += 1;
+        stats.max_depth = stats.max_depth.max(depth);
+        // the chance that a ray through the root passes through this node
+        let probability = if root_area > 0.0 {
+            self.bbox.surface_area() / root_area
+        } else {
+            1.0
+        };
+        match &self.contents {
+            Contents::Leaf(objects) => {
+                stats.leaves += 1;
+                stats.objects += objects.len();
+                stats.sah_cost += probability * INTERSECTION_COST * objects.len() as f64;
+            }
+            Contents::Split { left, right, .. } => {
+                stats.sah_cost += probability * TRAVERSAL_COST;
+                left.add_stats(stats, depth + 1, root_area);
+                right.add_stats(stats, depth + 1, root_area);
+            }
+        }
     }
 }
+
+// move the primitives for which `f` holds to the front, returning how many there are
+fn partition<F: Fn(&Primitive) -> bool>(primitives: &mut [Primitive], f: F) -> usize {
+    let mut first = 0;
+    for i in 0..primitives.len() {
+        if f(&primitives[i]) {
+            primitives.swap(first, i);
+            first += 1;
+        }
+    }
+    first
+}
+
 impl Hittable for BvhNode {
     fn bounding_box(&self) -> AABB {
         self.bbox.clone()
     }
 
-    fn hit(&self, ray: &Ray, ray_t: &mut Interval) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, ray_t: &mut Interval, sampler: &mut dyn Sampler) -> Option<HitRecord> {
         if !self.bbox.hit(ray, ray_t.clone()) {
             return None;
         }
-        let hit_left = self.left.hit(ray, ray_t);
-        let mut new_interval = Interval {
-            min: ray_t.min,
-            max: match hit_left {
-                Some(ref rec) => rec.t,
-                None => ray_t.max,
-            },
+        match &self.contents {
+            Contents::Leaf(objects) => {
+                let mut hit_record = None;
+                for object in objects {
+                    if let Some(rec) = object.hit(ray, ray_t, sampler) {
+                        ray_t.max = rec.t;
+                        hit_record = Some(rec);
+                    }
+                }
+                hit_record
+            }
+            Contents::Split { left, right, .. } => {
+                let hit_left = left.hit(ray, ray_t, sampler);
+                if let Some(rec) = &hit_left {
+                    ray_t.max = rec.t;
+                }
+                right.hit(ray, ray_t, sampler).or(hit_left)
+            }
+        }
+    }
+
+    fn emitters(&self) -> Vec<Arc<dyn Hittable>> {
+        match &self.contents {
+            Contents::Leaf(objects) => objects.iter().flat_map(emitters_of).collect(),
+            Contents::Split { left, right, .. } => {
+                let mut emitters = left.emitters();
+                emitters.extend(right.emitters());
+                emitters
+            }
+        }
+    }
+}
+
+// the deepest tree a `Bvh` can walk, deeper SAH trees are rebuilt with the median builder
+const STACK_SIZE: usize = 64;
+
+// a bounding volume hierarchy stored in one array
+pub struct Bvh {
+    nodes: Vec<LinearNode>,
+    objects: Vec<Arc<dyn Hittable>>,
+    bbox: AABB,
+    stats: BvhStats,
+}
+
+// a leaf if `count` > 0, holding `objects[offset..offset + count]`. otherwise the first child is
+// the next node and `offset` is the index of the second, split along `axis`
+struct LinearNode {
+    min: [f64; 3],
+    max: [f64; 3],
+    offset: u32,
+    count: u16,
+    axis: u8,
+}
+
+impl LinearNode {
+    // the slab test, with the inverse ray direction computed once per ray
+    fn hit(&self, origin: &[f64; 3], inv_dir: &[f64; 3], ray_t: &Interval) -> bool {
+        let (mut t_min, mut t_max) = (ray_t.min, ray_t.max);
+        for axis in 0..3 {
+            let mut t0 = (self.min[axis] - origin[axis]) * inv_dir[axis];
+            let mut t1 = (self.max[axis] - origin[axis]) * inv_dir[axis];
+            if inv_dir[axis] < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            if t0 > t_min {
+                t_min = t0;
+            }
+            if t1 < t_max {
+                t_max = t1;
+            }
+            if t_max <= t_min {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl Bvh {
+    pub fn new_from_hittables(hittables: &Hittables) -> Self {
+        Self::new(&hittables.objects)
+    }
+
+    pub fn new(objects: &[Arc<dyn Hittable>]) -> Self {
+        Self::with_builder(objects, BvhBuilder::default())
+    }
+
+    pub fn with_builder(objects: &[Arc<dyn Hittable>], builder: BvhBuilder) -> Self {
+        assert!(
+            objects.len() <= u32::MAX as usize,
+            "a BVH holds at most {} objects",
+            u32::MAX
+        );
+        let mut tree = BvhNode::with_builder(objects, builder);
+        let mut stats = tree.stats();
+        if stats.max_depth > STACK_SIZE {
+            // halving every node keeps the depth logarithmic
+            tree = BvhNode::with_builder(objects, BvhBuilder::Median);
+            stats = tree.stats();
+        }
+        let mut bvh = Self {
+            nodes: Vec::with_capacity(stats.nodes),
+            objects: Vec::with_capacity(stats.objects),
+            bbox: tree.bbox.clone(),
+            stats,
         };
-        let hit_right = self.right.hit(ray, &mut new_interval);
-        match (hit_left, hit_right) {
-            (Some(rec), None) => Some(rec),
-            (_, Some(rec)) => Some(rec),
-            _ => None,
+        bvh.flatten(&tree);
+        bvh
+    }
+
+    pub fn stats(&self) -> BvhStats {
+        self.stats
+    }
+
+    // append the nodes of `tree` in depth-first order, returning the index of its root
+    fn flatten(&mut self, tree: &BvhNode) -> u32 {
+        let index = self.nodes.len();
+        let bbox = &tree.bbox;
+        self.nodes.push(LinearNode {
+            min: [bbox.x.min, bbox.y.min, bbox.z.min],
+            max: [bbox.x.max, bbox.y.max, bbox.z.max],
+            offset: 0,
+            count: 0,
+            axis: 0,
+        });
+        match &tree.contents {
+            Contents::Leaf(objects) => {
+                self.nodes[index].offset = self.objects.len() as u32;
+                self.nodes[index].count = objects.len() as u16;
+                self.objects.extend(objects.iter().cloned());
+            }
+            Contents::Split { axis, left, right } => {
+                self.flatten(left);
+                self.nodes[index].offset = self.flatten(right);
+                self.nodes[index].axis = *axis as u8;
+            }
+        }
+        index as u32
+    }
+}
+
+impl Hittable for Bvh {
+    fn bounding_box(&self) -> AABB {
+        self.bbox.clone()
+    }
+
+    fn hit(&self, ray: &Ray, ray_t: &mut Interval, sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        let origin = [ray.orig.x(), ray.orig.y(), ray.orig.z()];
+        let inv_dir = [1.0 / ray.dir.x(), 1.0 / ray.dir.y(), 1.0 / ray.dir.z()];
+        let mut stack = [0u32; STACK_SIZE];
+        let mut stack_len = 0;
+        let mut current = 0;
+        let mut hit_record = None;
+        loop {
+            let node = &self.nodes[current as usize];
+            if node.hit(&origin, &inv_dir, ray_t) {
+                if node.count > 0 {
+                    let start = node.offset as usize;
+                    for object in &self.objects[start..start + node.count as usize] {
+                        if let Some(rec) = object.hit(ray, ray_t, sampler) {
+                            ray_t.max = rec.t;
+                            hit_record = Some(rec);
+                        }
+                    }
+                } else {
+                    // visit the near child first and come back for the far one
+                    let (near, far) = if inv_dir[node.axis as usize] < 0.0 {
+                        (node.offset, current + 1)
+                    } else {
+                        (current + 1, node.offset)
+                    };
+                    stack[stack_len] = far;
+                    stack_len += 1;
+                    current = near;
+                    continue;
+                }
+            }
+            if stack_len == 0 {
+                return hit_record;
+            }
+            stack_len -= 1;
+            current = stack[stack_len];
+        }
+    }
+
+    fn emitters(&self) -> Vec<Arc<dyn Hittable>> {
+        self.objects.iter().flat_map(emitters_of).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::utils::INFINITY;
+    use crate::{Color, RandomSampler, Vec3};
+
+    // a row of small spheres with a big one at the end
+    fn spheres() -> Vec<Arc<dyn Hittable>> {
+        let material = Arc::new(Lambertian::new_from_color(Color::new(0.5, 0.5, 0.5)));
+        let mut spheres: Vec<Arc<dyn Hittable>> = (0..40)
+            .map(|i| {
+                Arc::new(Sphere::new(
+                    Point3::new(i as f64, (i % 3) as f64, 0.0),
+                    0.4,
+                    material.clone(),
+                )) as Arc<dyn Hittable>
+            })
+            .collect();
+        spheres.push(Arc::new(Sphere::new(
+            Point3::new(100.0, 0.0, 0.0),
+            20.0,
+            material,
+        )));
+        spheres
+    }
+
+    #[test]
+    fn test_builders() {
+        let objects = spheres();
+        let sah = BvhNode::with_builder(&objects, BvhBuilder::Sah);
+        let median = BvhNode::with_builder(&objects, BvhBuilder::Median);
+        let (sah_stats, median_stats) = (sah.stats(), median.stats());
+        assert_eq!(sah_stats.objects, 41);
+        assert_eq!(median_stats.objects, 41);
+        assert_eq!(median_stats.leaves, 41);
+        assert_eq!(median_stats.nodes, 81);
+        assert!(sah_stats.sah_cost < median_stats.sah_cost);
+
+        // both trees find the same closest hits as a plain list
+        let mut list = Hittables::default();
+        for object in &objects {
+            list.add(object.clone());
+        }
+        let mut sampler = RandomSampler::new(0);
+        for i in 0..200 {
+            let origin = Point3::new(i as f64 * 0.3 - 10.0, 0.5, -10.0);
+            let ray = Ray::new(origin, Vec3::new(0.3, 0.05 * (i % 7) as f64, 1.0), 0.0);
+            let t = |world: &dyn Hittable, sampler: &mut RandomSampler| {
+                world
+                    .hit(&ray, &mut Interval::new(0.001, INFINITY), sampler)
+                    .map(|rec| rec.t)
+            };
+            let expected = t(&list, &mut sampler);
+            assert_eq!(t(&sah, &mut sampler), expected);
+            assert_eq!(t(&median, &mut sampler), expected);
+        }
+    }
+
+    #[test]
+    fn test_flat_bvh() {
+        let objects = spheres();
+        let mut list = Hittables::default();
+        for object in &objects {
+            list.add(object.clone());
+        }
+        let sah = Bvh::new(&objects);
+        let median = Bvh::with_builder(&objects, BvhBuilder::Median);
+        assert_eq!(sah.stats(), BvhNode::new(&objects).stats());
+        assert_eq!(sah.objects.len(), 41);
+
+        // rays in all directions, so that both child orders get visited
+        let mut sampler = RandomSampler::new(1);
+        for _ in 0..1000 {
+            let origin = Point3::new(
+                sampler.random_f64_range(-10.0, 130.0),
+                sampler.random_f64_range(-30.0, 30.0),
+                sampler.random_f64_range(-30.0, 30.0),
+            );
+            let direction = Vec3::new(
+                sampler.random_f64_range(-1.0, 1.0),
+                sampler.random_f64_range(-1.0, 1.0),
+                sampler.random_f64_range(-1.0, 1.0),
+            );
+            let ray = Ray::new(origin, direction, 0.0);
+            let t = |world: &dyn Hittable, sampler: &mut RandomSampler| {
+                world
+                    .hit(&ray, &mut Interval::new(0.001, INFINITY), sampler)
+                    .map(|rec| rec.t)
+            };
+            let expected = t(&list, &mut sampler);
+            assert_eq!(t(&sah, &mut sampler), expected);
+            assert_eq!(t(&median, &mut sampler), expected);
+        }
+    }
+
+    #[test]
+    fn test_deep_tree() {
+        // spheres growing so fast that every SAH split peels off one of them
+        let material = Arc::new(Lambertian::new_from_color(Color::new(0.5, 0.5, 0.5)));
+        let objects: Vec<Arc<dyn Hittable>> = (0..200)
+            .map(|i| {
+                let x = 2f64.powi(i);
+                Arc::new(Sphere::new(
+                    Point3::new(x, 0.0, 0.0),
+                    x / 4.0,
+                    material.clone(),
+                )) as Arc<dyn Hittable>
+            })
+            .collect();
+        assert!(BvhNode::new(&objects).stats().max_depth > STACK_SIZE);
+        let bvh = Bvh::new(&objects);
+        assert!(bvh.stats().max_depth <= STACK_SIZE);
+        let ray = Ray::new(Point3::new(16.0, 0.0, -10.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let rec = bvh.hit(
+            &ray,
+            &mut Interval::new(0.001, INFINITY),
+            &mut RandomSampler::new(0),
+        );
+        assert!((rec.unwrap().t - 6.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_builder_names() {
+        for builder in BVH_BUILDERS {
+            assert_eq!(builder.name().parse::<BvhBuilder>(), Ok(builder));
         }
+        assert!("random".parse::<BvhBuilder>().is_err());
     }
 }
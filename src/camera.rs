@@ -1,9 +1,26 @@
+use crate::adaptive::AdaptiveSampling;
+use crate::animation::{interpolate, CameraKeyframe};
+use crate::aov::Aov;
+use crate::aperture::Aperture;
+use crate::background::Background;
+use crate::checkpoint::Fingerprint;
+use crate::color::luminance;
+use crate::effects::Effects;
+use crate::framebuffer::Framebuffer;
+use crate::output;
+use crate::projection::Projection;
+use crate::sampler::{PixelSampler, SamplerKind};
+use crate::shutter::Shutter;
+use crate::tonemap::ToneMapping;
 use crate::utils::*;
-use crate::write_color;
 use crate::Result;
-use crate::{Color, Hittable, Hittables, Interval, Point3, Ray, Vec3};
+use crate::{Color, HitRecord, Hittable, Hittables, Interval, Point3, Ray, Sampler, Vec3};
 use rayon::prelude::*;
 use std::io::Write;
+use std::sync::Arc;
+
+// called with the first hit of a camera ray, to record the AOV passes
+type FirstHit<'a> = &'a mut dyn FnMut(&Ray, &HitRecord);
 #[derive(Debug, Default)]
 pub struct Camera {
     pub aspect_ratio: f64,
@@ -16,7 +33,25 @@ pub struct Camera {
     pub vup: Vec3,              // camera-relative "up" direction
     pub defocus_angle: f64,     // variation angle of rays through each pixel
     pub focus_dist: f64,        // distance from camera look from point to plane of perfect focus
-    pub background: Color,      // scene background color
+    pub aperture: Aperture,     // shape of the lens opening
+    pub cat_eye: f64,           // how much a second lens opening clips the aperture off center
+    pub tilt: f64,              // angle in degrees the plane of focus is tilted by
+    pub tilt_rotation: f64,     // angle in degrees of the tilt axis from horizontal
+    pub background: Background, // what rays that leave the scene see
+    pub seed: u64,              // seed of the per-sample random number generators
+    pub sampler: SamplerKind,   // how the samples of each pixel are distributed
+    pub projection: Projection, // how pixels map to ray directions
+    // spend the samples on noisy pixels, `samples_per_pixel` becomes the average
+    pub adaptive: Option<AdaptiveSampling>,
+    pub shutter_open: f64,  // scene time the exposure starts at
+    pub shutter_close: f64, // scene time the exposure ends at
+    pub shutter: Shutter,   // how the exposure is spread over that interval
+    // settings that change over time, applied at the middle of the shutter interval
+    pub keyframes: Vec<CameraKeyframe>,
+    pub aovs: Vec<Aov>, // passes recorded from the first hits besides the image
+    // how the image is shown on displays, applied when it is written to display formats
+    pub tone_mapping: ToneMapping,
+    pub effects: Effects, // lens effects applied to the finished image for displays
     image_height: u32,
     center: Point3, // Camera center
     pixel00_loc: Point3,
@@ -27,6 +62,7 @@ pub struct Camera {
     w: Vec3, // camera frame basis vector
     defocus_disk_u: Vec3,
     defocus_disk_v: Vec3,
+    focus_normal: Vec3, // of the plane of focus, towards the camera
 }
 
 impl Camera {
@@ -37,97 +73,369 @@ impl Camera {
         max_depth: i32,
         vfof: f64,
     ) -> Self {
-        let mut camera = Camera::default();
-        camera.aspect_ratio = aspect_ratio;
-        camera.image_width = image_width;
-        camera.samples_per_pixel = samples_per_pixel;
-        camera.max_depth = max_depth;
-        camera.vfov = vfof;
-        camera.look_from = Point3::new(0.0, 0.0, 0.0);
-        camera.look_at = Point3::new(0.0, 0.0, -1.0);
-        camera.vup = Vec3::new(0.0, 1.0, 0.0);
-        camera.defocus_angle = 10.0;
-        camera.focus_dist = 3.4;
-        camera
-    }
-
-    fn render_pixel(&self, world: &Hittables, i: u32, j: u32) -> Color {
-        let mut pixel_color = Color::default();
-        for _ in 0..self.samples_per_pixel {
-            let r = self.get_ray(i, j);
-            pixel_color += &self.ray_color(&r, self.max_depth, world);
-        }
-        // Divide the color by number of samples per pixel
-        pixel_color / self.samples_per_pixel as f64
-    }
-    // rdner world and write result to writter
+        Camera {
+            aspect_ratio,
+            image_width,
+            samples_per_pixel,
+            max_depth,
+            vfov: vfof,
+            look_from: Point3::new(0.0, 0.0, 0.0),
+            look_at: Point3::new(0.0, 0.0, -1.0),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            defocus_angle: 10.0,
+            focus_dist: 3.4,
+            shutter_close: 1.0,
+            ..Default::default()
+        }
+    }
+
+    // radiance of sample `sample_index` of pixel i,j
+    fn render_sample(
+        &self,
+        world: &Hittables,
+        lights: &Hittables,
+        i: u32,
+        j: u32,
+        sample_index: u32,
+        first_hit: Option<FirstHit>,
+    ) -> Color {
+        // every sample gets its own generator so the image does not depend on thread scheduling
+        let mut sampler = PixelSampler::new(
+            self.sampler,
+            self.seed,
+            (i, j),
+            self.image_width,
+            sample_index,
+            self.samples_per_pixel,
+        );
+        let Some(r) = self.get_ray(i, j, &mut sampler) else {
+            // outside what the projection covers
+            return Color::default();
+        };
+        self.ray_color(
+            &r,
+            self.max_depth,
+            world,
+            lights,
+            None,
+            first_hit,
+            &mut sampler,
+        )
+    }
+    // render world and write result to writter as a text PPM
     pub fn render<W>(&mut self, world: &Hittables, writer: &mut W) -> Result<()>
     where
         W: Write,
     {
-        self.initialize();
-        write!(
-            writer,
-            "P3\n{} {}\n255\n",
-            self.image_width, self.image_height
-        )?;
-        let rows_of_pixels: Vec<_> = (0..self.image_height)
-            .into_par_iter()
-            .map(|j| {
-                (0..self.image_width)
-                    .into_iter()
-                    .map(|i| self.render_pixel(world, i, j))
-                    .collect::<Vec<_>>()
-            })
-            .collect();
-        for row_of_pixels in rows_of_pixels {
-            for pixel_color in row_of_pixels {
-                write_color(writer, &pixel_color)?;
+        let framebuffer = self.render_to_buffer(world);
+        let image = self.effects.apply(&framebuffer);
+        output::write_ppm_ascii(writer, &self.tone_mapping.apply_to(&image))
+    }
+
+    // render world into a linear RGB framebuffer
+    pub fn render_to_buffer(&mut self, world: &Hittables) -> Framebuffer {
+        let mut framebuffer =
+            Framebuffer::with_aovs(self.image_width, self.image_height(), &self.aovs);
+        // adaptive sampling needs passes to find out where the noise is
+        let pass_samples = match self.adaptive {
+            Some(adaptive) => adaptive.min_samples.max(1),
+            None => self.samples_per_pixel,
+        };
+        while !self.is_finished(&framebuffer) {
+            self.render_pass(world, &mut framebuffer, pass_samples);
+        }
+        framebuffer
+    }
+
+    // render world in passes of `pass_samples` samples per pixel until `is_finished`, calling
+    // `on_pass` between passes. Stops early if `on_pass` returns an error. Without adaptive
+    // sampling the result is the same as that of `render_to_buffer`
+    pub fn render_progressive<F>(
+        &mut self,
+        world: &Hittables,
+        framebuffer: &mut Framebuffer,
+        pass_samples: u32,
+        mut on_pass: F,
+    ) -> Result<()>
+    where
+        F: FnMut(&Framebuffer) -> Result<()>,
+    {
+        while !self.is_finished(framebuffer) {
+            self.render_pass(world, framebuffer, pass_samples.max(1));
+            if !self.is_finished(framebuffer) {
+                on_pass(framebuffer)?;
             }
         }
         Ok(())
     }
 
-    fn get_ray(&self, i: u32, j: u32) -> Ray {
+    // whether every pixel of `framebuffer` has `samples_per_pixel` samples or, with adaptive
+    // sampling, the budget of `samples_per_pixel` samples on average is spent or no pixel needs
+    // more samples
+    pub fn is_finished(&self, framebuffer: &Framebuffer) -> bool {
+        let pixels = framebuffer.width() as u64 * framebuffer.height() as u64;
+        if framebuffer.total_samples() >= self.samples_per_pixel as u64 * pixels {
+            return true;
+        }
+        let limits = self.sample_limits(framebuffer);
+        (0..framebuffer.height())
+            .flat_map(|j| (0..framebuffer.width()).map(move |i| (i, j)))
+            .all(|(i, j)| {
+                framebuffer.sample_count(i, j) >= limits[(j * framebuffer.width() + i) as usize]
+            })
+    }
+
+    // the number of samples each pixel may reach: `samples_per_pixel`, or with adaptive sampling
+    // up to the maximum for pixels that need more samples and no more for the others
+    fn sample_limits(&self, framebuffer: &Framebuffer) -> Vec<u32> {
+        match self.adaptive {
+            None => {
+                let pixels = framebuffer.width() as usize * framebuffer.height() as usize;
+                vec![self.samples_per_pixel; pixels]
+            }
+            Some(adaptive) => {
+                let needs_samples = adaptive.needs_samples(framebuffer);
+                let mut limits = Vec::with_capacity(needs_samples.len());
+                for j in 0..framebuffer.height() {
+                    for i in 0..framebuffer.width() {
+                        let count = framebuffer.sample_count(i, j);
+                        let index = (j * framebuffer.width() + i) as usize;
+                        limits.push(if !needs_samples[index] {
+                            count
+                        } else if count < adaptive.min_samples {
+                            adaptive.min_samples
+                        } else {
+                            adaptive.max_samples
+                        });
+                    }
+                }
+                limits
+            }
+        }
+    }
+
+    // add up to `samples` samples to every pixel of `framebuffer` that needs more. Each pixel
+    // continues with the sample index it left off at, so splitting a render into passes does not
+    // change the image
+    pub fn render_pass(&mut self, world: &Hittables, framebuffer: &mut Framebuffer, samples: u32) {
+        self.initialize();
+        assert!(
+            framebuffer.width() == self.image_width && framebuffer.height() == self.image_height,
+            "framebuffer size does not match the camera"
+        );
+        assert!(
+            framebuffer.aovs() == self.aovs,
+            "framebuffer passes do not match the camera"
+        );
+        let limits = self.sample_limits(framebuffer);
+        let width = self.image_width as usize;
+        let mut lights = Hittables::default();
+        for emitter in world.emitters() {
+            lights.add(emitter);
+        }
+        if let Background::Environment(ref environment) = self.background {
+            lights.add(Arc::new(environment.clone()));
+        }
+        let passes = self.aovs.len();
+        framebuffer.par_rows_mut().enumerate().for_each(|(j, row)| {
+            for i in 0..width {
+                let start = row.sample_counts[i];
+                let end = limits[j * width + i].min(start.saturating_add(samples));
+                // samples go straight into the sums, so they are added in the same order
+                // however the render is split into passes
+                for s in start..end {
+                    // the passes record the first hit of the sample's camera ray
+                    let mut record = |ray: &Ray, rec: &HitRecord| {
+                        let hits = &mut row.aov_hits[i];
+                        let aov_sums = &mut row.aov_sums[i * passes..(i + 1) * passes];
+                        for (sum, aov) in aov_sums.iter_mut().zip(&self.aovs) {
+                            if !aov.is_id() {
+                                *sum += &aov.value(ray, rec);
+                            } else if *hits == 0 {
+                                *sum = aov.value(ray, rec);
+                            }
+                        }
+                        *hits += 1;
+                    };
+                    let first_hit = (passes > 0).then_some(&mut record as FirstHit);
+                    let color =
+                        self.render_sample(world, &lights, i as u32, j as u32, s, first_hit);
+                    row.luminance_sq[i] += luminance(&color).powi(2);
+                    row.radiance[i] += &color;
+                    row.sample_counts[i] = s + 1;
+                }
+            }
+        });
+    }
+
+    fn get_ray(&self, i: u32, j: u32, sampler: &mut dyn Sampler) -> Option<Ray> {
         // get a randomly-sampled camera ray for the pixel at location i,j, originating from the
         // camera defocus disk
         let pixel_center =
             &self.pixel00_loc + (&self.pixel_delta_u * i as f64) + (&self.pixel_delta_v * j as f64);
-        let pixel_sample = &pixel_center + self.pixel_sample_square();
-        let ray_origin = if self.defocus_angle < 0.0 {
-            self.center.clone()
-        } else {
-            self.defocus_disk_sample()
+        // the pixel, lens and time dimensions are always taken in this order
+        let (px, py) = sampler.get_2d();
+        let pixel_sample = &pixel_center
+            + ((&self.pixel_delta_u * (px - 0.5)) + (&self.pixel_delta_v * (py - 0.5)));
+        let (lens_x, lens_y) = self.aperture.sample(sampler);
+        let lens_sample =
+            &self.center + (lens_x * &self.defocus_disk_u) + (lens_y * &self.defocus_disk_v);
+        let (ray_origin, ray_direction) = match self.projection {
+            Projection::Perspective => {
+                let ray_origin = if self.defocus_angle < 0.0 {
+                    self.center.clone()
+                } else {
+                    if self.cat_eye > 0.0 && !self.in_cat_eye(i, j, lens_x, lens_y) {
+                        // blocked by the front of the lens
+                        return None;
+                    }
+                    lens_sample
+                };
+                let ray_direction = if self.tilt == 0.0 {
+                    &pixel_sample - &ray_origin
+                } else {
+                    &self.tilted_focus(pixel_sample) - &ray_origin
+                };
+                (ray_origin, ray_direction)
+            }
+            // parallel rays from the plane through the camera center
+            Projection::Orthographic { .. } => {
+                (&pixel_sample + self.focus_dist * &self.w, -1.0 * &self.w)
+            }
+            projection => {
+                let [right, up, forward] = projection.direction(
+                    i as f64 + px,
+                    j as f64 + py,
+                    self.image_width as f64,
+                    self.image_height as f64,
+                )?;
+                let direction = right * &self.u + up * &self.v - forward * &self.w;
+                (self.center.clone(), direction)
+            }
         };
-        let ray_direction = &pixel_sample - &ray_origin;
-        return Ray {
+        Some(Ray {
             orig: ray_origin,
             dir: ray_direction,
-            tm: random_f64(),
+            tm: self.shutter.sample_time(
+                self.shutter_open,
+                self.shutter_close,
+                j,
+                self.image_height,
+                sampler.get_1d(),
+            ),
+        })
+    }
+
+    // whether the lens position passes the front opening, which shifts off the aperture towards
+    // the image corners, making out of focus highlights there cat's eye shaped
+    fn in_cat_eye(&self, i: u32, j: u32, lens_x: f64, lens_y: f64) -> bool {
+        let (width, height) = (self.image_width as f64, self.image_height as f64);
+        let half_diagonal = (width * width + height * height).sqrt() / 2.0;
+        let x = (i as f64 + 0.5 - width / 2.0) / half_diagonal;
+        let y = (height / 2.0 - j as f64 - 0.5) / half_diagonal;
+        let (dx, dy) = (lens_x - self.cat_eye * x, lens_y - self.cat_eye * y);
+        dx * dx + dy * dy <= 1.0
+    }
+
+    // where the pinhole ray through `pixel_sample` meets the tilted plane of focus
+    fn tilted_focus(&self, pixel_sample: Point3) -> Point3 {
+        let direction = &pixel_sample - &self.center;
+        let t =
+            -self.focus_dist * self.w.dot(&self.focus_normal) / direction.dot(&self.focus_normal);
+        if t.is_finite() && t > 0.0 {
+            &self.center + t * &direction
+        } else {
+            // looking along or away from the plane, nothing there is in focus anyway
+            pixel_sample
+        }
+    }
+
+    // hash of the settings that shape the image except `samples_per_pixel` and `seed`, so a
+    // checkpointed render can be continued with more samples
+    pub fn fingerprint(&self) -> u64 {
+        let mut fingerprint = Fingerprint::default();
+        fingerprint
+            .add_f64(self.aspect_ratio)
+            .add_u64(self.image_width as u64)
+            .add_u64(self.max_depth as u64)
+            .add_f64(self.vfov)
+            .add_f64(self.defocus_angle)
+            .add_f64(self.focus_dist)
+            .add_bytes(self.aperture.to_string().as_bytes())
+            .add_f64(self.cat_eye)
+            .add_f64(self.tilt)
+            .add_f64(self.tilt_rotation)
+            .add_bytes(self.sampler.name().as_bytes())
+            .add_bytes(self.projection.to_string().as_bytes())
+            .add_f64(self.shutter_open)
+            .add_f64(self.shutter_close)
+            .add_bytes(self.shutter.to_string().as_bytes())
+            .add_bytes(format!("{:?}", self.keyframes).as_bytes());
+        // the passes are accumulated with the image
+        for aov in &self.aovs {
+            fingerprint.add_bytes(aov.name().as_bytes());
+        }
+        for v in [&self.look_from, &self.look_at, &self.vup] {
+            fingerprint.add_f64(v.x()).add_f64(v.y()).add_f64(v.z());
+        }
+        // the contents of the images, which may change under the same file name
+        if let Aperture::Mask(ref mask) = self.aperture {
+            fingerprint.add_u64(mask.fingerprint());
+        }
+        match self.background {
+            Background::Color(ref c) => fingerprint.add_f64(c.x()).add_f64(c.y()).add_f64(c.z()),
+            Background::Environment(ref e) => fingerprint
+                .add_bytes(e.to_string().as_bytes())
+                .add_u64(e.map.fingerprint()),
         };
+        fingerprint.finish()
     }
 
-    fn defocus_disk_sample(&self) -> Point3 {
-        let p = Vec3::random_in_unit_disk();
-        &self.center + (p.x() * &self.defocus_disk_u) + (p.y() * &self.defocus_disk_v)
+    // move the keyframed settings to where they are at `time`
+    pub fn apply_keyframes(&mut self, time: f64) {
+        let keyframes = &self.keyframes;
+        if let Some(look_from) = interpolate(keyframes, time, |k| k.look_from.as_ref()) {
+            self.look_from = look_from;
+        }
+        if let Some(look_at) = interpolate(keyframes, time, |k| k.look_at.as_ref()) {
+            self.look_at = look_at;
+        }
+        if let Some(vfov) = interpolate(keyframes, time, |k| k.vfov.as_ref()) {
+            self.vfov = vfov;
+        }
+        if let Some(focus_dist) = interpolate(keyframes, time, |k| k.focus_dist.as_ref()) {
+            self.focus_dist = focus_dist;
+        }
+        if let Some(defocus_angle) = interpolate(keyframes, time, |k| k.defocus_angle.as_ref()) {
+            self.defocus_angle = defocus_angle;
+        }
     }
 
-    fn pixel_sample_square(&self) -> Vec3 {
-        let px = -0.5 + random_f64();
-        let py = -0.5 + random_f64();
-        return (&self.pixel_delta_u * px) + (&self.pixel_delta_v * py);
+    // image height derived from image width and aspect ratio, at least one pixel
+    pub fn image_height(&self) -> u32 {
+        ((self.image_width as f64 / self.aspect_ratio) as u32).max(1)
     }
 
     // initialize some internal state
     fn initialize(&mut self) {
-        self.image_height = ((self.image_width as f64 / self.aspect_ratio) as u32).max(1);
+        self.image_height = self.image_height();
+        self.apply_keyframes((self.shutter_open + self.shutter_close) / 2.0);
 
         // determine viewport dimensions
         self.center = self.look_from.clone();
         let theta = degrees_to_radians(self.vfov);
         let h = f64::tan(theta / 2.0);
 
-        let viewport_height = 2.0 * h * self.focus_dist;
+        let viewport_height = match self.projection {
+            Projection::Orthographic {
+                height: Some(height),
+            } => height,
+            // what the perspective camera sees at the distance of `look_at`
+            Projection::Orthographic { height: None } => {
+                2.0 * h * (&self.look_at - &self.look_from).length()
+            }
+            _ => 2.0 * h * self.focus_dist,
+        };
         let viewport_width = viewport_height * (self.image_width as f64 / self.image_height as f64);
         // calculate u,v,w unit basis vector for the camera coordinate frame
         self.w = (&self.look_from - &self.look_at).make_unit_vector();
@@ -151,25 +459,307 @@ impl Camera {
             self.focus_dist * f64::tan(degrees_to_radians(self.defocus_angle / 2.0));
         self.defocus_disk_u = &self.u * defofus_radius;
         self.defocus_disk_v = &self.v * defofus_radius;
+
+        // tilt the plane of focus about an axis in the image plane
+        let rotation = degrees_to_radians(self.tilt_rotation);
+        let axis = rotation.cos() * &self.u + rotation.sin() * &self.v;
+        let tilt = degrees_to_radians(self.tilt);
+        self.focus_normal = tilt.cos() * &self.w + tilt.sin() * &self.w.cross(&axis);
     }
 
-    fn ray_color(&self, ray: &Ray, depth: i32, hittables: &Hittables) -> Color {
+    // radiance arriving along `ray`. At every hit on a non-specular material the emitters in
+    // `lights` are sampled directly, and emission found by the scattered ray is weighted against
+    // that with multiple importance sampling. `scatter_pdf` is the density with which the
+    // previous hit picked `ray`, None for camera rays and after specular bounces. `first_hit` is
+    // given the hit of a camera ray for the AOV passes
+    #[allow(clippy::too_many_arguments)]
+    fn ray_color(
+        &self,
+        ray: &Ray,
+        depth: i32,
+        hittables: &Hittables,
+        lights: &Hittables,
+        scatter_pdf: Option<f64>,
+        first_hit: Option<FirstHit>,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
         if depth == 0 {
             return Color::default();
         }
-        if let Some(rec) = hittables.hit(&ray, &mut Interval::new(0.001, INFINITY)) {
-            let color_from_emission = rec.material.emitted(rec.u, rec.v, &rec.point);
-
-            if let Some(scatter_info) = rec.material.scatter(&ray, &rec) {
-                let color_from_scatter = &scatter_info.attenuation
-                    * &self.ray_color(&scatter_info.ray_scattered, depth - 1, &hittables);
-                return color_from_emission + color_from_scatter;
-            } else {
-                return color_from_emission;
+        sampler.start_bounce((self.max_depth - depth) as u32);
+        let Some(rec) = hittables.hit(ray, &mut Interval::new(0.001, INFINITY), sampler) else {
+            // the ray hits nothing, an environment map is also sampled as a light
+            let mut color = self.background.value(&ray.dir);
+            if let (Some(pdf), Background::Environment(_)) = (scatter_pdf, &self.background) {
+                color *= power_heuristic(pdf, lights.pdf_value(ray, sampler));
             }
+            return color;
+        };
+        if let Some(first_hit) = first_hit {
+            first_hit(ray, &rec);
+        }
+        let mut color = rec.material.emitted(rec.u, rec.v, &rec.point);
+        if let Some(pdf) = scatter_pdf {
+            if rec.material.is_emissive() {
+                color *= power_heuristic(pdf, lights.pdf_value(ray, sampler));
+            }
+        }
+
+        let Some(scatter_info) = rec.material.scatter(ray, &rec, sampler) else {
+            return color;
+        };
+        // the scattered ray of the last bounce is not traced, so the lights are not sampled there
+        // either; a light sample without its scattered counterpart would not be balanced by MIS
+        let scatter_pdf = if rec.material.is_specular() || lights.objects.is_empty() || depth == 1 {
+            None
         } else {
-            // the ray hits nothing
-            return self.background.clone();
+            color += &self.sample_lights(ray, &rec, hittables, lights, sampler);
+            Some(rec.material.pdf(ray, &rec, &scatter_info.ray_scattered.dir))
+        };
+        let color_from_scatter = &scatter_info.attenuation
+            * &self.ray_color(
+                &scatter_info.ray_scattered,
+                depth - 1,
+                hittables,
+                lights,
+                scatter_pdf,
+                None,
+                sampler,
+            );
+        color + color_from_scatter
+    }
+
+    // next event estimation: light reaching `rec` from a random point on a random emitter
+    fn sample_lights(
+        &self,
+        ray: &Ray,
+        rec: &HitRecord,
+        hittables: &Hittables,
+        lights: &Hittables,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        let direction = lights.random_direction(&rec.point, ray.tm, sampler);
+        let light_ray = Ray::new(rec.point.clone(), direction, ray.tm);
+        let light_pdf = lights.pdf_value(&light_ray, sampler);
+        if light_pdf <= 0.0 {
+            return Color::default();
+        }
+        let bsdf = rec.material.eval(ray, rec, &light_ray.dir);
+        if bsdf == Color::default() {
+            return Color::default();
         }
+        // whatever the ray hits first; an emitter other than the sampled one is fine as
+        // `light_pdf` covers all of them, and so is the environment where it hits nothing
+        let emitted = match hittables.hit(&light_ray, &mut Interval::new(0.001, INFINITY), sampler)
+        {
+            Some(light_rec) => {
+                light_rec
+                    .material
+                    .emitted(light_rec.u, light_rec.v, &light_rec.point)
+            }
+            None => match self.background {
+                Background::Environment(ref environment) => environment.radiance(&light_ray.dir),
+                Background::Color(_) => return Color::default(),
+            },
+        };
+        let weight = power_heuristic(light_pdf, rec.material.pdf(ray, rec, &light_ray.dir));
+        &bsdf * &emitted * (weight / light_pdf)
+    }
+}
+
+// multiple importance sampling weight of a sample taken with density `pdf` when `other_pdf` is
+// the density of the other strategy for the same sample
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if b == 0.0 || a.is_infinite() {
+        1.0
+    } else {
+        a / (a + b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_scenes::find_scene;
+    use crate::RandomSampler;
+
+    fn render_with_threads(seed: u64, threads: usize) -> Framebuffer {
+        let mut scene = find_scene("cornell_smoke")
+            .unwrap()
+            .build(&mut RandomSampler::new(seed))
+            .unwrap();
+        scene.camera.image_width = 12;
+        scene.camera.samples_per_pixel = 3;
+        scene.camera.seed = seed;
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        pool.install(|| scene.camera.render_to_buffer(&scene.world))
+    }
+
+    #[test]
+    fn test_deterministic_render() {
+        let single = render_with_threads(7, 1);
+        assert_eq!(single, render_with_threads(7, 4));
+        assert_ne!(single, render_with_threads(8, 4));
+    }
+
+    #[test]
+    fn test_progressive_matches_single_pass() {
+        let mut scene = find_scene("cornell_box")
+            .unwrap()
+            .build(&mut RandomSampler::new(0))
+            .unwrap();
+        scene.camera.image_width = 8;
+        scene.camera.samples_per_pixel = 5;
+        scene.camera.aovs = vec![Aov::Depth, Aov::Albedo];
+        let single = scene.camera.render_to_buffer(&scene.world);
+        // the passes are recorded from the samples of the image, and continue with them
+        assert_eq!(single.aov_hits(4, 4), 5);
+
+        let mut framebuffer = Framebuffer::with_aovs(8, 8, &scene.camera.aovs);
+        let mut passes = 0;
+        scene
+            .camera
+            .render_progressive(&scene.world, &mut framebuffer, 2, |buffer| {
+                passes += 1;
+                assert_eq!(buffer.sample_count(0, 0), 2 * passes);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(passes, 2);
+        assert_eq!(framebuffer, single);
+    }
+
+    // the scene without emitters to sample, so it renders with scattered rays alone
+    struct WithoutLights(Hittables);
+
+    impl Hittable for WithoutLights {
+        fn hit(
+            &self,
+            ray: &Ray,
+            ray_t: &mut Interval,
+            sampler: &mut dyn Sampler,
+        ) -> Option<HitRecord> {
+            self.0.hit(ray, ray_t, sampler)
+        }
+
+        fn bounding_box(&self) -> crate::aabb::AABB {
+            self.0.bounding_box()
+        }
+    }
+
+    #[test]
+    fn test_projections() {
+        let mut camera = Camera::new(2.0, 20, 1, 1, 90.0);
+        camera.look_from = Point3::new(0.0, 0.0, 5.0);
+        camera.look_at = Point3::new(0.0, 0.0, 0.0);
+        camera.projection = Projection::Orthographic { height: Some(4.0) };
+        camera.initialize();
+        let mut sampler = RandomSampler::new(0);
+        // parallel rays from a 8 by 4 rectangle around the camera center
+        for (i, j) in [(0, 0), (19, 9), (10, 5)] {
+            let ray = camera.get_ray(i, j, &mut sampler).unwrap();
+            assert_eq!(ray.dir, Vec3::new(0.0, 0.0, -1.0));
+            assert!(ray.orig.x().abs() <= 4.0 && ray.orig.y().abs() <= 2.0);
+            assert!((ray.orig.z() - 5.0).abs() < 1e-9);
+        }
+
+        // a fisheye camera has no rays outside its image circle
+        camera.projection = Projection::Fisheye { fov: 180.0 };
+        camera.initialize();
+        assert!(camera.get_ray(0, 5, &mut sampler).is_none());
+        let ray = camera.get_ray(10, 5, &mut sampler).unwrap();
+        assert_eq!(ray.orig, camera.look_from);
+        assert!(ray.dir.z() < 0.0);
+    }
+
+    #[test]
+    fn test_lens() {
+        let mut camera = Camera::new(1.0, 10, 1, 1, 90.0);
+        camera.focus_dist = 10.0;
+        camera.tilt = 30.0;
+        camera.initialize();
+        // the plane of focus recedes towards the top of the image and passes through the center
+        // of the view at the focus distance
+        for (y, farther) in [(5.0, true), (-5.0, false), (0.0, false)] {
+            let focus = camera.tilted_focus(Point3::new(1.0, y, -10.0));
+            let on_plane = &focus + Vec3::new(0.0, 0.0, 10.0);
+            assert!(on_plane.dot(&camera.focus_normal).abs() < 1e-9);
+            assert_eq!(focus.z() < -10.0, farther, "{}", focus);
+        }
+
+        // off center pixels lose the side of the aperture facing away from them
+        camera.cat_eye = 0.5;
+        assert!(camera.in_cat_eye(5, 5, -0.9, 0.0));
+        assert!(camera.in_cat_eye(9, 0, 0.99, 0.0));
+        assert!(!camera.in_cat_eye(9, 0, -0.99, 0.0));
+    }
+
+    #[test]
+    fn test_shutter() {
+        let mut camera = Camera::new(1.0, 10, 1, 1, 90.0);
+        camera.shutter_open = 2.0;
+        camera.shutter_close = 3.0;
+        camera.shutter = Shutter::Rolling { exposure: 0.1 };
+        camera.initialize();
+        let mut sampler = RandomSampler::new(0);
+        for _ in 0..100 {
+            // rolling from the top row at the opening to the bottom row at the closing
+            let top = camera.get_ray(3, 0, &mut sampler).unwrap().tm;
+            let bottom = camera.get_ray(3, 9, &mut sampler).unwrap().tm;
+            assert!((2.0..2.1).contains(&top), "{}", top);
+            assert!((2.9..3.0).contains(&bottom), "{}", bottom);
+        }
+        camera.shutter = Shutter::Triangle;
+        for _ in 0..100 {
+            assert!((2.0..3.0).contains(&camera.get_ray(3, 0, &mut sampler).unwrap().tm));
+        }
+    }
+
+    #[test]
+    fn test_light_sampling_converges_to_path_tracing() {
+        let mut scene = find_scene("cornell_box")
+            .unwrap()
+            .build(&mut RandomSampler::new(0))
+            .unwrap();
+        scene.camera.image_width = 6;
+        scene.camera.samples_per_pixel = 256;
+        let mean = |framebuffer: &Framebuffer| {
+            let pixels = framebuffer.pixels();
+            pixels.iter().map(luminance).sum::<f64>() / pixels.len() as f64
+        };
+        let sampled = mean(&scene.camera.render_to_buffer(&scene.world));
+        let world = Hittables::new(std::sync::Arc::new(WithoutLights(scene.world)));
+        let unsampled = mean(&scene.camera.render_to_buffer(&world));
+        assert!((sampled / unsampled - 1.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_light_sampling_at_max_depth() {
+        // with two bounces the walls are lit directly, and light sampling must not darken them
+        let mut scene = find_scene("cornell_box")
+            .unwrap()
+            .build(&mut RandomSampler::new(0))
+            .unwrap();
+        scene.camera.image_width = 6;
+        scene.camera.samples_per_pixel = 512;
+        scene.camera.max_depth = 2;
+        let mean = |framebuffer: &Framebuffer| {
+            let pixels = framebuffer.pixels();
+            pixels.iter().map(luminance).sum::<f64>() / pixels.len() as f64
+        };
+        let sampled = mean(&scene.camera.render_to_buffer(&scene.world));
+        let world = Hittables::new(std::sync::Arc::new(WithoutLights(scene.world)));
+        scene.camera.samples_per_pixel = 4096;
+        let unsampled = mean(&scene.camera.render_to_buffer(&world));
+        assert!(
+            (sampled / unsampled - 1.0).abs() < 0.1,
+            "{} {}",
+            sampled,
+            unsampled
+        );
     }
 }
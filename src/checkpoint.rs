@@ -0,0 +1,292 @@
+// Checkpoints of an unfinished render.
+//
+// A checkpoint holds everything needed to continue a render where it stopped: the radiance sums
+// and squared luminance sums, the sample counts and the AOV sums of every pixel, the seed of the per-sample random number generators (which
+// together with the sample counts is the complete generator state) and fingerprints of the scene
+// (its source and the files it reads) and of the camera, so a checkpoint is never applied to a
+// different render. The file is a small little-endian binary format:
+//
+//     magic "RTCKPT03", scene fingerprint u64, camera fingerprint u64, seed u64,
+//     width u32, height u32, number of AOVs u32, each AOV as its index in `AOV_NAMES` u32,
+//     then per pixel radiance sum as 3 x f64, squared luminance sum f64, sample count u32 and,
+//     with AOVs, the samples that hit something u32 and every AOV sum as 3 x f64
+use crate::aov::{Aov, AOV_NAMES};
+use crate::{Color, Framebuffer, Result};
+use std::fmt;
+use std::fs;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"RTCKPT03";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    pub scene_fingerprint: u64,
+    pub camera_fingerprint: u64,
+    pub seed: u64,
+    pub framebuffer: Framebuffer,
+}
+
+// why a checkpoint does not fit the render it should continue
+#[derive(Debug, Clone, PartialEq)]
+pub enum CheckpointMismatch {
+    Scene,
+    Camera,
+    Seed { checkpoint: u64, render: u64 },
+}
+
+impl fmt::Display for CheckpointMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointMismatch::Scene => write!(f, "the checkpoint was made for a different scene"),
+            CheckpointMismatch::Camera => write!(
+                f,
+                "the checkpoint was made with different camera settings, only the samples per pixel may change"
+            ),
+            CheckpointMismatch::Seed { checkpoint, render } => write!(
+                f,
+                "the checkpoint was made with seed {}, not {}",
+                checkpoint, render
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointMismatch {}
+
+impl Checkpoint {
+    // refuse to continue a render whose scene or camera differ from the checkpointed one
+    pub fn check(
+        &self,
+        scene_fingerprint: u64,
+        camera_fingerprint: u64,
+        seed: u64,
+    ) -> std::result::Result<(), CheckpointMismatch> {
+        if self.scene_fingerprint != scene_fingerprint {
+            Err(CheckpointMismatch::Scene)
+        } else if self.seed != seed {
+            Err(CheckpointMismatch::Seed {
+                checkpoint: self.seed,
+                render: seed,
+            })
+        } else if self.camera_fingerprint != camera_fingerprint {
+            Err(CheckpointMismatch::Camera)
+        } else {
+            Ok(())
+        }
+    }
+
+    // write through a temporary file, so an interrupted save keeps the previous checkpoint
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        let mut writer = BufWriter::new(fs::File::create(&partial)?);
+        writer.write_all(MAGIC)?;
+        for n in [self.scene_fingerprint, self.camera_fingerprint, self.seed] {
+            writer.write_all(&n.to_le_bytes())?;
+        }
+        let (width, height) = (self.framebuffer.width(), self.framebuffer.height());
+        writer.write_all(&width.to_le_bytes())?;
+        writer.write_all(&height.to_le_bytes())?;
+        let aovs = self.framebuffer.aovs();
+        writer.write_all(&(aovs.len() as u32).to_le_bytes())?;
+        for aov in aovs {
+            let index = AOV_NAMES
+                .iter()
+                .position(|&name| name == aov.name())
+                .unwrap();
+            writer.write_all(&(index as u32).to_le_bytes())?;
+        }
+        for j in 0..height {
+            for i in 0..width {
+                let radiance = self.framebuffer.radiance_sum(i, j);
+                let luminance_sq = self.framebuffer.luminance_sq_sum(i, j);
+                for c in [radiance.x(), radiance.y(), radiance.z(), luminance_sq] {
+                    writer.write_all(&c.to_le_bytes())?;
+                }
+                writer.write_all(&self.framebuffer.sample_count(i, j).to_le_bytes())?;
+                if aovs.is_empty() {
+                    continue;
+                }
+                writer.write_all(&self.framebuffer.aov_hits(i, j).to_le_bytes())?;
+                for sum in self.framebuffer.aov_sums(i, j) {
+                    for c in [sum.x(), sum.y(), sum.z()] {
+                        writer.write_all(&c.to_le_bytes())?;
+                    }
+                }
+            }
+        }
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+        fs::rename(&partial, path)?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut reader = BufReader::new(fs::File::open(path)?);
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err("not a checkpoint file".into());
+        }
+        let scene_fingerprint = read_u64(&mut reader)?;
+        let camera_fingerprint = read_u64(&mut reader)?;
+        let seed = read_u64(&mut reader)?;
+        let width = read_u32(&mut reader)?;
+        let height = read_u32(&mut reader)?;
+        let mut aovs = Vec::new();
+        for _ in 0..read_u32(&mut reader)? {
+            let name = AOV_NAMES
+                .get(read_u32(&mut reader)? as usize)
+                .ok_or("unknown AOV in the checkpoint")?;
+            aovs.push(name.parse::<Aov>()?);
+        }
+        let mut framebuffer = Framebuffer::with_aovs(width, height, &aovs);
+        let mut sums = vec![Color::default(); aovs.len()];
+        for j in 0..height {
+            for i in 0..width {
+                let radiance = read_color(&mut reader)?;
+                let luminance_sq = read_f64(&mut reader)?;
+                let count = read_u32(&mut reader)?;
+                framebuffer.add_samples(i, j, &radiance, count);
+                framebuffer.add_luminance_sq(i, j, luminance_sq);
+                if aovs.is_empty() {
+                    continue;
+                }
+                let hits = read_u32(&mut reader)?;
+                for sum in sums.iter_mut() {
+                    *sum = read_color(&mut reader)?;
+                }
+                framebuffer.add_aov_samples(i, j, &sums, hits);
+            }
+        }
+        if reader.read(&mut [0])? != 0 {
+            return Err("trailing data after the checkpoint".into());
+        }
+        Ok(Self {
+            scene_fingerprint,
+            camera_fingerprint,
+            seed,
+            framebuffer,
+        })
+    }
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f64(reader: &mut impl Read) -> Result<f64> {
+    Ok(f64::from_bits(read_u64(reader)?))
+}
+
+fn read_color(reader: &mut impl Read) -> Result<Color> {
+    Ok(Color::new(
+        read_f64(reader)?,
+        read_f64(reader)?,
+        read_f64(reader)?,
+    ))
+}
+
+// 64-bit FNV-1a hash, unlike `DefaultHasher` it gives the same value in every build
+#[derive(Debug, Clone)]
+pub struct Fingerprint(u64);
+
+impl Default for Fingerprint {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fingerprint {
+    pub fn add_bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+        self
+    }
+
+    pub fn add_u64(&mut self, n: u64) -> &mut Self {
+        self.add_bytes(&n.to_le_bytes())
+    }
+
+    pub fn add_f64(&mut self, x: f64) -> &mut Self {
+        self.add_u64(x.to_bits())
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_and_load() {
+        let mut framebuffer = Framebuffer::with_aovs(3, 2, &[Aov::Depth, Aov::ObjectId]);
+        framebuffer.add_samples(2, 1, &Color::new(0.1, 2.5, 1e-9), 7);
+        framebuffer.add_luminance_sq(2, 1, 3.25);
+        let sums = [Color::new(6.0, 6.0, 6.0), Color::new(4.0, 4.0, 4.0)];
+        framebuffer.add_aov_samples(2, 1, &sums, 5);
+        let checkpoint = Checkpoint {
+            scene_fingerprint: 1,
+            camera_fingerprint: 2,
+            seed: 3,
+            framebuffer,
+        };
+        let path = std::env::temp_dir().join(format!("checkpoint_{}.bin", std::process::id()));
+        checkpoint.save(&path).unwrap();
+        let loaded = Checkpoint::load(&path);
+        fs::write(&path, b"RTCKPT03 too short").unwrap();
+        let truncated = Checkpoint::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), checkpoint);
+        assert!(truncated.is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let checkpoint = Checkpoint {
+            scene_fingerprint: 1,
+            camera_fingerprint: 2,
+            seed: 3,
+            framebuffer: Framebuffer::new(1, 1),
+        };
+        assert_eq!(checkpoint.check(1, 2, 3), Ok(()));
+        assert_eq!(checkpoint.check(9, 2, 3), Err(CheckpointMismatch::Scene));
+        assert_eq!(checkpoint.check(1, 9, 3), Err(CheckpointMismatch::Camera));
+        assert_eq!(
+            checkpoint.check(1, 2, 9),
+            Err(CheckpointMismatch::Seed {
+                checkpoint: 3,
+                render: 9
+            })
+        );
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        // reference values of FNV-1a
+        assert_eq!(Fingerprint::default().finish(), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(
+            Fingerprint::default().add_bytes(b"a").finish(),
+            0xaf63_dc4c_8601_ec8c
+        );
+        let a = Fingerprint::default().add_f64(1.0).add_f64(2.0).finish();
+        let b = Fingerprint::default().add_f64(2.0).add_f64(1.0).finish();
+        assert_ne!(a, b);
+    }
+}
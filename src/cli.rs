@@ -0,0 +1,1632 @@
+use raytracing_2_rs::adaptive::{self, AdaptiveSampling};
+use raytracing_2_rs::animation::{self, Animation};
+use raytracing_2_rs::aov::{Aov, AOV_NAMES};
+use raytracing_2_rs::background::{Background, Environment, EnvironmentMap};
+use raytracing_2_rs::checkpoint::Checkpoint;
+use raytracing_2_rs::denoise::{Denoiser, GUIDE_AOVS};
+use raytracing_2_rs::effects::{Bloom, Effects, StarGlare, Vignetting};
+use raytracing_2_rs::output::{self, OutputFormat, Y4mWriter};
+use raytracing_2_rs::projection::Projection;
+use raytracing_2_rs::sample_scenes::{self, SceneEntry, SCENES};
+use raytracing_2_rs::sampler::SamplerKind;
+use raytracing_2_rs::tonemap::{ToneMap, ToneMapping, WHITE_BALANCE_RANGE};
+use raytracing_2_rs::{Camera, Framebuffer, Hittable, RandomSampler, Result, Scene};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "\
+usage: raytracing_2_rs <command> [options]
+
+commands:
+    render        render a scene to an image file
+    denoise       denoise a rendered .exr, .pfm or .hdr image
+    list-scenes   list the built-in scenes
+    info          print the camera and world settings of a scene
+    help          print this message
+
+options for render and info:
+    -s, --scene <scene>         built-in scene name or number, or a scene file (default: random_balls)
+    -w, --width <pixels>        image width
+    -a, --aspect-ratio <ratio>  aspect ratio, e.g. 1.5 or 16:9
+        --spp <n>               samples per pixel
+        --max-depth <n>         maximum number of ray bounces
+    -j, --threads <n>           number of render threads (default: all cores)
+        --seed <n>              seed for the random number generators (default: 0)
+        --sampler <kind>        distribution of the samples in each pixel: random, stratified,
+                                halton, sobol or blue-noise
+        --projection <kind>     perspective, orthographic, fisheye, equirectangular or cube_map;
+                                the last two default to the aspect ratio that fits them
+    -o, --output <path>         output file (default: images/<scene name>.png), the extension
+                                picks the format: png, jpg, ppm, exr, pfm or hdr
+        --bit-depth <8|16>      bits per channel for png and ppm (default: 8)
+        --exposure <stops>      brighten (or darken, if negative) the image by stops
+        --white-balance <k>     color temperature in kelvin of the light that should look white
+                                (default: 6500)
+        --tone-map <curve>      how bright colors are fit into the displayable range: clamp,
+                                reinhard, aces or agx; exposure, white balance and tone mapping
+                                do not change exr, pfm and hdr images
+        --environment <path>    light the scene with a lat-long environment map (.hdr, .exr or
+                                .pfm) instead of the background color
+        --environment-rotation <degrees>
+                                turn the environment map counterclockwise about the up axis
+        --bloom <strength>      spread this fraction (0 to 1) of the light brighter than white
+                                into a glow around it
+        --star-glare <spokes>   draw rays with this many spokes around bright lights
+        --glare-threshold <l>   luminance above which light blooms and draws rays (default: 1)
+        --vignetting <degrees>  darken towards the corners like a lens whose corners are seen at
+                                this angle to its axis; like tone mapping the lens effects do
+                                not change exr, pfm and hdr images
+        --pass-spp <n>          render progressively in passes of n samples per pixel and
+                                update the output after every pass
+        --snapshot-every <s>    update the output at most every s seconds while rendering
+                                progressively (default pass size: 4 samples per pixel)
+        --adaptive <error>      adaptive sampling: keep sampling pixels until their relative
+                                error is below <error>, --spp becomes the average
+        --min-spp <n>           samples every pixel gets with --adaptive (default: spp / 4)
+        --max-spp <n>           samples no pixel gets more of with --adaptive (default: 4 * spp)
+        --heatmap <path>        also write an image of the samples each pixel got
+        --variance-map <path>   also write an image of the relative error of each pixel
+        --checkpoint <path>     save the render state with every update, to continue it later
+        --resume                continue from the checkpoint (default: <output>.checkpoint);
+                                fails if the scene or camera changed, but --spp may be raised
+        --frames <first>-<last> render frames of an animation, each to its own numbered file:
+                                a run of # in the output path becomes the frame number, or
+                                _0001 is added before the extension
+        --fps <rate>            frames per second of an animation (default: 24)
+        --shutter-angle <deg>   part of each frame the shutter is open, 360 is all of it
+                                (default: 180)
+        --y4m <path>            also write the frames as an uncompressed Y4M video
+        --aov <passes>          also render passes of what the camera sees first, comma separated:
+                                depth, normal, albedo, uv, object_id, material_id, emission or
+                                all; each is written next to the output as a linear .exr
+                                image, e.g. <output>_depth.exr
+        --aov-layers            write the passes as layers of the .exr output instead
+        --denoise               denoise the image, guided by albedo, normal, depth and emission
+                                passes that are rendered for it
+        --iterations, --color-sigma, --normal-sigma, --depth-sigma
+                                tune the denoiser like for the denoise command
+
+options not given keep the values set up by the scene itself.
+
+usage: raytracing_2_rs denoise <image> [options]
+
+options for denoise:
+    -o, --output <path>         output file (default: <image>_denoised with the same extension)
+        --albedo <path>         albedo pass (default: the albedo layer of an .exr image, or
+                                <image>_albedo next to it if it exists)
+        --normal <path>         normal pass, found like the albedo pass
+        --depth <path>          depth pass, found like the albedo pass
+        --emission <path>       emission pass, found like the albedo pass
+        --iterations <n>        filter passes, each twice as wide as the one before (default: 5)
+        --color-sigma <x>       brightness difference that still blends, in standard deviations
+                                of the noise (default: 4)
+        --normal-sigma <x>      normal difference that still blends (default: 0.3)
+        --depth-sigma <x>       relative depth difference per pixel that still blends
+                                (default: 0.02)";
+
+#[derive(Debug, PartialEq)]
+pub enum CliError {
+    MissingCommand,
+    MissingArgument(&'static str),
+    UnknownCommand(String),
+    UnknownOption(String),
+    MissingValue(&'static str),
+    InvalidValue {
+        option: &'static str,
+        value: String,
+        expected: &'static str,
+    },
+    UnknownScene(String),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::MissingCommand => write!(f, "no command given"),
+            CliError::MissingArgument(a) => write!(f, "missing {}", a),
+            CliError::UnknownCommand(c) => write!(f, "unknown command `{}`", c),
+            CliError::UnknownOption(o) => write!(f, "unknown option `{}`", o),
+            CliError::MissingValue(o) => write!(f, "option `{}` needs a value", o),
+            CliError::InvalidValue {
+                option,
+                value,
+                expected,
+            } => write!(
+                f,
+                "invalid value `{}` for `{}`, expected {}",
+                value, option, expected
+            ),
+            CliError::UnknownScene(s) => {
+                let names: Vec<_> = SCENES.iter().map(|entry| entry.name).collect();
+                write!(
+                    f,
+                    "unknown scene `{}`, available scenes are: {}",
+                    s,
+                    names.join(", ")
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+#[derive(Debug)]
+pub enum Command {
+    Render(RenderOptions),
+    Denoise(DenoiseOptions),
+    ListScenes,
+    Info(RenderOptions),
+    Help,
+}
+
+#[derive(Debug)]
+pub enum SceneSource {
+    BuiltIn(&'static SceneEntry),
+    File(PathBuf),
+}
+
+impl SceneSource {
+    pub fn name(&self) -> String {
+        match self {
+            SceneSource::BuiltIn(entry) => entry.name.to_string(),
+            SceneSource::File(path) => path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_else(|| "scene".to_string()),
+        }
+    }
+}
+
+// settings from the command line, `None` means "use what the scene sets up"
+#[derive(Debug)]
+pub struct RenderOptions {
+    pub scene: SceneSource,
+    pub image_width: Option<u32>,
+    pub aspect_ratio: Option<f64>,
+    pub samples_per_pixel: Option<u32>,
+    pub max_depth: Option<i32>,
+    pub threads: Option<usize>,
+    pub seed: u64,
+    pub sampler: Option<SamplerKind>,
+    pub projection: Option<Projection>,
+    pub output: Option<String>,
+    pub bit_depth: u8,
+    pub pass_samples: Option<u32>,
+    pub snapshot_interval: Option<Duration>,
+    pub checkpoint: Option<PathBuf>,
+    pub resume: bool,
+    pub adaptive_threshold: Option<f64>,
+    pub min_samples: Option<u32>,
+    pub max_samples: Option<u32>,
+    pub heatmap: Option<String>,
+    pub variance_map: Option<String>,
+    pub frames: Option<(u32, u32)>,
+    pub fps: Option<f64>,
+    pub shutter_angle: Option<f64>,
+    pub y4m: Option<String>,
+    pub aovs: Option<Vec<Aov>>,
+    pub aov_layers: bool,
+    pub denoise: bool,
+    pub denoiser: Denoiser, // settings of `denoise`
+    pub exposure: Option<f64>,
+    pub white_balance: Option<f64>,
+    pub tone_map: Option<ToneMap>,
+    pub environment: Option<String>,
+    pub environment_rotation: Option<f64>,
+    pub bloom: Option<f64>,
+    pub star_glare: Option<u32>,
+    pub glare_threshold: Option<f64>,
+    pub vignetting: Option<f64>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            scene: SceneSource::BuiltIn(&SCENES[0]),
+            image_width: None,
+            aspect_ratio: None,
+            samples_per_pixel: None,
+            max_depth: None,
+            threads: None,
+            seed: 0,
+            sampler: None,
+            projection: None,
+            output: None,
+            bit_depth: 8,
+            pass_samples: None,
+            snapshot_interval: None,
+            checkpoint: None,
+            resume: false,
+            adaptive_threshold: None,
+            min_samples: None,
+            max_samples: None,
+            heatmap: None,
+            variance_map: None,
+            frames: None,
+            fps: None,
+            shutter_angle: None,
+            y4m: None,
+            aovs: None,
+            aov_layers: false,
+            denoise: false,
+            denoiser: Denoiser::default(),
+            exposure: None,
+            white_balance: None,
+            tone_map: None,
+            environment: None,
+            environment_rotation: None,
+            bloom: None,
+            star_glare: None,
+            glare_threshold: None,
+            vignetting: None,
+        }
+    }
+}
+
+impl RenderOptions {
+    // build the scene and override its camera with the options that were given
+    pub fn build_scene(&self) -> Result<Scene> {
+        // the same seed drives scene construction and rendering, so a render is repeatable
+        let mut sampler = RandomSampler::new(self.seed);
+        // scenes only carry IDs for ID passes they ask for themselves
+        let ids = self.aovs.iter().flatten().any(Aov::is_id);
+        let mut scene = match self.scene {
+            SceneSource::BuiltIn(entry) if ids => entry.build_with_ids(&mut sampler)?,
+            SceneSource::BuiltIn(entry) => entry.build(&mut sampler)?,
+            SceneSource::File(ref path) if ids => Scene::load_with_ids(path, &mut sampler)?,
+            SceneSource::File(ref path) => Scene::load(path, &mut sampler)?,
+        };
+        let camera = &mut scene.camera;
+        camera.seed = self.seed;
+        if let Some(sampler) = self.sampler {
+            camera.sampler = sampler;
+        }
+        if let Some(projection) = self.projection {
+            // keep the parameters the scene gave the same kind of projection
+            if camera.projection.name() != projection.name() {
+                camera.projection = projection;
+                if let Some(aspect_ratio) = projection.aspect_ratio() {
+                    camera.aspect_ratio = aspect_ratio;
+                }
+            }
+        }
+        if let Some(image_width) = self.image_width {
+            camera.image_width = image_width;
+        }
+        if let Some(aspect_ratio) = self.aspect_ratio {
+            camera.aspect_ratio = aspect_ratio;
+        }
+        if let Some(samples_per_pixel) = self.samples_per_pixel {
+            camera.samples_per_pixel = samples_per_pixel;
+        }
+        if let Some(ref aovs) = self.aovs {
+            camera.aovs = aovs.clone();
+        }
+        if let Some(max_depth) = self.max_depth {
+            camera.max_depth = max_depth;
+        }
+        if let Some(exposure) = self.exposure {
+            camera.tone_mapping.exposure = exposure;
+        }
+        if let Some(white_balance) = self.white_balance {
+            camera.tone_mapping.white_balance = white_balance;
+        }
+        if let Some(tone_map) = self.tone_map {
+            camera.tone_mapping.tone_map = tone_map;
+        }
+        if let Some(ref path) = self.environment {
+            let map = EnvironmentMap::open(path)
+                .map_err(|e| format!("cannot load environment map `{}`: {}", path, e))?;
+            camera.background = Background::Environment(Environment::new(Arc::new(map)));
+        }
+        if let Some(rotation) = self.environment_rotation {
+            let Background::Environment(ref mut environment) = camera.background else {
+                return Err("`--environment-rotation` needs an environment map".into());
+            };
+            environment.rotation = rotation;
+        }
+        let effects = &mut camera.effects;
+        if let Some(strength) = self.bloom {
+            effects.bloom.get_or_insert_with(Bloom::default).strength = strength;
+        }
+        if let Some(spokes) = self.star_glare {
+            effects
+                .star_glare
+                .get_or_insert_with(StarGlare::default)
+                .spokes = spokes;
+        }
+        if let Some(threshold) = self.glare_threshold {
+            if effects.bloom.is_none() && effects.star_glare.is_none() {
+                return Err("`--glare-threshold` needs --bloom or --star-glare".into());
+            }
+            if let Some(ref mut bloom) = effects.bloom {
+                bloom.threshold = threshold;
+            }
+            if let Some(ref mut star_glare) = effects.star_glare {
+                star_glare.threshold = threshold;
+            }
+        }
+        if let Some(corner_angle) = self.vignetting {
+            effects.vignetting = Some(Vignetting { corner_angle });
+        }
+        if let Some(threshold) = self.adaptive_threshold {
+            let spp = camera.samples_per_pixel;
+            let min_samples = self.min_samples.unwrap_or((spp / 4).max(2));
+            camera.adaptive = Some(AdaptiveSampling {
+                min_samples,
+                max_samples: self
+                    .max_samples
+                    .unwrap_or(spp.saturating_mul(4))
+                    .max(min_samples),
+                threshold,
+            });
+        }
+        if self.frames.is_some() || self.fps.is_some() || self.shutter_angle.is_some() {
+            let animation = scene.animation.get_or_insert_with(Animation::default);
+            if let Some(frames) = self.frames {
+                animation.frames = Some(frames);
+            }
+            if let Some(fps) = self.fps {
+                animation.fps = fps;
+            }
+            if let Some(shutter_angle) = self.shutter_angle {
+                animation.shutter_angle = shutter_angle;
+            }
+        }
+        Ok(scene)
+    }
+
+    pub fn output_path(&self) -> String {
+        match self.output {
+            Some(ref output) => output.clone(),
+            None => format!("images/{}.png", self.scene.name()),
+        }
+    }
+
+    // where checkpoints are written and resumed from, if at all
+    pub fn checkpoint_path(&self) -> Option<PathBuf> {
+        match self.checkpoint {
+            Some(ref path) => Some(path.clone()),
+            None if self.resume => {
+                Some(PathBuf::from(format!("{}.checkpoint", self.output_path())))
+            }
+            None => None,
+        }
+    }
+
+    pub fn is_progressive(&self) -> bool {
+        self.pass_samples.is_some()
+            || self.snapshot_interval.is_some()
+            || self.checkpoint_path().is_some()
+    }
+}
+
+// settings of the denoise command
+#[derive(Debug)]
+pub struct DenoiseOptions {
+    pub input: String,
+    pub output: Option<String>,
+    pub guides: Vec<(Aov, String)>, // guide passes given on the command line
+    pub denoiser: Denoiser,
+}
+
+impl DenoiseOptions {
+    pub fn output_path(&self) -> String {
+        match self.output {
+            Some(ref output) => output.clone(),
+            None => output::with_suffix(&self.input, "_denoised"),
+        }
+    }
+}
+
+// parse the arguments following the program name
+pub fn parse<I>(args: I) -> std::result::Result<Command, CliError>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut args = args.into_iter();
+    let command = args.next().ok_or(CliError::MissingCommand)?;
+    match command.as_str() {
+        "render" => Ok(Command::Render(parse_render_options(args)?)),
+        "denoise" => Ok(Command::Denoise(parse_denoise_options(args)?)),
+        "info" => Ok(Command::Info(parse_render_options(args)?)),
+        "list-scenes" => match args.next() {
+            Some(arg) => Err(CliError::UnknownOption(arg)),
+            None => Ok(Command::ListScenes),
+        },
+        "help" | "-h" | "--help" => Ok(Command::Help),
+        _ => Err(CliError::UnknownCommand(command)),
+    }
+}
+
+fn parse_render_options<I>(mut args: I) -> std::result::Result<RenderOptions, CliError>
+where
+    I: Iterator<Item = String>,
+{
+    let mut options = RenderOptions::default();
+    while let Some(arg) = args.next() {
+        // accept both `--width 400` and `--width=400`
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value)),
+            _ => (arg.clone(), None),
+        };
+        let option: &'static str = match flag.as_str() {
+            "-s" | "--scene" => "--scene",
+            "-w" | "--width" => "--width",
+            "-a" | "--aspect-ratio" => "--aspect-ratio",
+            "--spp" => "--spp",
+            "--max-depth" => "--max-depth",
+            "-j" | "--threads" => "--threads",
+            "--seed" => "--seed",
+            "--sampler" => "--sampler",
+            "--projection" => "--projection",
+            "-o" | "--output" => "--output",
+            "--bit-depth" => "--bit-depth",
+            "--exposure" => "--exposure",
+            "--white-balance" => "--white-balance",
+            "--tone-map" => "--tone-map",
+            "--environment" => "--environment",
+            "--environment-rotation" => "--environment-rotation",
+            "--bloom" => "--bloom",
+            "--star-glare" => "--star-glare",
+            "--glare-threshold" => "--glare-threshold",
+            "--vignetting" => "--vignetting",
+            "--pass-spp" => "--pass-spp",
+            "--snapshot-every" => "--snapshot-every",
+            "--checkpoint" => "--checkpoint",
+            "--adaptive" => "--adaptive",
+            "--min-spp" => "--min-spp",
+            "--max-spp" => "--max-spp",
+            "--heatmap" => "--heatmap",
+            "--variance-map" => "--variance-map",
+            "--frames" => "--frames",
+            "--fps" => "--fps",
+            "--shutter-angle" => "--shutter-angle",
+            "--y4m" => "--y4m",
+            "--aov" => "--aov",
+            "--iterations" => "--iterations",
+            "--color-sigma" => "--color-sigma",
+            "--normal-sigma" => "--normal-sigma",
+            "--depth-sigma" => "--depth-sigma",
+            "--aov-layers" if inline_value.is_none() => {
+                options.aov_layers = true;
+                continue;
+            }
+            "--denoise" if inline_value.is_none() => {
+                options.denoise = true;
+                continue;
+            }
+            "--resume" if inline_value.is_none() => {
+                options.resume = true;
+                continue;
+            }
+            _ => return Err(CliError::UnknownOption(arg)),
+        };
+        let value = match inline_value {
+            Some(value) => value.to_string(),
+            None => args.next().ok_or(CliError::MissingValue(option))?,
+        };
+        match option {
+            "--scene" => options.scene = parse_scene(value)?,
+            "--width" => options.image_width = Some(parse_positive(option, &value)?),
+            "--aspect-ratio" => options.aspect_ratio = Some(parse_aspect_ratio(&value)?),
+            "--spp" => options.samples_per_pixel = Some(parse_positive(option, &value)?),
+            "--max-depth" => options.max_depth = Some(parse_positive(option, &value)?),
+            "--threads" => options.threads = Some(parse_positive(option, &value)?),
+            "--checkpoint" => options.checkpoint = Some(PathBuf::from(value)),
+            "--iterations" | "--color-sigma" | "--normal-sigma" | "--depth-sigma" => {
+                parse_denoiser_option(&mut options.denoiser, option, &value)?
+            }
+            "--sampler" => {
+                options.sampler = Some(value.parse().map_err(|_| CliError::InvalidValue {
+                    option,
+                    value,
+                    expected: "random, stratified, halton, sobol or blue-noise",
+                })?)
+            }
+            "--projection" => {
+                options.projection = Some(value.parse().map_err(|_| CliError::InvalidValue {
+                    option,
+                    value,
+                    expected: "perspective, orthographic, fisheye, equirectangular or cube_map",
+                })?)
+            }
+            "--adaptive" => {
+                options.adaptive_threshold = match value.parse::<f64>() {
+                    Ok(threshold) if threshold.is_finite() && threshold > 0.0 => Some(threshold),
+                    _ => {
+                        return Err(CliError::InvalidValue {
+                            option,
+                            value,
+                            expected: "a positive number like 0.05",
+                        })
+                    }
+                }
+            }
+            "--min-spp" => options.min_samples = Some(parse_positive(option, &value)?),
+            "--max-spp" => options.max_samples = Some(parse_positive(option, &value)?),
+            "--heatmap" => options.heatmap = Some(value),
+            "--variance-map" => options.variance_map = Some(value),
+            "--y4m" => options.y4m = Some(value),
+            "--exposure" => {
+                options.exposure = match value.parse::<f64>() {
+                    Ok(stops) if stops.is_finite() => Some(stops),
+                    _ => {
+                        return Err(CliError::InvalidValue {
+                            option,
+                            value,
+                            expected: "a number of stops like 1.5 or -2",
+                        })
+                    }
+                }
+            }
+            "--white-balance" => {
+                options.white_balance = match value.parse::<f64>() {
+                    Ok(kelvin) if WHITE_BALANCE_RANGE.contains(&kelvin) => Some(kelvin),
+                    _ => {
+                        return Err(CliError::InvalidValue {
+                            option,
+                            value,
+                            expected: "a temperature from 1667 to 25000 kelvin",
+                        })
+                    }
+                }
+            }
+            "--tone-map" => {
+                options.tone_map = Some(value.parse().map_err(|_| CliError::InvalidValue {
+                    option,
+                    value,
+                    expected: "clamp, reinhard, aces or agx",
+                })?)
+            }
+            "--environment" => options.environment = Some(value),
+            "--environment-rotation" => {
+                options.environment_rotation = match value.parse::<f64>() {
+                    Ok(angle) if angle.is_finite() => Some(angle),
+                    _ => {
+                        return Err(CliError::InvalidValue {
+                            option,
+                            value,
+                            expected: "an angle in degrees",
+                        })
+                    }
+                }
+            }
+            "--bloom" => {
+                options.bloom = match value.parse::<f64>() {
+                    Ok(strength) if (0.0..=1.0).contains(&strength) => Some(strength),
+                    _ => {
+                        return Err(CliError::InvalidValue {
+                            option,
+                            value,
+                            expected: "a fraction from 0 to 1",
+                        })
+                    }
+                }
+            }
+            "--star-glare" => options.star_glare = Some(parse_positive(option, &value)?),
+            "--glare-threshold" => {
+                options.glare_threshold = match value.parse::<f64>() {
+                    Ok(threshold) if threshold.is_finite() && threshold >= 0.0 => Some(threshold),
+                    _ => {
+                        return Err(CliError::InvalidValue {
+                            option,
+                            value,
+                            expected: "a luminance of at least 0",
+                        })
+                    }
+                }
+            }
+            "--vignetting" => {
+                options.vignetting = match value.parse::<f64>() {
+                    Ok(angle) if angle > 0.0 && angle < 90.0 => Some(angle),
+                    _ => {
+                        return Err(CliError::InvalidValue {
+                            option,
+                            value,
+                            expected: "an angle between 0 and 90 degrees",
+                        })
+                    }
+                }
+            }
+            "--aov" => options.aovs = Some(parse_aovs(&value)?),
+            "--frames" => options.frames = Some(parse_frames(&value)?),
+            "--fps" => {
+                options.fps = match value.parse::<f64>() {
+                    Ok(fps) if fps.is_finite() && fps > 0.0 => Some(fps),
+                    _ => {
+                        return Err(CliError::InvalidValue {
+                            option,
+                            value,
+                            expected: "a positive number like 24 or 29.97",
+                        })
+                    }
+                }
+            }
+            "--shutter-angle" => {
+                options.shutter_angle = match value.parse::<f64>() {
+                    Ok(angle) if (0.0..=360.0).contains(&angle) => Some(angle),
+                    _ => {
+                        return Err(CliError::InvalidValue {
+                            option,
+                            value,
+                            expected: "an angle from 0 to 360 degrees",
+                        })
+                    }
+                }
+            }
+            "--pass-spp" => options.pass_samples = Some(parse_positive(option, &value)?),
+            "--snapshot-every" => {
+                options.snapshot_interval = match value.parse::<f64>() {
+                    Ok(seconds) if seconds.is_finite() && seconds >= 0.0 => {
+                        Some(Duration::from_secs_f64(seconds))
+                    }
+                    _ => {
+                        return Err(CliError::InvalidValue {
+                            option,
+                            value,
+                            expected: "a number of seconds",
+                        })
+                    }
+                }
+            }
+            "--seed" => {
+                options.seed = value.parse().map_err(|_| CliError::InvalidValue {
+                    option,
+                    value,
+                    expected: "a non-negative integer",
+                })?
+            }
+            "--bit-depth" => {
+                options.bit_depth = match value.as_str() {
+                    "8" => 8,
+                    "16" => 16,
+                    _ => {
+                        return Err(CliError::InvalidValue {
+                            option,
+                            value,
+                            expected: "8 or 16",
+                        })
+                    }
+                }
+            }
+            _ => options.output = Some(value),
+        }
+    }
+    Ok(options)
+}
+
+fn parse_denoise_options<I>(mut args: I) -> std::result::Result<DenoiseOptions, CliError>
+where
+    I: Iterator<Item = String>,
+{
+    let mut input = None;
+    let mut output = None;
+    let mut guides: Vec<(Aov, String)> = Vec::new();
+    let mut denoiser = Denoiser::default();
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value)),
+            _ => (arg.clone(), None),
+        };
+        let option: &'static str = match flag.as_str() {
+            "-o" | "--output" => "--output",
+            "--albedo" => "--albedo",
+            "--normal" => "--normal",
+            "--depth" => "--depth",
+            "--emission" => "--emission",
+            "--iterations" => "--iterations",
+            "--color-sigma" => "--color-sigma",
+            "--normal-sigma" => "--normal-sigma",
+            "--depth-sigma" => "--depth-sigma",
+            _ if input.is_none() && !arg.starts_with('-') => {
+                input = Some(arg);
+                continue;
+            }
+            _ => return Err(CliError::UnknownOption(arg)),
+        };
+        let value = match inline_value {
+            Some(value) => value.to_string(),
+            None => args.next().ok_or(CliError::MissingValue(option))?,
+        };
+        match option {
+            "--albedo" | "--normal" | "--depth" | "--emission" => {
+                let aov: Aov = option[2..]
+                    .parse()
+                    .expect("guide options are named after passes");
+                guides.retain(|(guide, _)| *guide != aov);
+                guides.push((aov, value));
+            }
+            "--output" => output = Some(value),
+            _ => parse_denoiser_option(&mut denoiser, option, &value)?,
+        }
+    }
+    Ok(DenoiseOptions {
+        input: input.ok_or(CliError::MissingArgument("the image to denoise"))?,
+        output,
+        guides,
+        denoiser,
+    })
+}
+
+// the settings of the denoiser, shared by the denoise command and `render --denoise`
+fn parse_denoiser_option(
+    denoiser: &mut Denoiser,
+    option: &'static str,
+    value: &str,
+) -> std::result::Result<(), CliError> {
+    match option {
+        "--iterations" => denoiser.iterations = parse_positive(option, value)?,
+        "--color-sigma" => denoiser.color_sigma = parse_positive_number(option, value)?,
+        "--normal-sigma" => denoiser.normal_sigma = parse_positive_number(option, value)?,
+        _ => denoiser.depth_sigma = parse_positive_number(option, value)?,
+    }
+    Ok(())
+}
+
+// a built-in scene, or anything that looks like a path to a scene file
+fn parse_scene(value: String) -> std::result::Result<SceneSource, CliError> {
+    if let Some(entry) = sample_scenes::find_scene(&value) {
+        return Ok(SceneSource::BuiltIn(entry));
+    }
+    let path = Path::new(&value);
+    if path.extension().is_some_and(|ext| ext == "toml") || path.is_file() {
+        return Ok(SceneSource::File(path.to_path_buf()));
+    }
+    Err(CliError::UnknownScene(value))
+}
+
+fn parse_positive<T>(option: &'static str, value: &str) -> std::result::Result<T, CliError>
+where
+    T: std::str::FromStr + PartialOrd + Default,
+{
+    match value.parse::<T>() {
+        Ok(n) if n > T::default() => Ok(n),
+        _ => Err(CliError::InvalidValue {
+            option,
+            value: value.to_string(),
+            expected: "a positive integer",
+        }),
+    }
+}
+
+fn parse_positive_number(option: &'static str, value: &str) -> std::result::Result<f64, CliError> {
+    match value.parse::<f64>() {
+        Ok(x) if x.is_finite() && x > 0.0 => Ok(x),
+        _ => Err(CliError::InvalidValue {
+            option,
+            value: value.to_string(),
+            expected: "a positive number",
+        }),
+    }
+}
+
+// either a plain number like `1.5` or a ratio like `16:9`
+fn parse_aspect_ratio(value: &str) -> std::result::Result<f64, CliError> {
+    let ratio = match value.split_once(':') {
+        Some((w, h)) => match (w.parse::<f64>(), h.parse::<f64>()) {
+            (Ok(w), Ok(h)) => w / h,
+            _ => f64::NAN,
+        },
+        None => value.parse().unwrap_or(f64::NAN),
+    };
+    if ratio.is_finite() && ratio > 0.0 {
+        Ok(ratio)
+    } else {
+        Err(CliError::InvalidValue {
+            option: "--aspect-ratio",
+            value: value.to_string(),
+            expected: "a positive number like 1.5 or a ratio like 16:9",
+        })
+    }
+}
+
+// a comma separated list of passes, or `all`
+fn parse_aovs(value: &str) -> std::result::Result<Vec<Aov>, CliError> {
+    let names: Vec<&str> = match value {
+        "all" => AOV_NAMES.to_vec(),
+        _ => value.split(',').map(str::trim).collect(),
+    };
+    let mut aovs = Vec::new();
+    for name in names {
+        let aov = name.parse().map_err(|_| CliError::InvalidValue {
+            option: "--aov",
+            value: value.to_string(),
+            expected: "passes out of depth, normal, albedo, uv, object_id, material_id, emission",
+        })?;
+        if !aovs.contains(&aov) {
+            aovs.push(aov);
+        }
+    }
+    Ok(aovs)
+}
+
+// a range like `10-20`, or a single frame
+fn parse_frames(value: &str) -> std::result::Result<(u32, u32), CliError> {
+    let frames = match value.split_once('-') {
+        Some((first, last)) => first.parse().ok().zip(last.parse().ok()),
+        None => value.parse().ok().map(|frame| (frame, frame)),
+    };
+    match frames {
+        Some((first, last)) if first <= last => Ok((first, last)),
+        _ => Err(CliError::InvalidValue {
+            option: "--frames",
+            value: value.to_string(),
+            expected: "a frame number or a range like 0-47",
+        }),
+    }
+}
+
+pub fn usage() -> &'static str {
+    USAGE
+}
+
+// parse the arguments and run the requested command
+pub fn run<I>(args: I) -> Result<()>
+where
+    I: IntoIterator<Item = String>,
+{
+    match parse(args)? {
+        Command::Help => println!("{}", USAGE),
+        Command::ListScenes => {
+            for (i, entry) in SCENES.iter().enumerate() {
+                println!("{:>2}  {:<16}{}", i + 1, entry.name, entry.description);
+            }
+        }
+        Command::Info(options) => print_info(&options)?,
+        Command::Render(options) => render(&options)?,
+        Command::Denoise(options) => denoise(&options)?,
+    }
+    Ok(())
+}
+
+fn print_info(options: &RenderOptions) -> Result<()> {
+    let scene = options.build_scene()?;
+    let camera = &scene.camera;
+    let bbox = scene.world.bounding_box();
+    println!("scene:             {}", options.scene.name());
+    match options.scene {
+        SceneSource::BuiltIn(entry) => {
+            println!("description:       {}", entry.description);
+            println!("file:              {} (built in)", entry.file);
+        }
+        SceneSource::File(ref path) => println!("file:              {}", path.display()),
+    }
+    println!(
+        "image size:        {}x{}",
+        camera.image_width,
+        camera.image_height()
+    );
+    println!("aspect ratio:      {}", camera.aspect_ratio);
+    println!("samples per pixel: {}", camera.samples_per_pixel);
+    println!("max depth:         {}", camera.max_depth);
+    println!("sampler:           {}", camera.sampler);
+    println!("projection:        {}", camera.projection);
+    println!(
+        "shutter:           {}, open from {} to {}",
+        camera.shutter, camera.shutter_open, camera.shutter_close
+    );
+    if let Some(adaptive) = camera.adaptive {
+        println!(
+            "adaptive sampling: {} to {} samples, relative error {}",
+            adaptive.min_samples, adaptive.max_samples, adaptive.threshold
+        );
+    }
+    println!("vertical fov:      {}", camera.vfov);
+    println!("look from:         {}", camera.look_from);
+    println!("look at:           {}", camera.look_at);
+    println!("defocus angle:     {}", camera.defocus_angle);
+    println!("focus distance:    {}", camera.focus_dist);
+    println!("aperture:          {}", camera.aperture);
+    if camera.cat_eye > 0.0 {
+        println!("cat's eye:         {}", camera.cat_eye);
+    }
+    if camera.tilt != 0.0 {
+        println!(
+            "tilt:              {} degrees, axis rotated {} degrees",
+            camera.tilt, camera.tilt_rotation
+        );
+    }
+    println!("background:        {}", camera.background);
+    println!("tone mapping:      {}", camera.tone_mapping);
+    println!("effects:           {}", camera.effects);
+    println!("top-level objects: {}", scene.world.objects.len());
+    println!(
+        "world bounds:      x [{}, {}], y [{}, {}], z [{}, {}]",
+        bbox.x.min, bbox.x.max, bbox.y.min, bbox.y.max, bbox.z.min, bbox.z.max
+    );
+    for (label, stats) in &scene.bvh_reports {
+        println!("bvh ({}): {}", label, stats);
+    }
+    if let Some(animation) = scene.animation {
+        println!(
+            "animation:         {} fps, shutter angle {}",
+            animation.fps, animation.shutter_angle
+        );
+        if let Some((first, last)) = animation.frames {
+            println!("frames:            {} to {}", first, last);
+        }
+    }
+    if !camera.aovs.is_empty() {
+        let names: Vec<_> = camera.aovs.iter().map(Aov::name).collect();
+        println!("passes:            {}", names.join(", "));
+    }
+    if !camera.keyframes.is_empty() {
+        println!("camera keyframes:  {}", camera.keyframes.len());
+    }
+    println!("output:            {}", options.output_path());
+    Ok(())
+}
+
+fn render(options: &RenderOptions) -> Result<()> {
+    if let Some(threads) = options.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()?;
+    }
+    let output = options.output_path();
+    // check the format before spending time on rendering
+    let format = OutputFormat::from_path(&output, options.bit_depth)?;
+    let with_format = |path: &String| -> Result<(String, OutputFormat)> {
+        Ok((path.clone(), OutputFormat::from_path(path, 8)?))
+    };
+    let heatmap = options.heatmap.as_ref().map(with_format).transpose()?;
+    let variance_map = options.variance_map.as_ref().map(with_format).transpose()?;
+    if options.aov_layers && format != OutputFormat::Exr {
+        return Err("`--aov-layers` needs an .exr output".into());
+    }
+    if !options.denoise && options.denoiser != Denoiser::default() {
+        return Err("the denoiser settings need `--denoise`".into());
+    }
+    let scene = options.build_scene()?;
+    let start = Instant::now();
+    if let Some(animation) = scene
+        .animation
+        .filter(|animation| animation.frames.is_some())
+    {
+        if options.is_progressive() || heatmap.is_some() || variance_map.is_some() {
+            return Err("an animation cannot be rendered progressively or with sample maps".into());
+        }
+        return render_animation(options, scene, animation, &output, format, start);
+    }
+    if options.y4m.is_some() {
+        return Err("`--y4m` needs frames to render, from `--frames` or the scene".into());
+    }
+    let mut scene = scene;
+    let requested = with_guides(options, &mut scene.camera);
+    let framebuffer = if options.is_progressive() {
+        render_progressive(options, &mut scene, &output, format, start)?
+    } else {
+        scene.camera.render_to_buffer(&scene.world)
+    };
+    if let Some((ref path, format)) = heatmap {
+        write_output(path, format, &adaptive::sample_count_heatmap(&framebuffer))?;
+    }
+    if let Some((ref path, format)) = variance_map {
+        write_output(path, format, &adaptive::error_heatmap(&framebuffer))?;
+    }
+    let (image, aovs) = finish_image(options, &requested, framebuffer)?;
+    let camera = &scene.camera;
+    write_with_aovs(options, &output, format, &image, &aovs, camera)?;
+    eprintln!(
+        "rendered {} to {} in {:.1?}",
+        options.scene.name(),
+        output,
+        start.elapsed()
+    );
+    Ok(())
+}
+
+// render in passes, writing a snapshot of the image so far and a checkpoint after a pass
+// whenever the snapshot interval has passed
+fn render_progressive(
+    options: &RenderOptions,
+    scene: &mut Scene,
+    output: &str,
+    format: OutputFormat,
+    start: Instant,
+) -> Result<Framebuffer> {
+    let Scene {
+        world,
+        camera,
+        fingerprint,
+        ..
+    } = scene;
+    let fingerprint = *fingerprint;
+    let checkpoint_path = options.checkpoint_path();
+    let mut checkpoint = Checkpoint {
+        scene_fingerprint: fingerprint,
+        camera_fingerprint: camera.fingerprint(),
+        seed: camera.seed,
+        framebuffer: Framebuffer::with_aovs(
+            camera.image_width,
+            camera.image_height(),
+            &camera.aovs,
+        ),
+    };
+    if options.resume {
+        let path = checkpoint_path
+            .as_ref()
+            .expect("resuming needs a checkpoint");
+        let resumed = Checkpoint::load(path)
+            .map_err(|e| format!("cannot read checkpoint `{}`: {}", path.display(), e))?;
+        resumed
+            .check(fingerprint, camera.fingerprint(), camera.seed)
+            .map_err(|e| format!("cannot resume from `{}`: {}", path.display(), e))?;
+        checkpoint.framebuffer = resumed.framebuffer;
+        eprintln!(
+            "resuming from {} at {:.1} samples per pixel",
+            path.display(),
+            average_samples(&checkpoint.framebuffer)
+        );
+    }
+
+    let (effects, tone_mapping) = (camera.effects, camera.tone_mapping);
+    let save = |checkpoint: &Checkpoint| -> Result<()> {
+        write_display(
+            output,
+            format,
+            &checkpoint.framebuffer,
+            &effects,
+            &tone_mapping,
+        )?;
+        if let Some(ref path) = checkpoint_path {
+            checkpoint
+                .save(path)
+                .map_err(|e| format!("cannot write checkpoint `{}`: {}", path.display(), e))?;
+        }
+        Ok(())
+    };
+    let pass_samples = options.pass_samples.unwrap_or(4);
+    let interval = options.snapshot_interval.unwrap_or_default();
+    let spp = camera.samples_per_pixel;
+    let mut framebuffer = checkpoint.framebuffer.clone();
+    let mut last_snapshot = start;
+    camera.render_progressive(world, &mut framebuffer, pass_samples, |framebuffer| {
+        if last_snapshot.elapsed() >= interval {
+            checkpoint.framebuffer.clone_from(framebuffer);
+            save(&checkpoint)?;
+            last_snapshot = Instant::now();
+            eprintln!(
+                "{:.1}/{} samples per pixel after {:.1?}, wrote {}",
+                average_samples(framebuffer),
+                spp,
+                start.elapsed(),
+                output
+            );
+        }
+        Ok(())
+    })?;
+    // the final image is written by the caller, the final checkpoint allows adding samples later
+    if checkpoint_path.is_some() {
+        checkpoint.framebuffer = framebuffer.clone();
+        save(&checkpoint)?;
+    }
+    Ok(framebuffer)
+}
+
+// render every frame of an animation with the same scene, each with its own shutter interval
+fn render_animation(
+    options: &RenderOptions,
+    scene: Scene,
+    animation: Animation,
+    output: &str,
+    format: OutputFormat,
+    start: Instant,
+) -> Result<()> {
+    let Scene {
+        world, mut camera, ..
+    } = scene;
+    let (first, last) = animation.frames.expect("an animation needs frames");
+    let mut video = match options.y4m {
+        Some(ref path) => {
+            let file =
+                fs::File::create(path).map_err(|e| format!("cannot write `{}`: {}", path, e))?;
+            Some(Y4mWriter::new(
+                std::io::BufWriter::new(file),
+                camera.image_width,
+                camera.image_height(),
+                animation.fps,
+            )?)
+        }
+        None => None,
+    };
+    let seed = camera.seed;
+    let requested = with_guides(options, &mut camera);
+    for frame in first..=last {
+        (camera.shutter_open, camera.shutter_close) = animation.shutter(frame);
+        // a different seed per frame keeps the noise from standing still on the screen
+        camera.seed = seed.wrapping_add(frame as u64);
+        let framebuffer = camera.render_to_buffer(&world);
+        let (image, aovs) = finish_image(options, &requested, framebuffer)?;
+        let path = animation::frame_path(output, frame);
+        write_with_aovs(options, &path, format, &image, &aovs, &camera)?;
+        if let Some(ref mut video) = video {
+            video.write_frame(&display_image(
+                &image,
+                &camera.effects,
+                &camera.tone_mapping,
+            ))?;
+        }
+        eprintln!(
+            "frame {} of {}-{} to {} after {:.1?}",
+            frame,
+            first,
+            last,
+            path,
+            start.elapsed()
+        );
+    }
+    eprintln!(
+        "rendered {} frames of {} in {:.1?}",
+        last - first + 1,
+        options.scene.name(),
+        start.elapsed()
+    );
+    Ok(())
+}
+
+// add the passes the denoiser needs to the ones the camera renders, returning the passes that
+// were asked for
+fn with_guides(options: &RenderOptions, camera: &mut Camera) -> Vec<Aov> {
+    let requested = camera.aovs.clone();
+    if options.denoise {
+        for aov in GUIDE_AOVS {
+            if !camera.aovs.contains(&aov) {
+                camera.aovs.push(aov);
+            }
+        }
+    }
+    requested
+}
+
+// the image and the passes in `requested` of a finished render, denoised if asked to
+fn finish_image(
+    options: &RenderOptions,
+    requested: &[Aov],
+    framebuffer: Framebuffer,
+) -> Result<(Framebuffer, Vec<(Aov, Framebuffer)>)> {
+    let mut aovs = framebuffer.aov_images();
+    let image = if options.denoise {
+        options.denoiser.denoise(&framebuffer, &aovs)?
+    } else {
+        framebuffer
+    };
+    aovs.retain(|(aov, _)| requested.contains(aov));
+    Ok((image, aovs))
+}
+
+// denoise a saved image with the guide passes in it, given on the command line or next to it
+fn denoise(options: &DenoiseOptions) -> Result<()> {
+    let start = Instant::now();
+    let input = &options.input;
+    let output = options.output_path();
+    let format = OutputFormat::from_path(&output, 8)?;
+    let read = |path: &str| -> Result<Framebuffer> {
+        output::read_image(path).map_err(|e| format!("cannot read `{}`: {}", path, e).into())
+    };
+    let (image, mut aovs) = match OutputFormat::from_path(input, 8)? {
+        OutputFormat::Exr => {
+            output::read_exr_layers(input).map_err(|e| format!("cannot read `{}`: {}", input, e))?
+        }
+        _ => (read(input)?, Vec::new()),
+    };
+    // passes given on the command line replace the layers of the image
+    aovs.retain(|(aov, _)| {
+        GUIDE_AOVS.contains(aov) && !options.guides.iter().any(|(guide, _)| guide == aov)
+    });
+    for aov in GUIDE_AOVS {
+        let path = match options.guides.iter().find(|(guide, _)| *guide == aov) {
+            Some((_, path)) => path.clone(),
+            None if aovs.iter().any(|(layer, _)| *layer == aov) => continue,
+            None => {
+                let path = output::aov_path(input, aov);
+                if !Path::new(&path).is_file() {
+                    continue;
+                }
+                path
+            }
+        };
+        aovs.push((aov, read(&path)?));
+    }
+    aovs.sort_by_key(|(aov, _)| GUIDE_AOVS.iter().position(|guide| guide == aov));
+    let denoised = options.denoiser.denoise(&image, &aovs)?;
+    write_output(&output, format, &denoised)?;
+    let guides: Vec<_> = aovs.iter().map(|(aov, _)| aov.name()).collect();
+    let guides = if guides.is_empty() {
+        "the colors alone".to_string()
+    } else {
+        guides.join(", ")
+    };
+    eprintln!(
+        "denoised {} to {} guided by {} in {:.1?}",
+        input,
+        output,
+        guides,
+        start.elapsed()
+    );
+    Ok(())
+}
+
+// write the image, and its passes either as images of their own or as layers of the image
+fn write_with_aovs(
+    options: &RenderOptions,
+    output: &str,
+    format: OutputFormat,
+    framebuffer: &Framebuffer,
+    aovs: &[(Aov, Framebuffer)],
+    camera: &Camera,
+) -> Result<()> {
+    if options.aov_layers {
+        let partial = format!("{}.partial", output);
+        return output::write_exr_layers(&partial, framebuffer, aovs)
+            .and_then(|_| Ok(fs::rename(&partial, output)?))
+            .map_err(|e| format!("cannot write `{}`: {}", output, e).into());
+    }
+    write_display(
+        output,
+        format,
+        framebuffer,
+        &camera.effects,
+        &camera.tone_mapping,
+    )?;
+    for (aov, buffer) in aovs {
+        // the passes are data, not pictures, so they keep their values in a linear format
+        write_output(&output::aov_path(output, *aov), OutputFormat::Exr, buffer)?;
+    }
+    Ok(())
+}
+
+fn average_samples(framebuffer: &Framebuffer) -> f64 {
+    let pixels = framebuffer.width() as f64 * framebuffer.height() as f64;
+    framebuffer.total_samples() as f64 / pixels.max(1.0)
+}
+
+// write an image as it is shown: linear formats keep the radiance, the others get the lens
+// effects and are tone mapped
+fn write_display(
+    output: &str,
+    format: OutputFormat,
+    framebuffer: &Framebuffer,
+    effects: &Effects,
+    tone_mapping: &ToneMapping,
+) -> Result<()> {
+    if format.is_linear() || (effects.is_none() && tone_mapping.is_identity()) {
+        write_output(output, format, framebuffer)
+    } else {
+        write_output(
+            output,
+            format,
+            &display_image(framebuffer, effects, tone_mapping),
+        )
+    }
+}
+
+// the lens effects on the radiance, then tone mapping
+fn display_image(
+    framebuffer: &Framebuffer,
+    effects: &Effects,
+    tone_mapping: &ToneMapping,
+) -> Framebuffer {
+    tone_mapping.apply_to(&effects.apply(framebuffer))
+}
+
+// write through a temporary file so viewers never see a half-written image
+fn write_output(output: &str, format: OutputFormat, framebuffer: &Framebuffer) -> Result<()> {
+    let partial = format!("{}.partial", output);
+    output::write_image(&partial, format, framebuffer)
+        .and_then(|_| Ok(fs::rename(&partial, output)?))
+        .map_err(|e| format!("cannot write `{}`: {}", output, e).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn test_render_options() {
+        let command = parse(args(
+            "render --scene cornell_box -w 400 --spp=64 --seed 7 -o out.ppm --bit-depth 16 --sampler sobol",
+        ))
+        .unwrap();
+        if let Command::Render(options) = command {
+            assert_eq!(options.scene.name(), "cornell_box");
+            assert_eq!(options.image_width, Some(400));
+            assert_eq!(options.samples_per_pixel, Some(64));
+            assert_eq!(options.seed, 7);
+            assert_eq!(options.sampler, Some(SamplerKind::Sobol));
+            assert_eq!(options.max_depth, None);
+            assert_eq!(options.output_path(), "out.ppm");
+            assert_eq!(options.bit_depth, 16);
+        } else {
+            panic!("expected a render command");
+        }
+    }
+
+    #[test]
+    fn test_progressive_options() {
+        let command = parse(args("render --pass-spp 8 --snapshot-every=2.5")).unwrap();
+        if let Command::Render(options) = command {
+            assert_eq!(options.pass_samples, Some(8));
+            assert_eq!(options.snapshot_interval, Some(Duration::from_millis(2500)));
+            assert_eq!(options.checkpoint_path(), None);
+        } else {
+            panic!("expected a render command");
+        }
+    }
+
+    #[test]
+    fn test_adaptive_options() {
+        let scene = "--scene cornell_box --spp 64 --adaptive 0.05";
+        if let Command::Render(options) =
+            parse(args(&format!("render {} --max-spp 100", scene))).unwrap()
+        {
+            let camera = options.build_scene().unwrap().camera;
+            assert_eq!(
+                camera.adaptive,
+                Some(AdaptiveSampling {
+                    min_samples: 16,
+                    max_samples: 100,
+                    threshold: 0.05
+                })
+            );
+        } else {
+            panic!("expected a render command");
+        }
+        assert!(parse(args("render --adaptive 0")).is_err());
+    }
+
+    #[test]
+    fn test_checkpoint_options() {
+        if let Command::Render(options) = parse(args("render -o a.exr --resume")).unwrap() {
+            assert!(options.resume && options.is_progressive());
+            assert_eq!(
+                options.checkpoint_path(),
+                Some(PathBuf::from("a.exr.checkpoint"))
+            );
+        } else {
+            panic!("expected a render command");
+        }
+        if let Command::Render(options) = parse(args("render --checkpoint c.bin")).unwrap() {
+            assert!(!options.resume && options.is_progressive());
+            assert_eq!(options.checkpoint_path(), Some(PathBuf::from("c.bin")));
+        } else {
+            panic!("expected a render command");
+        }
+        assert!(parse(args("render --resume=yes")).is_err());
+    }
+
+    #[test]
+    fn test_animation_options() {
+        let options = "render --frames 10-20 --fps 30 --shutter-angle 90 --y4m a.y4m";
+        if let Command::Render(options) = parse(args(options)).unwrap() {
+            assert_eq!(options.frames, Some((10, 20)));
+            assert_eq!(options.fps, Some(30.0));
+            assert_eq!(options.shutter_angle, Some(90.0));
+            assert_eq!(options.y4m.as_deref(), Some("a.y4m"));
+            let animation = options.build_scene().unwrap().animation.unwrap();
+            assert_eq!(animation.shutter(15), (0.5, 0.5 + 1.0 / 120.0));
+        } else {
+            panic!("expected a render command");
+        }
+        if let Command::Render(options) = parse(args("render --frames=7")).unwrap() {
+            assert_eq!(options.frames, Some((7, 7)));
+        } else {
+            panic!("expected a render command");
+        }
+        assert!(parse(args("render --frames 9-3")).is_err());
+        assert!(parse(args("render --frames a-b")).is_err());
+        assert!(parse(args("render --fps 0")).is_err());
+        assert!(parse(args("render --shutter-angle 400")).is_err());
+    }
+
+    #[test]
+    fn test_aov_options() {
+        if let Command::Render(options) = parse(args("render --aov depth,normal,depth")).unwrap() {
+            assert_eq!(options.aovs, Some(vec![Aov::Depth, Aov::Normal]));
+            assert!(!options.aov_layers);
+        } else {
+            panic!("expected a render command");
+        }
+        if let Command::Render(options) = parse(args("render --aov all --aov-layers")).unwrap() {
+            assert_eq!(options.aovs.unwrap().len(), AOV_NAMES.len());
+            assert!(options.aov_layers);
+        } else {
+            panic!("expected a render command");
+        }
+        assert!(parse(args("render --aov depth,position")).is_err());
+    }
+
+    #[test]
+    fn test_aov_files() {
+        let dir = std::env::temp_dir().join(format!("cli_aovs_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("box.png").display().to_string();
+        let command = format!(
+            "render --scene cornell_box -w 8 --spp 1 --aov depth -o {}",
+            output
+        );
+        let Command::Render(options) = parse(args(&command)).unwrap() else {
+            panic!("expected a render command");
+        };
+        render(&options).unwrap();
+        // the depth next to a png keeps distances beyond 1 in a linear image
+        let depth = image::open(dir.join("box_depth.exr"));
+        fs::remove_dir_all(&dir).unwrap();
+        let depth = depth.unwrap().to_rgb32f().get_pixel(4, 4).0[0] as f64;
+        assert!(depth > 1.0 && depth < f64::MAX, "{}", depth);
+    }
+
+    #[test]
+    fn test_tone_mapping_options() {
+        let command = "render --exposure=-1.5 --white-balance 3200 --tone-map agx";
+        if let Command::Render(options) = parse(args(command)).unwrap() {
+            assert_eq!(options.exposure, Some(-1.5));
+            assert_eq!(options.white_balance, Some(3200.0));
+            assert_eq!(options.tone_map, Some(ToneMap::Agx));
+        } else {
+            panic!("expected a render command");
+        }
+        assert!(parse(args("render --exposure bright")).is_err());
+        assert!(parse(args("render --white-balance 500")).is_err());
+        assert!(parse(args("render --tone-map filmic")).is_err());
+    }
+
+    #[test]
+    fn test_effect_options() {
+        let command = "render --bloom 0.2 --star-glare 8 --glare-threshold=2 --vignetting 30";
+        if let Command::Render(options) = parse(args(command)).unwrap() {
+            let camera = options.build_scene().unwrap().camera;
+            let bloom = camera.effects.bloom.unwrap();
+            assert_eq!((bloom.strength, bloom.threshold), (0.2, 2.0));
+            let star_glare = camera.effects.star_glare.unwrap();
+            assert_eq!((star_glare.spokes, star_glare.threshold), (8, 2.0));
+            assert_eq!(camera.effects.vignetting.unwrap().corner_angle, 30.0);
+        } else {
+            panic!("expected a render command");
+        }
+        assert!(parse(args("render --bloom 2")).is_err());
+        assert!(parse(args("render --star-glare 0")).is_err());
+        assert!(parse(args("render --vignetting 90")).is_err());
+        // the threshold only applies to bloom and star glare
+        if let Command::Render(options) = parse(args("render --glare-threshold 2")).unwrap() {
+            assert!(options.build_scene().is_err());
+        }
+    }
+
+    #[test]
+    fn test_effects_only_on_display_formats() {
+        let dir = std::env::temp_dir().join(format!("cli_effects_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut framebuffer = Framebuffer::new(8, 8);
+        framebuffer.map_pixels(|_| raytracing_2_rs::Color::new(0.5, 0.5, 0.5));
+        let effects = Effects {
+            vignetting: Some(Vignetting { corner_angle: 60.0 }),
+            ..Effects::default()
+        };
+        let tone_mapping = ToneMapping::default();
+        let write = |name: &str| {
+            let path = dir.join(name).display().to_string();
+            let format = OutputFormat::from_path(&path, 8).unwrap();
+            write_display(&path, format, &framebuffer, &effects, &tone_mapping).unwrap();
+            path
+        };
+        // the linear image keeps the radiance, the corners of the png are darkened
+        let pfm = output::read_image(write("t.pfm")).unwrap();
+        assert_eq!(pfm.pixel(0, 0), framebuffer.pixel(0, 0));
+        let png = image::open(write("t.png")).unwrap().to_rgb8();
+        assert!(png.get_pixel(0, 0).0[0] < png.get_pixel(4, 4).0[0]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_environment_options() {
+        let command = "render --environment sky.hdr --environment-rotation=-90";
+        if let Command::Render(options) = parse(args(command)).unwrap() {
+            assert_eq!(options.environment.as_deref(), Some("sky.hdr"));
+            assert_eq!(options.environment_rotation, Some(-90.0));
+        } else {
+            panic!("expected a render command");
+        }
+        assert!(parse(args("render --environment-rotation west")).is_err());
+        // only an environment map can be rotated
+        if let Command::Render(options) = parse(args("render --environment-rotation 10")).unwrap() {
+            assert!(options.build_scene().is_err());
+        }
+    }
+
+    #[test]
+    fn test_denoise_options() {
+        let command =
+            "denoise box.exr --normal n.pfm --iterations 3 --color-sigma=2 --normal m.pfm";
+        if let Command::Denoise(options) = parse(args(command)).unwrap() {
+            assert_eq!(options.input, "box.exr");
+            assert_eq!(options.output_path(), "box_denoised.exr");
+            assert_eq!(options.guides, [(Aov::Normal, "m.pfm".to_string())]);
+            assert_eq!(options.denoiser.iterations, 3);
+            assert_eq!(options.denoiser.color_sigma, 2.0);
+        } else {
+            panic!("expected a denoise command");
+        }
+        let command = "render --denoise --iterations=2 --depth-sigma 0.1";
+        if let Command::Render(options) = parse(args(command)).unwrap() {
+            assert!(options.denoise);
+            assert_eq!(options.denoiser.iterations, 2);
+            assert_eq!(options.denoiser.depth_sigma, 0.1);
+        } else {
+            panic!("expected a render command");
+        }
+        assert_eq!(
+            parse(args("denoise -o out.png")).unwrap_err(),
+            CliError::MissingArgument("the image to denoise")
+        );
+        assert!(parse(args("denoise a.exr b.exr")).is_err());
+        assert!(parse(args("denoise a.exr --depth-sigma 0")).is_err());
+    }
+
+    #[test]
+    fn test_scene_by_number() {
+        if let Command::Info(options) = parse(args("info --scene 9")).unwrap() {
+            assert_eq!(options.scene.name(), "final_scene");
+            assert_eq!(options.output_path(), "images/final_scene.png");
+        } else {
+            panic!("expected an info command");
+        }
+    }
+
+    #[test]
+    fn test_scene_file() {
+        if let Command::Render(options) = parse(args("render -s my/room.toml")).unwrap() {
+            assert!(matches!(options.scene, SceneSource::File(_)));
+            assert_eq!(options.output_path(), "images/room.png");
+        } else {
+            panic!("expected a render command");
+        }
+    }
+
+    #[test]
+    fn test_aspect_ratio() {
+        assert_eq!(parse_aspect_ratio("16:9"), Ok(16.0 / 9.0));
+        assert_eq!(parse_aspect_ratio("1.5"), Ok(1.5));
+        assert!(parse_aspect_ratio("16:0").is_err());
+        assert!(parse_aspect_ratio("wide").is_err());
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(parse(args("")).unwrap_err(), CliError::MissingCommand);
+        assert_eq!(
+            parse(args("draw")).unwrap_err(),
+            CliError::UnknownCommand("draw".to_string())
+        );
+        assert_eq!(
+            parse(args("render --width")).unwrap_err(),
+            CliError::MissingValue("--width")
+        );
+        assert_eq!(
+            parse(args("render --width 0")).unwrap_err(),
+            CliError::InvalidValue {
+                option: "--width",
+                value: "0".to_string(),
+                expected: "a positive integer",
+            }
+        );
+        assert_eq!(
+            parse(args("render --scene nowhere")).unwrap_err(),
+            CliError::UnknownScene("nowhere".to_string())
+        );
+        assert_eq!(
+            parse(args("render --fast")).unwrap_err(),
+            CliError::UnknownOption("--fast".to_string())
+        );
+        assert!(parse(args("render --snapshot-every soon")).is_err());
+    }
+}
@@ -9,25 +9,35 @@ const COLOR_INTERVAL: Interval = Interval {
     max: 0.9999,
 };
 
-fn linear_to_gamma(linear_component: f64) -> f64 {
-    linear_component.sqrt()
+// relative luminance of a linear Rec. 709 color
+pub fn luminance(c: &Color) -> f64 {
+    0.2126 * c.x() + 0.7152 * c.y() + 0.0722 * c.z()
+}
+
+// the sRGB transfer function (IEC 61966-2-1) that encodes linear light for display
+pub fn linear_to_srgb(linear_component: f64) -> f64 {
+    if linear_component <= 0.0031308 {
+        12.92 * linear_component
+    } else {
+        1.055 * linear_component.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// sRGB encoded 8 bit components of a linear color
+pub fn to_rgb8(c: &Color) -> [u8; 3] {
+    [c.x(), c.y(), c.z()].map(|x| (COLOR_INTERVAL.clamp(linear_to_srgb(x)) * 256.0) as u8)
+}
+
+// sRGB encoded 16 bit components of a linear color
+pub fn to_rgb16(c: &Color) -> [u16; 3] {
+    [c.x(), c.y(), c.z()].map(|x| (linear_to_srgb(x).clamp(0.0, 1.0) * 65535.0).round() as u16)
 }
 
 pub fn write_color<W>(writer: &mut W, c: &Color) -> Result<()>
 where
     W: Write,
 {
-    // apply linear to gamma transform
-    let r = linear_to_gamma(c.x());
-    let g = linear_to_gamma(c.y());
-    let b = linear_to_gamma(c.z());
-
-    write!(
-        writer,
-        "{} {} {}\n",
-        (COLOR_INTERVAL.clamp(r) * 256.0) as u32,
-        (COLOR_INTERVAL.clamp(g) * 256.0) as u32,
-        (COLOR_INTERVAL.clamp(b) * 256.0) as u32
-    )?;
+    let [r, g, b] = to_rgb8(c);
+    writeln!(writer, "{} {} {}", r, g, b)?;
     Ok(())
 }
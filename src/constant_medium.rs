@@ -1,10 +1,10 @@
 use crate::interval;
 use crate::material::Isotropic;
 use crate::traits::Texture;
-use crate::utils::{random_f64, INFINITY};
+use crate::utils::INFINITY;
 use crate::Color;
 use crate::Interval;
-use crate::{HitRecord, Hittable, Material};
+use crate::{HitRecord, Hittable, Material, Sampler};
 use std::sync::Arc;
 
 pub struct ConstantMedium {
@@ -38,15 +38,16 @@ impl Hittable for ConstantMedium {
         &self,
         ray: &crate::ray::Ray,
         ray_t: &mut crate::interval::Interval,
+        sampler: &mut dyn Sampler,
     ) -> Option<HitRecord> {
         //print occasional samples when debugging. To enable, set it to true
         const ENABLE_DEBUG: bool = false;
-        let debugging = ENABLE_DEBUG && random_f64() < 1e-5;
+        let debugging = ENABLE_DEBUG && sampler.get_1d() < 1e-5;
 
         let mut interval_1 = interval::UNIVERSE_INTERVAL;
-        if let Some(mut rec1) = self.boundary.hit(ray, &mut interval_1) {
+        if let Some(mut rec1) = self.boundary.hit(ray, &mut interval_1, sampler) {
             let mut interval_2 = Interval::new(rec1.t + 0.0001, INFINITY);
-            if let Some(mut rec2) = self.boundary.hit(ray, &mut interval_2) {
+            if let Some(mut rec2) = self.boundary.hit(ray, &mut interval_2, sampler) {
                 if debugging {
                     println!("\nray_tmin = {}, ray_tmax = {} ", rec1.t, rec2.t);
                 }
@@ -59,7 +60,7 @@ impl Hittable for ConstantMedium {
                 }
                 let ray_length = ray.dir.length();
                 let distance_inside_boundary = (rec2.t - rec1.t) * ray_length;
-                let hit_distance = self.neg_inv_density * random_f64().log(1.0_f64.exp());
+                let hit_distance = self.neg_inv_density * sampler.get_1d().log(1.0_f64.exp());
                 if hit_distance > distance_inside_boundary {
                     return None;
                 }
@@ -72,10 +73,10 @@ impl Hittable for ConstantMedium {
 
                 Some(new_hitrecord)
             } else {
-                return None;
+                None
             }
         } else {
-            return None;
+            None
         }
     }
     fn bounding_box(&self) -> crate::aabb::AABB {
@@ -0,0 +1,291 @@
+// A denoiser for renders at low sample counts, the edge-avoiding à-trous wavelet filter of
+// "Edge-Avoiding À-Trous Wavelet Transform for fast Global Illumination Filtering" (Dammertz et
+// al. 2010) with the variance guided color weights of SVGF (Schied et al. 2017).
+//
+// Every iteration blurs the image with a 5x5 B3 spline kernel whose taps are spread twice as far
+// apart as in the iteration before, so a few iterations cover a wide area. A neighbor counts less
+// the more its normal and depth differ from the pixel's, which stops the blur at the edges the guide
+// passes show, and the more its brightness differs by more than the noise around the pixel, which
+// keeps lights and shadows sharp. The noise is estimated from the pixel's neighborhood and
+// filtered along with the image. The image is divided by the albedo before filtering and multiplied
+// with it again afterwards, so only the lighting is blurred and textures stay sharp, and the light
+// seen directly on emitters is left out of the filter, since it has no noise.
+use crate::aov::Aov;
+use crate::color::luminance;
+use crate::framebuffer::Framebuffer;
+use crate::{Color, Result};
+use rayon::prelude::*;
+
+// the passes the denoiser is guided by, any of them may be missing
+pub const GUIDE_AOVS: [Aov; 4] = [Aov::Albedo, Aov::Normal, Aov::Depth, Aov::Emission];
+
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+// albedo below this is too dark to divide by
+const MIN_ALBEDO: f64 = 0.01;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Denoiser {
+    pub iterations: u32, // filter passes, the last one reaches 2^(iterations + 1) pixels away
+    pub color_sigma: f64, // standard deviations of the noise a brightness difference may be
+    pub normal_sigma: f64, // normal difference that still blends
+    pub depth_sigma: f64, // relative depth difference per pixel of distance that still blends
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self {
+            iterations: 5,
+            color_sigma: 4.0,
+            normal_sigma: 0.3,
+            depth_sigma: 0.02,
+        }
+    }
+}
+
+// the guide passes of every pixel, row by row from the top left
+struct Guides {
+    width: usize,
+    height: usize,
+    normal: Option<Vec<Color>>,
+    depth: Option<Vec<f64>>,
+}
+
+impl Denoiser {
+    // the denoised image of `framebuffer`, guided by the albedo, normal, depth and emission passes
+    // among `aovs`. Without any of them only the brightness differences stop the blur
+    pub fn denoise(
+        &self,
+        framebuffer: &Framebuffer,
+        aovs: &[(Aov, Framebuffer)],
+    ) -> Result<Framebuffer> {
+        let (width, height) = (framebuffer.width(), framebuffer.height());
+        let guide = |guide: Aov| -> Result<Option<Vec<Color>>> {
+            match aovs.iter().find(|(aov, _)| *aov == guide) {
+                Some((aov, buffer)) if (buffer.width(), buffer.height()) != (width, height) => {
+                    Err(format!("the {} pass does not match the image size", aov).into())
+                }
+                Some((_, buffer)) => Ok(Some(buffer.pixels())),
+                None => Ok(None),
+            }
+        };
+        let albedo = guide(Aov::Albedo)?;
+        let emission = guide(Aov::Emission)?;
+        let guides = Guides {
+            width: width as usize,
+            height: height as usize,
+            normal: guide(Aov::Normal)?,
+            depth: guide(Aov::Depth)?.map(|depth| depth.iter().map(Color::x).collect()),
+        };
+
+        let mut image = framebuffer.pixels();
+        if let Some(ref emission) = emission {
+            image.iter_mut().zip(emission).for_each(|(c, e)| *c -= e);
+        }
+        if let Some(ref albedo) = albedo {
+            for (color, albedo) in image.iter_mut().zip(albedo) {
+                *color = map_channels(color, albedo, |c, a| c / a);
+            }
+        }
+        let mut variance = self.estimate_variance(&image, &guides);
+        for i in 0..self.iterations.min(30) {
+            (image, variance) = self.filter(&image, &variance, &guides, 1 << i);
+        }
+        if let Some(ref albedo) = albedo {
+            for (color, albedo) in image.iter_mut().zip(albedo) {
+                *color = map_channels(color, albedo, |c, a| c * a);
+            }
+        }
+        if let Some(ref emission) = emission {
+            image.iter_mut().zip(emission).for_each(|(c, e)| *c += e);
+        }
+
+        let mut denoised = Framebuffer::new(width, height);
+        for (index, color) in image.into_iter().enumerate() {
+            let (i, j) = (index as u32 % width, index as u32 / width);
+            denoised.set_pixel(i, j, color);
+        }
+        Ok(denoised)
+    }
+
+    // the variance of the brightness of every pixel, from the 3x3 pixels around it on the same
+    // surface
+    fn estimate_variance(&self, image: &[Color], guides: &Guides) -> Vec<f64> {
+        let (width, height) = (guides.width, guides.height);
+        let brightness: Vec<f64> = image.iter().map(luminance).collect();
+        (0..width * height)
+            .into_par_iter()
+            .map(|p| {
+                let (x, y) = (p % width, p / width);
+                let (mut sum, mut sum_sq, mut total) = (0.0, 0.0, 0.0);
+                for qy in y.saturating_sub(1)..(y + 2).min(height) {
+                    for qx in x.saturating_sub(1)..(x + 2).min(width) {
+                        let q = qy * width + qx;
+                        let distance = (qx.abs_diff(x) + qy.abs_diff(y)) as f64;
+                        let weight = self.guide_weight(guides, p, q, distance.sqrt());
+                        sum += weight * brightness[q];
+                        sum_sq += weight * brightness[q] * brightness[q];
+                        total += weight;
+                    }
+                }
+                let mean = sum / total;
+                (sum_sq / total - mean * mean).max(0.0)
+            })
+            .collect()
+    }
+
+    // one pass of the filter with taps `step` pixels apart, returning the filtered image and its
+    // variance
+    fn filter(
+        &self,
+        image: &[Color],
+        variance: &[f64],
+        guides: &Guides,
+        step: usize,
+    ) -> (Vec<Color>, Vec<f64>) {
+        let (width, height) = (guides.width, guides.height);
+        let brightness: Vec<f64> = image.iter().map(luminance).collect();
+        (0..width * height)
+            .into_par_iter()
+            .map(|p| {
+                let (x, y) = (p % width, p / width);
+                let mut sum = Color::default();
+                let (mut total, mut sum_variance) = (0.0, 0.0);
+                for (ky, wy) in KERNEL.iter().enumerate() {
+                    let dy = (ky as isize - 2) * step as isize;
+                    let Some(qy) = y.checked_add_signed(dy).filter(|&qy| qy < height) else {
+                        continue;
+                    };
+                    for (kx, wx) in KERNEL.iter().enumerate() {
+                        let dx = (kx as isize - 2) * step as isize;
+                        let Some(qx) = x.checked_add_signed(dx).filter(|&qx| qx < width) else {
+                            continue;
+                        };
+                        let q = qy * width + qx;
+                        let distance = ((dx * dx + dy * dy) as f64).sqrt();
+                        let deviation = variance[p].sqrt();
+                        let color = (brightness[p] - brightness[q]).abs()
+                            / (self.color_sigma * deviation + 1e-6);
+                        let weight =
+                            wx * wy * (-color).exp() * self.guide_weight(guides, p, q, distance);
+                        sum += &(weight * &image[q]);
+                        sum_variance += weight * weight * variance[q];
+                        total += weight;
+                    }
+                }
+                // the pixel itself always has a weight
+                (sum / total, sum_variance / (total * total))
+            })
+            .unzip()
+    }
+
+    // how much pixel q may blend into pixel p by their normals and depths
+    fn guide_weight(&self, guides: &Guides, p: usize, q: usize, distance: f64) -> f64 {
+        let mut weight = 1.0;
+        if let Some(ref normal) = guides.normal {
+            let difference = (&normal[p] - &normal[q]).length_squared();
+            weight *= (-difference / (self.normal_sigma * self.normal_sigma)).exp();
+        }
+        if let Some(ref depth) = guides.depth {
+            let (a, b) = (depth[p], depth[q]);
+            if a.is_infinite() || b.is_infinite() {
+                // the background only blends with the background
+                if a != b {
+                    return 0.0;
+                }
+            } else if distance > 0.0 {
+                let difference = (a - b).abs() / (a.max(b) * distance).max(1e-9);
+                weight *= (-difference / self.depth_sigma).exp();
+            }
+        }
+        weight
+    }
+}
+
+// apply `f` to the channels of `color` with the albedo channels where they are bright enough
+fn map_channels<F>(color: &Color, albedo: &Color, f: F) -> Color
+where
+    F: Fn(f64, f64) -> f64,
+{
+    let channel = |c: f64, a: f64| if a > MIN_ALBEDO { f(c, a) } else { c };
+    Color::new(
+        channel(color.x(), albedo.x()),
+        channel(color.y(), albedo.y()),
+        channel(color.z(), albedo.z()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::RandomSampler;
+    use crate::Sampler;
+
+    // a gray left half and a white right half with noise, and guides that show the edge
+    fn noisy_edge() -> (Framebuffer, Vec<(Aov, Framebuffer)>) {
+        let (width, height) = (32, 16);
+        let mut sampler = RandomSampler::new(1);
+        let mut image = Framebuffer::new(width, height);
+        let mut normal = Framebuffer::new(width, height);
+        let mut depth = Framebuffer::new(width, height);
+        for j in 0..height {
+            for i in 0..width {
+                let (value, n, d) = if i < width / 2 {
+                    (0.2, Color::new(1.0, 0.0, 0.0), 2.0)
+                } else {
+                    (1.0, Color::new(0.0, 0.0, 1.0), 5.0)
+                };
+                let noise = 2.0 * sampler.get_1d();
+                image.set_pixel(i, j, Color::new(1.0, 1.0, 1.0) * value * noise);
+                normal.set_pixel(i, j, n);
+                depth.set_pixel(i, j, Color::new(d, d, d));
+            }
+        }
+        (image, vec![(Aov::Normal, normal), (Aov::Depth, depth)])
+    }
+
+    fn mean_error(image: &Framebuffer, left: f64, right: f64) -> f64 {
+        let mut error = 0.0;
+        for j in 0..image.height() {
+            for i in 0..image.width() {
+                let expected = if i < image.width() / 2 { left } else { right };
+                error += (image.pixel(i, j).y() - expected).abs();
+            }
+        }
+        error / (image.width() * image.height()) as f64
+    }
+
+    #[test]
+    fn test_denoise() {
+        let (image, guides) = noisy_edge();
+        let denoiser = Denoiser::default();
+        let denoised = denoiser.denoise(&image, &guides).unwrap();
+        assert!(mean_error(&denoised, 0.2, 1.0) * 4.0 < mean_error(&image, 0.2, 1.0));
+        // the guides keep the halves apart right at the edge
+        let (left, right) = (denoised.pixel(15, 8).y(), denoised.pixel(16, 8).y());
+        assert!(
+            (left - 0.2).abs() < 0.1 && (right - 1.0).abs() < 0.2,
+            "{} {}",
+            left,
+            right
+        );
+
+        // the albedo and the light seen directly are kept out of the blur
+        let mut albedo = Framebuffer::new(32, 16);
+        albedo.set_pixel(3, 3, Color::new(0.5, 0.5, 0.5));
+        let mut emission = Framebuffer::new(32, 16);
+        emission.set_pixel(5, 5, Color::new(10.0, 10.0, 10.0));
+        let mut flat = Framebuffer::new(32, 16);
+        flat.map_pixels(|_| Color::new(1.0, 1.0, 1.0));
+        flat.set_pixel(3, 3, Color::new(0.5, 0.5, 0.5));
+        flat.set_pixel(5, 5, Color::new(11.0, 11.0, 11.0));
+        let guides = [(Aov::Albedo, albedo), (Aov::Emission, emission)];
+        let denoised = denoiser.denoise(&flat, &guides).unwrap();
+        assert!((denoised.pixel(3, 3).y() - 0.5).abs() < 1e-9);
+        assert!((denoised.pixel(5, 5).y() - 11.0).abs() < 1e-9);
+        assert!((denoised.pixel(6, 5).y() - 1.0).abs() < 1e-9);
+
+        let too_small = [(Aov::Normal, Framebuffer::new(1, 1))];
+        assert!(denoiser.denoise(&image, &too_small).is_err());
+    }
+}
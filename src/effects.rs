@@ -0,0 +1,430 @@
+// Lens effects on the linear radiance of a render, before it is tone mapped for a display.
+//
+// Real lenses scatter a little of the light that passes through them: bloom is the soft glow
+// around bright lights from scattering in the glass, star glare the rays diffraction at the
+// aperture blades draws around them, and vignetting the falloff towards the corners of the frame
+// with the cosine to the fourth power of the angle to the optical axis. Bloom and star glare only
+// spread the light above a threshold and take it from where it came from, so they move light
+// around without adding any. Bloom sums Gaussian blurs of ever coarser copies of the bright light,
+// star glare smears it along every ray with an exponential falloff in a few passes whose taps are
+// four times as far apart as in the pass before.
+use crate::color::luminance;
+use crate::framebuffer::Framebuffer;
+use crate::utils::{degrees_to_radians, PI};
+use crate::Color;
+use rayon::prelude::*;
+use std::fmt;
+
+// the Gaussian every level of the bloom is blurred with, in pixels of that level
+const BLOOM_SIGMA: f64 = 1.5;
+
+// the star glare fades to this much of the light at its full length
+const STAR_FADE: f64 = 0.01;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bloom {
+    pub threshold: f64, // luminance above which light blooms
+    pub strength: f64,  // fraction of the light above the threshold that is spread out, up to 1
+    pub radius: f64,    // width of the widest blur, as a fraction of the image width
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Self {
+            threshold: 1.0,
+            strength: 0.1,
+            radius: 0.05,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StarGlare {
+    pub threshold: f64, // luminance above which light draws rays
+    pub strength: f64, // fraction of the light above the threshold that goes into the rays, up to 1
+    pub spokes: u32,   // rays around every bright point
+    pub length: f64,   // how far the rays reach, as a fraction of the image width
+    pub rotation: f64, // degrees counterclockwise from the right to the first ray
+}
+
+impl Default for StarGlare {
+    fn default() -> Self {
+        Self {
+            threshold: 1.0,
+            strength: 0.05,
+            spokes: 6,
+            length: 0.1,
+            rotation: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vignetting {
+    pub corner_angle: f64, // degrees between the optical axis and the corners of the image
+}
+
+impl Default for Vignetting {
+    fn default() -> Self {
+        Self { corner_angle: 45.0 }
+    }
+}
+
+// the effects a render goes through, applied in the order vignetting, bloom, star glare
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Effects {
+    pub bloom: Option<Bloom>,
+    pub star_glare: Option<StarGlare>,
+    pub vignetting: Option<Vignetting>,
+}
+
+impl Effects {
+    pub fn is_none(&self) -> bool {
+        *self == Self::default()
+    }
+
+    // a copy of `framebuffer` with the effects applied
+    pub fn apply(&self, framebuffer: &Framebuffer) -> Framebuffer {
+        if self.is_none() {
+            return framebuffer.clone();
+        }
+        let mut image = Image::from_framebuffer(framebuffer);
+        if let Some(vignetting) = self.vignetting {
+            image = vignetting.apply(&image);
+        }
+        let mut result = image.clone();
+        if let Some(bloom) = self.bloom {
+            result.add(&bloom.glow(&image), 1.0);
+        }
+        if let Some(star_glare) = self.star_glare {
+            result.add(&star_glare.glow(&image), 1.0);
+        }
+        result.to_framebuffer()
+    }
+}
+
+impl fmt::Display for Effects {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut effects = Vec::new();
+        if let Some(ref v) = self.vignetting {
+            effects.push(format!("vignetting ({}° corners)", v.corner_angle));
+        }
+        if let Some(ref b) = self.bloom {
+            effects.push(format!(
+                "bloom (threshold {}, strength {}, radius {})",
+                b.threshold, b.strength, b.radius
+            ));
+        }
+        if let Some(ref s) = self.star_glare {
+            effects.push(format!(
+                "star glare ({} spokes, threshold {}, strength {}, length {}, rotation {}°)",
+                s.spokes, s.threshold, s.strength, s.length, s.rotation
+            ));
+        }
+        if effects.is_empty() {
+            f.write_str("none")
+        } else {
+            f.write_str(&effects.join(", "))
+        }
+    }
+}
+
+impl Vignetting {
+    fn apply(&self, image: &Image) -> Image {
+        let (width, height) = (image.width as f64, image.height as f64);
+        let half_diagonal = 0.5 * (width * width + height * height).sqrt();
+        let tan_corner = degrees_to_radians(self.corner_angle).tan();
+        image.map(|x, y, c| {
+            let (dx, dy) = (x as f64 + 0.5 - 0.5 * width, y as f64 + 0.5 - 0.5 * height);
+            let tan = (dx * dx + dy * dy).sqrt() / half_diagonal * tan_corner;
+            // cos^4 of the angle, from its tangent
+            c / (1.0 + tan * tan).powi(2)
+        })
+    }
+}
+
+impl Bloom {
+    // the light the bloom adds to every pixel, negative where it takes the light from
+    fn glow(&self, image: &Image) -> Image {
+        let bright = image.above(self.threshold);
+        // every level has half the resolution of the one before, so its blur reaches twice as far
+        let radius = self.radius * image.width as f64;
+        let levels = (radius / BLOOM_SIGMA).log2().ceil().clamp(1.0, 16.0) as u32;
+        let mut glow = Image::new(image.width, image.height);
+        let mut level = bright.clone();
+        for _ in 0..levels {
+            level = level.downsample().blur(BLOOM_SIGMA);
+            glow.add(
+                &level.resize(image.width, image.height),
+                1.0 / levels as f64,
+            );
+        }
+        glow.add(&bright, -1.0);
+        glow.scale(self.strength);
+        glow
+    }
+}
+
+impl StarGlare {
+    // the light the rays add to every pixel, negative where they take the light from
+    fn glow(&self, image: &Image) -> Image {
+        let bright = image.above(self.threshold);
+        let length = (self.length * image.width as f64).max(1.0);
+        let decay = STAR_FADE.powf(1.0 / length);
+        // pass p sums 4 taps 4^p pixels apart, so the passes together sum every pixel of the ray
+        let passes = length.log(4.0).ceil().clamp(1.0, 8.0) as u32;
+        let taps = 4_i32.pow(passes);
+        let normalization = (1.0 - decay) / (1.0 - decay.powi(taps));
+        let mut glow = Image::new(image.width, image.height);
+        for spoke in 0..self.spokes {
+            let angle =
+                degrees_to_radians(self.rotation) + 2.0 * PI * spoke as f64 / self.spokes as f64;
+            // rows go down the image
+            let (dx, dy) = (angle.cos(), -angle.sin());
+            let mut ray = bright.clone();
+            for pass in 0..passes {
+                let step = 4_i32.pow(pass);
+                ray = ray.map(|x, y, _| {
+                    let mut sum = Color::default();
+                    for tap in 0..4 {
+                        let distance = (tap * step) as f64;
+                        let (sx, sy) = (x as f64 - distance * dx, y as f64 - distance * dy);
+                        sum += &(decay.powi(tap * step) * &ray.sample(sx, sy));
+                    }
+                    sum
+                });
+            }
+            glow.add(&ray, normalization / self.spokes as f64);
+        }
+        glow.add(&bright, -1.0);
+        glow.scale(self.strength);
+        glow
+    }
+}
+
+// the average radiance of every pixel, row by row from the top left
+#[derive(Clone)]
+struct Image {
+    width: u32,
+    height: u32,
+    pixels: Vec<Color>,
+}
+
+impl Image {
+    fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Color::default(); (width * height) as usize],
+        }
+    }
+
+    fn from_framebuffer(framebuffer: &Framebuffer) -> Self {
+        Self {
+            width: framebuffer.width(),
+            height: framebuffer.height(),
+            pixels: framebuffer.pixels(),
+        }
+    }
+
+    fn to_framebuffer(&self) -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(self.width, self.height);
+        for (index, color) in self.pixels.iter().enumerate() {
+            let (i, j) = (index as u32 % self.width, index as u32 / self.width);
+            framebuffer.set_pixel(i, j, color.clone());
+        }
+        framebuffer
+    }
+
+    fn pixel(&self, x: u32, y: u32) -> &Color {
+        &self.pixels[(y * self.width + x) as usize]
+    }
+
+    fn map<F>(&self, f: F) -> Image
+    where
+        F: Fn(u32, u32, &Color) -> Color + Sync,
+    {
+        let pixels = (0..self.pixels.len())
+            .into_par_iter()
+            .map(|index| {
+                let (x, y) = (index as u32 % self.width, index as u32 / self.width);
+                f(x, y, &self.pixels[index])
+            })
+            .collect();
+        Image { pixels, ..*self }
+    }
+
+    fn add(&mut self, other: &Image, weight: f64) {
+        for (c, o) in self.pixels.iter_mut().zip(&other.pixels) {
+            *c += &(weight * o);
+        }
+    }
+
+    fn scale(&mut self, factor: f64) {
+        self.pixels.iter_mut().for_each(|c| *c *= factor);
+    }
+
+    // the light above `threshold`, in the colors of the pixels it comes from
+    fn above(&self, threshold: f64) -> Image {
+        self.map(|_, _, c| {
+            let l = luminance(c);
+            if l > threshold {
+                c * ((l - threshold) / l)
+            } else {
+                Color::default()
+            }
+        })
+    }
+
+    // the bilinear interpolation at pixel coordinates (x, y), black outside the image
+    fn sample(&self, x: f64, y: f64) -> Color {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let mut sum = Color::default();
+        for (ox, wx) in [(0, 1.0 - fx), (1, fx)] {
+            for (oy, wy) in [(0, 1.0 - fy), (1, fy)] {
+                let (px, py) = (x0 as i64 + ox, y0 as i64 + oy);
+                let weight = wx * wy;
+                if weight > 0.0
+                    && (0..self.width as i64).contains(&px)
+                    && (0..self.height as i64).contains(&py)
+                {
+                    sum += &(weight * self.pixel(px as u32, py as u32));
+                }
+            }
+        }
+        sum
+    }
+
+    // half the resolution, each pixel the average of the up to 2x2 pixels it covers
+    fn downsample(&self) -> Image {
+        let (width, height) = (
+            self.width.div_ceil(2).max(1),
+            self.height.div_ceil(2).max(1),
+        );
+        Image::new(width, height).map(|x, y, _| {
+            let mut sum = Color::default();
+            let mut count = 0.0;
+            for sy in 2 * y..(2 * y + 2).min(self.height) {
+                for sx in 2 * x..(2 * x + 2).min(self.width) {
+                    sum += self.pixel(sx, sy);
+                    count += 1.0;
+                }
+            }
+            sum / count
+        })
+    }
+
+    // scaled to `width` by `height` pixels with bilinear interpolation
+    fn resize(&self, width: u32, height: u32) -> Image {
+        let (sx, sy) = (
+            self.width as f64 / width as f64,
+            self.height as f64 / height as f64,
+        );
+        let (max_x, max_y) = ((self.width - 1) as f64, (self.height - 1) as f64);
+        Image::new(width, height).map(|x, y, _| {
+            let u = ((x as f64 + 0.5) * sx - 0.5).clamp(0.0, max_x);
+            let v = ((y as f64 + 0.5) * sy - 0.5).clamp(0.0, max_y);
+            self.sample(u, v)
+        })
+    }
+
+    // a separable Gaussian blur, the weights renormalized where the kernel leaves the image
+    fn blur(&self, sigma: f64) -> Image {
+        let radius = (3.0 * sigma).ceil() as i64;
+        let kernel: Vec<f64> = (-radius..=radius)
+            .map(|d| (-(d * d) as f64 / (2.0 * sigma * sigma)).exp())
+            .collect();
+        let pass = |image: &Image, horizontal: bool| {
+            image.map(|x, y, _| {
+                let (mut sum, mut total) = (Color::default(), 0.0);
+                for (k, weight) in kernel.iter().enumerate() {
+                    let d = k as i64 - radius;
+                    let (px, py) = if horizontal {
+                        (x as i64 + d, y as i64)
+                    } else {
+                        (x as i64, y as i64 + d)
+                    };
+                    if (0..image.width as i64).contains(&px)
+                        && (0..image.height as i64).contains(&py)
+                    {
+                        sum += &(*weight * image.pixel(px as u32, py as u32));
+                        total += weight;
+                    }
+                }
+                sum / total
+            })
+        };
+        pass(&pass(self, true), false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a dark gray image with one bright pixel in the middle
+    fn point_light() -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(64, 64);
+        framebuffer.map_pixels(|_| Color::new(0.1, 0.1, 0.1));
+        framebuffer.set_pixel(32, 32, Color::new(100.0, 100.0, 100.0));
+        framebuffer
+    }
+
+    fn total(framebuffer: &Framebuffer) -> f64 {
+        framebuffer.pixels().iter().map(luminance).sum()
+    }
+
+    #[test]
+    fn test_bloom() {
+        let image = point_light();
+        let effects = Effects {
+            bloom: Some(Bloom::default()),
+            ..Effects::default()
+        };
+        let bloomed = effects.apply(&image);
+        // the light is spread around the bright pixel without adding any
+        assert!(bloomed.pixel(32, 32).x() < 100.0);
+        assert!(bloomed.pixel(34, 33).x() > 0.11);
+        assert!((bloomed.pixel(0, 0).x() - 0.1).abs() < 1e-6);
+        assert!((total(&bloomed) - total(&image)).abs() < 1e-4 * total(&image));
+
+        assert_eq!(Effects::default().apply(&image).pixels(), image.pixels());
+    }
+
+    #[test]
+    fn test_star_glare() {
+        let image = point_light();
+        let effects = Effects {
+            star_glare: Some(StarGlare {
+                spokes: 4,
+                strength: 0.5,
+                length: 0.2,
+                ..StarGlare::default()
+            }),
+            ..Effects::default()
+        };
+        let glared = effects.apply(&image);
+        // the rays go right, up, left and down, not along the diagonals
+        for (i, j) in [(38, 32), (32, 26), (26, 32), (32, 38)] {
+            assert!(glared.pixel(i, j).x() > 0.2, "{} {}", i, j);
+        }
+        assert!((glared.pixel(36, 36).x() - 0.1).abs() < 1e-9);
+        assert!((total(&glared) - total(&image)).abs() < 1e-6 * total(&image));
+    }
+
+    #[test]
+    fn test_vignetting() {
+        let mut image = Framebuffer::new(64, 64);
+        image.map_pixels(|_| Color::new(1.0, 1.0, 1.0));
+        let effects = Effects {
+            vignetting: Some(Vignetting::default()),
+            ..Effects::default()
+        };
+        let vignetted = effects.apply(&image);
+        assert!(vignetted.pixel(32, 32).x() > 0.99);
+        // cos^4 of 45 degrees in the corners
+        assert!((vignetted.pixel(0, 0).x() - 0.25).abs() < 0.02);
+        assert!(vignetted.pixel(0, 32).x() > vignetted.pixel(0, 0).x());
+    }
+}
@@ -0,0 +1,321 @@
+use crate::aov::Aov;
+use crate::color::luminance;
+use crate::utils::INFINITY;
+use crate::Color;
+use rayon::iter::Either;
+use rayon::prelude::*;
+
+// linear RGB image that accumulates radiance samples, the output of `Camera::render_to_buffer`.
+// It can also accumulate AOV passes from the first hits of the same samples
+#[derive(Debug, Clone, PartialEq)]
+pub struct Framebuffer {
+    width: u32,
+    height: u32,
+    radiance: Vec<Color>,    // sum of all samples of each pixel
+    luminance_sq: Vec<f64>,  // sum of the squared luminance of all samples, for the variance
+    sample_counts: Vec<u32>, // number of samples summed into each pixel
+    aovs: Vec<Aov>,
+    aov_sums: Vec<Color>, // per pixel one sum for each of `aovs`, the first hit for the ID passes
+    aov_hits: Vec<u32>,   // number of samples of each pixel whose camera ray hit something
+}
+
+// one row of a framebuffer, for renderers filling the buffer in parallel
+pub(crate) struct Row<'a> {
+    pub radiance: &'a mut [Color],
+    pub luminance_sq: &'a mut [f64],
+    pub sample_counts: &'a mut [u32],
+    pub aov_sums: &'a mut [Color], // empty without AOVs
+    pub aov_hits: &'a mut [u32],   // empty without AOVs
+}
+
+impl Framebuffer {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self::with_aovs(width, height, &[])
+    }
+
+    // a framebuffer that also accumulates the passes in `aovs`
+    pub fn with_aovs(width: u32, height: u32, aovs: &[Aov]) -> Self {
+        let len = width as usize * height as usize;
+        let aov_len = if aovs.is_empty() { 0 } else { len };
+        Self {
+            width,
+            height,
+            radiance: vec![Color::default(); len],
+            luminance_sq: vec![0.0; len],
+            sample_counts: vec![0; len],
+            aovs: aovs.to_vec(),
+            aov_sums: vec![Color::default(); aov_len * aovs.len()],
+            aov_hits: vec![0; aov_len],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn index(&self, i: u32, j: u32) -> usize {
+        assert!(i < self.width && j < self.height, "pixel out of bounds");
+        j as usize * self.width as usize + i as usize
+    }
+
+    // add `count` samples whose radiance sums up to `radiance` to pixel i,j
+    pub fn add_samples(&mut self, i: u32, j: u32, radiance: &Color, count: u32) {
+        let index = self.index(i, j);
+        self.radiance[index] += radiance;
+        self.sample_counts[index] += count;
+    }
+
+    // add the sum of the squared luminance of samples added with `add_samples`; without it the
+    // samples count as having no variance
+    pub fn add_luminance_sq(&mut self, i: u32, j: u32, luminance_sq: f64) {
+        let index = self.index(i, j);
+        self.luminance_sq[index] += luminance_sq;
+    }
+
+    // average radiance of pixel i,j, black if it has no samples yet
+    pub fn pixel(&self, i: u32, j: u32) -> Color {
+        let index = self.index(i, j);
+        Self::average(&self.radiance[index], self.sample_counts[index])
+    }
+
+    pub fn set_pixel(&mut self, i: u32, j: u32, color: Color) {
+        let index = self.index(i, j);
+        self.luminance_sq[index] = luminance(&color).powi(2);
+        self.radiance[index] = color;
+        self.sample_counts[index] = 1;
+    }
+
+    pub fn radiance_sum(&self, i: u32, j: u32) -> &Color {
+        &self.radiance[self.index(i, j)]
+    }
+
+    pub fn luminance_sq_sum(&self, i: u32, j: u32) -> f64 {
+        self.luminance_sq[self.index(i, j)]
+    }
+
+    // estimated variance of the average luminance of pixel i,j, i.e. of the pixel value, which is
+    // infinite while the pixel has fewer than two samples
+    pub fn variance(&self, i: u32, j: u32) -> f64 {
+        let index = self.index(i, j);
+        let n = self.sample_counts[index] as f64;
+        if n < 2.0 {
+            return f64::INFINITY;
+        }
+        let mean = luminance(&self.radiance[index]) / n;
+        let sample_variance = (self.luminance_sq[index] - n * mean * mean) / (n - 1.0);
+        sample_variance.max(0.0) / n
+    }
+
+    pub fn sample_count(&self, i: u32, j: u32) -> u32 {
+        self.sample_counts[self.index(i, j)]
+    }
+
+    // the passes accumulated besides the radiance
+    pub fn aovs(&self) -> &[Aov] {
+        &self.aovs
+    }
+
+    // the sums of the passes of pixel i,j in the order of `aovs`
+    pub fn aov_sums(&self, i: u32, j: u32) -> &[Color] {
+        let index = self.index(i, j) * self.aovs.len();
+        &self.aov_sums[index..index + self.aovs.len()]
+    }
+
+    pub fn aov_hits(&self, i: u32, j: u32) -> u32 {
+        self.aov_hits.get(self.index(i, j)).copied().unwrap_or(0)
+    }
+
+    // add the pass values of `hits` samples that hit something to pixel i,j; the ID passes take
+    // `sums` as their value if the pixel had no hits yet
+    pub fn add_aov_samples(&mut self, i: u32, j: u32, sums: &[Color], hits: u32) {
+        assert_eq!(sums.len(), self.aovs.len(), "one sum per pass");
+        let index = self.index(i, j);
+        let first = self.aov_hits[index] == 0;
+        let pixel_sums = &mut self.aov_sums[index * sums.len()..(index + 1) * sums.len()];
+        for ((sum, value), aov) in pixel_sums.iter_mut().zip(sums).zip(&self.aovs) {
+            if !aov.is_id() {
+                *sum += value;
+            } else if first {
+                *sum = value.clone();
+            }
+        }
+        self.aov_hits[index] += hits;
+    }
+
+    // every pass as an image of its own: the average of the samples, over the samples that hit
+    // something for depth, which is infinite where nothing was hit, and the first hit for the IDs
+    pub fn aov_images(&self) -> Vec<(Aov, Framebuffer)> {
+        let passes = self.aovs.len();
+        if passes == 0 {
+            return Vec::new();
+        }
+        let mut images: Vec<(Aov, Framebuffer)> = self
+            .aovs
+            .iter()
+            .map(|&aov| (aov, Framebuffer::new(self.width, self.height)))
+            .collect();
+        for index in 0..self.sample_counts.len() {
+            let (i, j) = (
+                (index % self.width as usize) as u32,
+                (index / self.width as usize) as u32,
+            );
+            let (count, hits) = (self.sample_counts[index], self.aov_hits[index]);
+            for (k, (aov, image)) in images.iter_mut().enumerate() {
+                let sum = &self.aov_sums[index * passes + k];
+                let value = match aov {
+                    Aov::Depth if hits == 0 => Color::new(INFINITY, INFINITY, INFINITY),
+                    Aov::Depth => sum / hits as f64,
+                    _ if aov.is_id() => sum.clone(),
+                    _ => Self::average(sum, count),
+                };
+                image.set_pixel(i, j, value);
+            }
+        }
+        images
+    }
+
+    pub fn total_samples(&self) -> u64 {
+        self.sample_counts.iter().map(|&n| n as u64).sum()
+    }
+
+    // average radiance of every pixel, row by row from the top left
+    pub fn pixels(&self) -> Vec<Color> {
+        self.radiance
+            .iter()
+            .zip(self.sample_counts.iter())
+            .map(|(radiance, &count)| Self::average(radiance, count))
+            .collect()
+    }
+
+    // apply `f` to the average of every pixel, replacing the accumulated samples and dropping
+    // the passes, which no longer match them
+    pub fn map_pixels<F>(&mut self, f: F)
+    where
+        F: Fn(&Color) -> Color,
+    {
+        self.aovs.clear();
+        self.aov_sums.clear();
+        self.aov_hits.clear();
+        let pixels = self.radiance.iter_mut().zip(self.sample_counts.iter_mut());
+        for ((radiance, count), luminance_sq) in pixels.zip(self.luminance_sq.iter_mut()) {
+            *radiance = f(&Self::average(radiance, *count));
+            *luminance_sq = luminance(radiance).powi(2);
+            *count = 1;
+        }
+    }
+
+    // add the samples of another buffer of the same size
+    pub fn merge(&mut self, other: &Framebuffer) {
+        assert!(
+            self.width == other.width && self.height == other.height,
+            "cannot merge framebuffers of different size"
+        );
+        assert!(self.aovs == other.aovs, "cannot merge different passes");
+        for (index, radiance) in other.radiance.iter().enumerate() {
+            self.radiance[index] += radiance;
+            self.luminance_sq[index] += other.luminance_sq[index];
+            self.sample_counts[index] += other.sample_counts[index];
+        }
+        if !self.aovs.is_empty() {
+            for (index, &hits) in other.aov_hits.iter().enumerate() {
+                let (i, j) = (
+                    (index % self.width as usize) as u32,
+                    (index / self.width as usize) as u32,
+                );
+                self.add_aov_samples(i, j, other.aov_sums(i, j), hits);
+            }
+        }
+    }
+
+    pub(crate) fn par_rows_mut(&mut self) -> impl IndexedParallelIterator<Item = Row<'_>> {
+        let width = self.width.max(1) as usize;
+        let rows = self.height as usize;
+        let passes = self.aovs.len();
+        self.radiance
+            .par_chunks_mut(width)
+            .zip(self.luminance_sq.par_chunks_mut(width))
+            .zip(self.sample_counts.par_chunks_mut(width))
+            .zip(chunks_or_empty(&mut self.aov_sums, width * passes, rows))
+            .zip(chunks_or_empty(&mut self.aov_hits, width, rows))
+            .map(
+                |((((radiance, luminance_sq), sample_counts), aov_sums), aov_hits)| Row {
+                    radiance,
+                    luminance_sq,
+                    sample_counts,
+                    aov_sums,
+                    aov_hits,
+                },
+            )
+    }
+
+    fn average(radiance: &Color, count: u32) -> Color {
+        if count == 0 {
+            Color::default()
+        } else {
+            radiance / count as f64
+        }
+    }
+}
+
+// `rows` chunks of `chunk_size`, or as many empty slices if `values` is empty
+fn chunks_or_empty<T: Send>(
+    values: &mut [T],
+    chunk_size: usize,
+    rows: usize,
+) -> impl IndexedParallelIterator<Item = &mut [T]> {
+    if values.is_empty() {
+        Either::Left((0..rows).into_par_iter().map(|_| &mut [][..]))
+    } else {
+        Either::Right(values.par_chunks_mut(chunk_size))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accumulate() {
+        let mut buffer = Framebuffer::new(2, 3);
+        assert_eq!(buffer.pixel(1, 2), Color::default());
+        buffer.add_samples(1, 2, &Color::new(1.0, 2.0, 3.0), 1);
+        buffer.add_samples(1, 2, &Color::new(3.0, 2.0, 1.0), 3);
+        assert_eq!(buffer.sample_count(1, 2), 4);
+        assert_eq!(buffer.pixel(1, 2), Color::new(1.0, 1.0, 1.0));
+        assert_eq!(buffer.total_samples(), 4);
+        assert_eq!(buffer.pixels()[5], Color::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn test_merge_and_map() {
+        let mut a = Framebuffer::new(1, 1);
+        let mut b = Framebuffer::new(1, 1);
+        a.add_samples(0, 0, &Color::new(2.0, 2.0, 2.0), 2);
+        b.add_samples(0, 0, &Color::new(4.0, 4.0, 4.0), 2);
+        a.merge(&b);
+        assert_eq!(a.pixel(0, 0), Color::new(1.5, 1.5, 1.5));
+        a.map_pixels(|c| c * 2.0);
+        assert_eq!(a.pixel(0, 0), Color::new(3.0, 3.0, 3.0));
+        assert_eq!(a.sample_count(0, 0), 1);
+    }
+
+    #[test]
+    fn test_variance() {
+        let mut buffer = Framebuffer::new(1, 1);
+        assert_eq!(buffer.variance(0, 0), f64::INFINITY);
+        // samples of luminance 1 and 3: sample variance 2, variance of the mean 1
+        buffer.add_samples(0, 0, &Color::new(4.0, 4.0, 4.0), 2);
+        buffer.add_luminance_sq(0, 0, 1.0 + 9.0);
+        assert!((buffer.variance(0, 0) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_out_of_bounds() {
+        Framebuffer::new(2, 2).pixel(2, 0);
+    }
+}
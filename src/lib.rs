@@ -0,0 +1,66 @@
+//! A ray tracer following "Ray Tracing: The Next Week", usable as a library.
+//!
+//! Load or build a scene, render it into a [`Framebuffer`] and encode it however you like:
+//!
+//! ```
+//! use raytracing_2_rs::sample_scenes::find_scene;
+//! use raytracing_2_rs::RandomSampler;
+//!
+//! let mut sampler = RandomSampler::new(0);
+//! let mut scene = find_scene("cornell_box").unwrap().build(&mut sampler).unwrap();
+//! scene.camera.image_width = 16;
+//! scene.camera.samples_per_pixel = 2;
+//! let framebuffer = scene.camera.render_to_buffer(&scene.world);
+//! assert_eq!(framebuffer.width(), 16);
+//! assert_eq!(framebuffer.sample_count(0, 0), 2);
+//! ```
+pub mod aabb;
+pub mod adaptive;
+pub mod animation;
+pub mod aov;
+pub mod aperture;
+pub mod background;
+pub mod bvh;
+pub mod camera;
+pub mod checkpoint;
+pub mod color;
+pub mod constant_medium;
+pub mod denoise;
+pub mod effects;
+pub mod framebuffer;
+pub mod hittables;
+pub mod interval;
+pub mod low_discrepancy;
+pub mod material;
+pub mod obj;
+pub mod onb;
+pub mod output;
+pub mod perlin;
+pub mod projection;
+pub mod quad;
+pub mod ray;
+pub mod sample_scenes;
+pub mod sampler;
+pub mod scene;
+pub mod shutter;
+pub mod sphere;
+pub mod texture;
+pub mod tonemap;
+pub mod traits;
+pub mod transform;
+pub mod triangle;
+pub mod utils;
+pub mod vec3;
+
+pub use camera::Camera;
+pub use color::Color;
+pub use framebuffer::Framebuffer;
+pub use hittables::{HitRecord, Hittables};
+pub use interval::Interval;
+pub use ray::Ray;
+pub use sampler::RandomSampler;
+pub use scene::Scene;
+pub use traits::{Hittable, Material, Sampler, Texture};
+pub use vec3::{Point3, Vec3};
+pub type Error = Box<dyn std::error::Error>;
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,326 @@
+// Building blocks of the stratified and low-discrepancy samplers: random-access permutations,
+// the Halton and Sobol sequences and a blue-noise mask.
+use crate::sampler::RandomSampler;
+use crate::traits::Sampler;
+use std::sync::OnceLock;
+
+// the Halton sequence uses one prime base per dimension
+pub const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+pub const SOBOL_DIMENSIONS: usize = 16;
+
+// degree, coefficients and initial direction numbers of Sobol dimensions 2 to 16, from the
+// new-joe-kuo-6.21201 table by Joe and Kuo
+const SOBOL_POLYNOMIALS: [(u32, u32, [u32; 6]); SOBOL_DIMENSIONS - 1] = [
+    (1, 0, [1, 0, 0, 0, 0, 0]),
+    (2, 1, [1, 3, 0, 0, 0, 0]),
+    (3, 1, [1, 3, 1, 0, 0, 0]),
+    (3, 2, [1, 1, 1, 0, 0, 0]),
+    (4, 1, [1, 1, 3, 3, 0, 0]),
+    (4, 4, [1, 3, 5, 13, 0, 0]),
+    (5, 2, [1, 1, 5, 5, 17, 0]),
+    (5, 4, [1, 1, 5, 5, 5, 0]),
+    (5, 7, [1, 1, 7, 11, 19, 0]),
+    (5, 11, [1, 1, 5, 1, 1, 0]),
+    (5, 13, [1, 1, 1, 3, 11, 0]),
+    (5, 14, [1, 3, 5, 5, 31, 0]),
+    (6, 1, [1, 3, 3, 9, 7, 49]),
+    (6, 13, [1, 1, 1, 15, 21, 21]),
+    (6, 16, [1, 3, 1, 13, 27, 49]),
+];
+
+const BLUE_NOISE_SIZE: usize = 64;
+
+// element `i` of a pseudo-random permutation of 0..n chosen by `seed`, after Kensler's
+// "Correlated Multi-Jittered Sampling"
+pub fn permutation_element(i: u32, n: u32, seed: u32) -> u32 {
+    let mut w = n.max(1) - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    let mut i = i;
+    // a bijection of 0..=w, applied until the result lands in 0..n
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < n {
+            return i;
+        }
+    }
+}
+
+// the digits of `index` in base `base` mirrored around the decimal point
+pub fn radical_inverse(base: u32, mut index: u64) -> f64 {
+    let inverse_base = 1.0 / base as f64;
+    let mut inverse = 0.0;
+    let mut factor = inverse_base;
+    while index > 0 {
+        inverse += (index % base as u64) as f64 * factor;
+        index /= base as u64;
+        factor *= inverse_base;
+    }
+    inverse.min(1.0 - f64::EPSILON / 2.0)
+}
+
+// `radical_inverse` with the digits at every position shuffled by a permutation chosen by `seed`,
+// which keeps the stratification but breaks up the patterns of the higher prime bases
+pub fn scrambled_radical_inverse(base: u32, mut index: u64, seed: u64) -> f64 {
+    let inverse_base = 1.0 / base as f64;
+    let mut inverse = 0.0;
+    let mut factor = inverse_base;
+    let mut position = 0;
+    // zeros past the last digit get shuffled as well, up to about 32 bits of precision
+    while factor > 1e-10 {
+        let digit_seed = (seed ^ (seed >> 32) ^ position) as u32;
+        let digit = permutation_element((index % base as u64) as u32, base, digit_seed);
+        inverse += digit as f64 * factor;
+        index /= base as u64;
+        factor *= inverse_base;
+        position = position.wrapping_add(0x9e37_79b9);
+    }
+    inverse.min(1.0 - f64::EPSILON / 2.0)
+}
+
+// dimension `dimension` of point `index` of the Sobol sequence, as a 32 bit fraction
+pub fn sobol(index: u32, dimension: usize) -> u32 {
+    let directions = &sobol_directions()[dimension];
+    let mut result = 0;
+    let mut bits = index;
+    let mut k = 0;
+    while bits != 0 {
+        if bits & 1 != 0 {
+            result ^= directions[k];
+        }
+        bits >>= 1;
+        k += 1;
+    }
+    result
+}
+
+fn sobol_directions() -> &'static [[u32; 32]; SOBOL_DIMENSIONS] {
+    static DIRECTIONS: OnceLock<[[u32; 32]; SOBOL_DIMENSIONS]> = OnceLock::new();
+    DIRECTIONS.get_or_init(|| {
+        let mut directions = [[0; 32]; SOBOL_DIMENSIONS];
+        // the first dimension is the van der Corput sequence
+        for (k, v) in directions[0].iter_mut().enumerate() {
+            *v = 1 << (31 - k);
+        }
+        for (d, &(degree, coefficients, m)) in SOBOL_POLYNOMIALS.iter().enumerate() {
+            let s = degree as usize;
+            let v = &mut directions[d + 1];
+            for k in 0..s {
+                v[k] = m[k] << (31 - k);
+            }
+            for k in s..32 {
+                let mut value = v[k - s] ^ (v[k - s] >> s);
+                for i in 1..s {
+                    if (coefficients >> (s - 1 - i)) & 1 != 0 {
+                        value ^= v[k - i];
+                    }
+                }
+                v[k] = value;
+            }
+        }
+        directions
+    })
+}
+
+// rank of pixel x,y in a tileable 64x64 blue-noise mask, divided by the number of pixels; nearby
+// pixels get very different values
+pub fn blue_noise(x: u32, y: u32) -> f64 {
+    let mask = blue_noise_mask();
+    let index = (y as usize % BLUE_NOISE_SIZE) * BLUE_NOISE_SIZE + x as usize % BLUE_NOISE_SIZE;
+    mask[index] as f64 / mask.len() as f64
+}
+
+fn blue_noise_mask() -> &'static [u32] {
+    static MASK: OnceLock<Vec<u32>> = OnceLock::new();
+    MASK.get_or_init(|| void_and_cluster(BLUE_NOISE_SIZE, 1.9))
+}
+
+// ranks of the pixels of a size x size blue-noise dither array, built with Ulichney's
+// void-and-cluster method on a torus
+fn void_and_cluster(size: usize, sigma: f64) -> Vec<u32> {
+    let n = size * size;
+    // gaussian energy a point adds at each toroidal offset
+    let kernel: Vec<f64> = (0..n)
+        .map(|index| {
+            let wrap = |d: usize| d.min(size - d) as f64;
+            let (dx, dy) = (wrap(index % size), wrap(index / size));
+            (-(dx * dx + dy * dy) / (2.0 * sigma * sigma)).exp()
+        })
+        .collect();
+    let update = |energy: &mut [f64], point: usize, sign: f64| {
+        let (px, py) = (point % size, point / size);
+        for y in 0..size {
+            let dy = (y + size - py) % size;
+            for x in 0..size {
+                let dx = (x + size - px) % size;
+                energy[y * size + x] += sign * kernel[dy * size + dx];
+            }
+        }
+    };
+    // the densest point among the set ones, or the emptiest spot among the unset ones
+    let tightest_cluster = |energy: &[f64], set: &[bool]| {
+        (0..n)
+            .filter(|&i| set[i])
+            .max_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            .unwrap()
+    };
+    let largest_void = |energy: &[f64], set: &[bool]| {
+        (0..n)
+            .filter(|&i| !set[i])
+            .min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            .unwrap()
+    };
+
+    // random initial pattern of a tenth of the pixels, relaxed until the tightest cluster is
+    // also the largest void
+    let mut sampler = RandomSampler::new(0x5eed);
+    let mut set = vec![false; n];
+    let mut energy = vec![0.0; n];
+    let initial = n / 10;
+    while set.iter().filter(|&&s| s).count() < initial {
+        let point = sampler.random_usize(0, n);
+        if !set[point] {
+            set[point] = true;
+            update(&mut energy, point, 1.0);
+        }
+    }
+    loop {
+        let cluster = tightest_cluster(&energy, &set);
+        set[cluster] = false;
+        update(&mut energy, cluster, -1.0);
+        let void = largest_void(&energy, &set);
+        set[void] = true;
+        update(&mut energy, void, 1.0);
+        if void == cluster {
+            break;
+        }
+    }
+
+    // rank the initial points by removing tightest clusters, then fill the largest voids
+    let mut ranks = vec![0; n];
+    let (mut removed_set, mut removed_energy) = (set.clone(), energy.clone());
+    for rank in (0..initial).rev() {
+        let cluster = tightest_cluster(&removed_energy, &removed_set);
+        removed_set[cluster] = false;
+        update(&mut removed_energy, cluster, -1.0);
+        ranks[cluster] = rank as u32;
+    }
+    for rank in initial..n {
+        let void = largest_void(&energy, &set);
+        set[void] = true;
+        update(&mut energy, void, 1.0);
+        ranks[void] = rank as u32;
+    }
+    ranks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permutation_element() {
+        for n in [1, 2, 5, 16, 100] {
+            for seed in [0, 1, 0xdead_beef] {
+                let mut seen: Vec<u32> = (0..n).map(|i| permutation_element(i, n, seed)).collect();
+                seen.sort();
+                assert_eq!(seen, (0..n).collect::<Vec<u32>>());
+            }
+        }
+    }
+
+    #[test]
+    fn test_radical_inverse() {
+        assert_eq!(radical_inverse(2, 0), 0.0);
+        assert_eq!(radical_inverse(2, 1), 0.5);
+        assert_eq!(radical_inverse(2, 6), 0.375);
+        assert!((radical_inverse(3, 5) - 7.0 / 9.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_scrambled_radical_inverse() {
+        for base in [2, 3, 131] {
+            // the first `base` points still fall into different intervals of length 1 / base
+            let mut intervals: Vec<u32> = (0..base)
+                .map(|i| {
+                    let x = scrambled_radical_inverse(base, i as u64, 42);
+                    assert!((0.0..1.0).contains(&x));
+                    (x * base as f64) as u32
+                })
+                .collect();
+            intervals.sort();
+            assert_eq!(intervals, (0..base).collect::<Vec<u32>>());
+        }
+        assert_ne!(
+            scrambled_radical_inverse(5, 3, 1),
+            scrambled_radical_inverse(5, 3, 2)
+        );
+    }
+
+    #[test]
+    fn test_sobol_stratification() {
+        // the first 2^k points of every dimension hit each interval of length 2^-k once
+        for dimension in 0..SOBOL_DIMENSIONS {
+            let mut intervals: Vec<u32> = (0..64).map(|i| sobol(i, dimension) >> 26).collect();
+            intervals.sort();
+            assert_eq!(
+                intervals,
+                (0..64).collect::<Vec<u32>>(),
+                "dimension {}",
+                dimension
+            );
+        }
+        // and the first two dimensions together hit each cell of a 4x4 grid once
+        let mut cells: Vec<u32> = (0..16)
+            .map(|i| (sobol(i, 0) >> 30) * 4 + (sobol(i, 1) >> 30))
+            .collect();
+        cells.sort();
+        assert_eq!(cells, (0..16).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn test_blue_noise_mask() {
+        let mut ranks = blue_noise_mask().to_vec();
+        ranks.sort();
+        assert_eq!(ranks, (0..ranks.len() as u32).collect::<Vec<u32>>());
+        // blue noise has little low-frequency content: averages over 4x4 blocks vary much less
+        // than the 1/192 of white noise
+        let mut variance = 0.0;
+        for by in 0..16 {
+            for bx in 0..16 {
+                let mut sum = 0.0;
+                for y in 0..4 {
+                    for x in 0..4 {
+                        sum += blue_noise(bx * 4 + x, by * 4 + y);
+                    }
+                }
+                variance += (sum / 16.0 - 0.5).powi(2) / 256.0;
+            }
+        }
+        assert!(variance < 0.25 / 192.0, "{}", variance);
+    }
+}
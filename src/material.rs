@@ -1,6 +1,7 @@
-use crate::texture::{CheckerTexture, SolidColor};
-use crate::traits::{ScatterInfo, Texture};
-use crate::utils::random_f64;
+use crate::hittables::HitRecord;
+use crate::texture::SolidColor;
+use crate::traits::{Sampler, ScatterInfo, Texture};
+use crate::utils::PI;
 use crate::Color;
 use crate::Material;
 use crate::Point3;
@@ -37,12 +38,17 @@ impl Lambertian {
     }
 }
 impl Material for Lambertian {
+    fn albedo(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.albedo.value(u, v, p)
+    }
+
     fn scatter(
         &self,
         ray_in: &crate::ray::Ray,
         rec: &crate::hittables::HitRecord,
+        sampler: &mut dyn Sampler,
     ) -> Option<ScatterInfo> {
-        let mut scatter_direction = &rec.normal + Vec3::random_unit_vec3();
+        let mut scatter_direction = &rec.normal + Vec3::random_unit_vec3(sampler);
         if scatter_direction.near_zero() {
             scatter_direction = rec.normal.clone();
         }
@@ -57,6 +63,20 @@ impl Material for Lambertian {
             ray_scattered,
         })
     }
+
+    fn is_specular(&self) -> bool {
+        false
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        self.albedo.value(rec.u, rec.v, &rec.point) * self.pdf(ray_in, rec, direction)
+    }
+
+    // scattering around the normal picks directions with a cosine distribution
+    fn pdf(&self, _ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        let cosine = rec.normal.dot(&direction.make_unit_vector());
+        cosine.max(0.0) / PI
+    }
 }
 
 impl Metal {
@@ -75,11 +95,20 @@ impl Metal {
     }
 }
 impl Material for Metal {
-    fn scatter(&self, ray_in: &Ray, rec: &crate::hittables::HitRecord) -> Option<ScatterInfo> {
+    fn albedo(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.albedo.value(u, v, p)
+    }
+
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &crate::hittables::HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterInfo> {
         let reflected = Vec3::reflect(&ray_in.dir, &rec.normal);
         let ray_scattered = Ray {
             orig: rec.point.clone(),
-            dir: reflected + Vec3::random_unit_vec3() * self.fuzz,
+            dir: reflected + Vec3::random_unit_vec3(sampler) * self.fuzz,
             tm: ray_in.tm,
         };
         let attenuation = self.albedo.value(rec.u, rec.v, &rec.point);
@@ -104,7 +133,12 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, ray_in: &Ray, rec: &crate::hittables::HitRecord) -> Option<ScatterInfo> {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &crate::hittables::HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterInfo> {
         let refraction_ratio = if rec.front_face {
             1.0 / self.ir
         } else {
@@ -115,7 +149,7 @@ impl Material for Dielectric {
         let sin_theta = (1.0 - cos_theta.powi(2)).sqrt();
         let can_reflect = refraction_ratio * sin_theta > 1.0;
         let direction =
-            if can_reflect || Self::reflectance(cos_theta, refraction_ratio) > random_f64() {
+            if can_reflect || Self::reflectance(cos_theta, refraction_ratio) > sampler.get_1d() {
                 Vec3::reflect(&unit_direction, &rec.normal)
             } else {
                 Vec3::refract(&unit_direction, &rec.normal, refraction_ratio)
@@ -151,11 +185,20 @@ impl DiffuseLight {
     }
 }
 impl Material for DiffuseLight {
+    fn is_emissive(&self) -> bool {
+        true
+    }
+
     fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
         self.emit.value(u, v, p)
     }
 
-    fn scatter(&self, ray_in: &Ray, rec: &crate::hittables::HitRecord) -> Option<ScatterInfo> {
+    fn scatter(
+        &self,
+        _ray_in: &Ray,
+        _rec: &crate::hittables::HitRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<ScatterInfo> {
         None
     }
 }
@@ -176,12 +219,37 @@ impl Isotropic {
 }
 
 impl Material for Isotropic {
-    fn scatter(&self, ray_in: &Ray, rec: &crate::hittables::HitRecord) -> Option<ScatterInfo> {
-        let ray_scattered = Ray::new(rec.point.clone(), Vec3::random_unit_vec3(), ray_in.tm);
+    fn albedo(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.albedo.value(u, v, p)
+    }
+
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &crate::hittables::HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterInfo> {
+        let ray_scattered = Ray::new(
+            rec.point.clone(),
+            Vec3::random_unit_vec3(sampler),
+            ray_in.tm,
+        );
         let attenuation = self.albedo.value(rec.u, rec.v, &rec.point);
         Some(ScatterInfo {
             attenuation,
             ray_scattered,
         })
     }
+    fn is_specular(&self) -> bool {
+        false
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        self.albedo.value(rec.u, rec.v, &rec.point) * self.pdf(ray_in, rec, direction)
+    }
+
+    // uniform over the sphere of directions
+    fn pdf(&self, _ray_in: &Ray, _rec: &HitRecord, _direction: &Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
 }
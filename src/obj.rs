@@ -0,0 +1,591 @@
+// Wavefront OBJ and MTL import.
+//
+// An OBJ file becomes one triangle mesh per group and material: polygons are split into fans of
+// triangles, and vertices with the same position, texture coordinate and normal are shared. The
+// materials of the MTL libraries the file references map onto the renderer's materials:
+//
+//     Ke not black              -> DiffuseLight emitting Ke
+//     d (or 1 - Tr) below 1     -> Dielectric with index of refraction Ni (1.5 if not given)
+//     Ks brighter than Kd       -> Metal of color Ks, the higher Ns the less fuzz
+//     anything else             -> Lambertian with map_Kd if given, otherwise Kd
+//
+// Statements that do not matter for rendering (s, l, illum, ...) are skipped.
+use crate::bvh::Bvh;
+use crate::color::luminance;
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Metal};
+use crate::scene::SceneError;
+use crate::texture::ImageTexture;
+use crate::triangle::{Mesh, TriangleMesh};
+use crate::{Color, Hittable, Material, Point3, Vec3};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+type LoadResult<T> = std::result::Result<T, SceneError>;
+
+// the faces of one group that share a material
+#[derive(Debug, Clone)]
+pub struct ObjMesh {
+    pub group: String,
+    pub material: Option<String>, // name from `usemtl`
+    pub mesh: Mesh,
+}
+
+pub struct Obj {
+    pub meshes: Vec<ObjMesh>,
+    pub materials: HashMap<String, Arc<dyn Material>>, // from the MTL libraries
+    pub files: Vec<PathBuf>,                           // the MTL libraries and images read
+}
+
+impl Obj {
+    pub fn load<P: AsRef<Path>>(path: P) -> LoadResult<Obj> {
+        let path = path.as_ref();
+        let source = read(path)?;
+        let base_dir = path.parent().unwrap_or(Path::new("."));
+        Self::parse(&source, &path.display().to_string(), base_dir)
+    }
+
+    // parse OBJ source text, `file` is only used in error messages and MTL libraries are looked
+    // up relative to `base_dir`
+    pub fn parse(source: &str, file: &str, base_dir: &Path) -> LoadResult<Obj> {
+        let error = |line: usize, message: String| SceneError {
+            file: file.to_string(),
+            line,
+            message,
+        };
+        let mut positions: Vec<Point3> = Vec::new();
+        let mut uvs: Vec<(f64, f64)> = Vec::new();
+        let mut normals: Vec<Vec3> = Vec::new();
+        let mut materials = HashMap::new();
+        let mut files = Vec::new();
+        let mut builders: Vec<MeshBuilder> = Vec::new();
+        let mut current: Option<usize> = None; // builder of the current group and material
+        let mut group = String::from("default");
+        let mut material: Option<String> = None;
+        let mut used_materials: Vec<(String, usize)> = Vec::new();
+
+        for (index, line) in source.lines().enumerate() {
+            let number = index + 1;
+            let mut words = line.split('#').next().unwrap().split_whitespace();
+            let Some(keyword) = words.next() else {
+                continue;
+            };
+            let args: Vec<&str> = words.collect();
+            match keyword {
+                "v" => {
+                    let [x, y, z] = numbers(&args, 3, 4).map_err(|m| error(number, m))?[..3] else {
+                        unreachable!()
+                    };
+                    positions.push(Point3::new(x, y, z));
+                }
+                "vt" => {
+                    let uv = numbers(&args, 1, 3).map_err(|m| error(number, m))?;
+                    uvs.push((uv[0], uv.get(1).copied().unwrap_or(0.0)));
+                }
+                "vn" => {
+                    let [x, y, z] = numbers(&args, 3, 3).map_err(|m| error(number, m))?[..] else {
+                        unreachable!()
+                    };
+                    normals.push(Vec3::new(x, y, z));
+                }
+                "f" => {
+                    if args.len() < 3 {
+                        return Err(error(number, "a face needs at least three vertices".into()));
+                    }
+                    let counts = (positions.len(), uvs.len(), normals.len());
+                    let vertices = args
+                        .iter()
+                        .map(|arg| face_vertex(arg, counts))
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|m| error(number, m))?;
+                    let builder = match current {
+                        Some(builder) => builder,
+                        None => {
+                            let name = material.clone();
+                            let found = builders
+                                .iter()
+                                .position(|b| b.group == group && b.material == name);
+                            let builder = found.unwrap_or_else(|| {
+                                builders.push(MeshBuilder::new(&group, name));
+                                builders.len() - 1
+                            });
+                            current = Some(builder);
+                            builder
+                        }
+                    };
+                    // a fan of triangles around the first vertex
+                    for k in 1..vertices.len() - 1 {
+                        let triangle = [vertices[0], vertices[k], vertices[k + 1]];
+                        builders[builder].add(triangle, &positions, &uvs, &normals);
+                    }
+                }
+                "g" | "o" => {
+                    group = if args.is_empty() {
+                        String::from("default")
+                    } else {
+                        args.join(" ")
+                    };
+                    current = None;
+                }
+                "usemtl" => {
+                    let name = args.join(" ");
+                    if name.is_empty() {
+                        return Err(error(number, "`usemtl` needs a material name".into()));
+                    }
+                    used_materials.push((name.clone(), number));
+                    material = Some(name);
+                    current = None;
+                }
+                "mtllib" => {
+                    if args.is_empty() {
+                        return Err(error(number, "`mtllib` needs a file name".into()));
+                    }
+                    for library in args {
+                        let path = base_dir.join(library);
+                        let source = read(&path).map_err(|e| {
+                            error(number, format!("cannot read `{}`: {}", library, e.message))
+                        })?;
+                        let library_dir = path.parent().unwrap_or(Path::new("."));
+                        let file = path.display().to_string();
+                        materials.extend(parse_mtl(&source, &file, library_dir, &mut files)?);
+                        files.push(path);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if let Some((name, line)) = used_materials
+            .into_iter()
+            .find(|(name, _)| !materials.contains_key(name))
+        {
+            return Err(error(
+                line,
+                format!("material `{}` is not defined in any `mtllib`", name),
+            ));
+        }
+        let meshes: Vec<ObjMesh> = builders
+            .into_iter()
+            .filter_map(MeshBuilder::finish)
+            .collect();
+        if meshes.is_empty() {
+            return Err(error(0, "the file has no faces".into()));
+        }
+        Ok(Obj {
+            meshes,
+            materials,
+            files,
+        })
+    }
+
+    // a triangle mesh for every group and material, with a BVH over them if there are several.
+    // `default_material` is used for faces without `usemtl`, and for all faces if `replace` is set
+    pub fn build(self, default_material: Arc<dyn Material>, replace: bool) -> Arc<dyn Hittable> {
+        let mut objects: Vec<Arc<dyn Hittable>> = Vec::new();
+        for obj_mesh in self.meshes {
+            let material = match &obj_mesh.material {
+                Some(name) if !replace => self.materials[name].clone(),
+                _ => default_material.clone(),
+            };
+            objects.push(Arc::new(TriangleMesh::new(obj_mesh.mesh, material)));
+        }
+        if objects.len() == 1 {
+            objects.pop().unwrap()
+        } else {
+            Arc::new(Bvh::new(&objects))
+        }
+    }
+}
+
+// the materials of an MTL library, image paths are relative to `base_dir` and the images read are
+// added to `files`
+pub fn parse_mtl(
+    source: &str,
+    file: &str,
+    base_dir: &Path,
+    files: &mut Vec<PathBuf>,
+) -> LoadResult<HashMap<String, Arc<dyn Material>>> {
+    let error = |line: usize, message: String| SceneError {
+        file: file.to_string(),
+        line,
+        message,
+    };
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlMaterial)> = None;
+    for (index, line) in source.lines().enumerate() {
+        let number = index + 1;
+        let mut words = line.split('#').next().unwrap().split_whitespace();
+        let Some(keyword) = words.next() else {
+            continue;
+        };
+        let args: Vec<&str> = words.collect();
+        if keyword == "newmtl" {
+            if args.is_empty() {
+                return Err(error(number, "`newmtl` needs a material name".into()));
+            }
+            if let Some((name, material)) = current.take() {
+                materials.insert(name, material.build());
+            }
+            current = Some((args.join(" "), MtlMaterial::default()));
+            continue;
+        }
+        let known = [
+            "Kd", "Ks", "Ke", "Ns", "Ni", "d", "Tr", "map_Kd", "Ka", "illum",
+        ];
+        if !known.contains(&keyword) {
+            continue;
+        }
+        let Some((_, material)) = current.as_mut() else {
+            return Err(error(number, format!("`{}` before any `newmtl`", keyword)));
+        };
+        match keyword {
+            "Kd" | "Ks" | "Ke" => {
+                let c = numbers(&args, 1, 3).map_err(|m| error(number, m))?;
+                // a single number is a gray
+                let color = if c.len() == 3 {
+                    Color::new(c[0], c[1], c[2])
+                } else if c.len() == 1 {
+                    Color::new(c[0], c[0], c[0])
+                } else {
+                    return Err(error(number, "expected 1 or 3 numbers".into()));
+                };
+                match keyword {
+                    "Kd" => material.diffuse = color,
+                    "Ks" => material.specular = color,
+                    _ => material.emission = color,
+                }
+            }
+            "Ns" | "Ni" | "d" | "Tr" => {
+                let x = numbers(&args, 1, 1).map_err(|m| error(number, m))?[0];
+                match keyword {
+                    "Ns" => material.exponent = x,
+                    "Ni" => material.index = Some(x),
+                    "d" => material.dissolve = x,
+                    _ => material.dissolve = 1.0 - x,
+                }
+            }
+            "map_Kd" => {
+                // options such as `-s 1 1 1` come before the file name
+                let Some(name) = args.last() else {
+                    return Err(error(number, "`map_Kd` needs a file name".into()));
+                };
+                let path = base_dir.join(name);
+                let texture = ImageTexture::new(&path).map_err(|e| {
+                    error(
+                        number,
+                        format!("cannot load image `{}`: {}", path.display(), e),
+                    )
+                })?;
+                material.texture = Some(Arc::new(texture));
+                files.push(path);
+            }
+            _ => {}
+        }
+    }
+    if let Some((name, material)) = current {
+        materials.insert(name, material.build());
+    }
+    Ok(materials)
+}
+
+// the statements of one `newmtl`
+struct MtlMaterial {
+    diffuse: Color,
+    specular: Color,
+    emission: Color,
+    exponent: f64,
+    index: Option<f64>,
+    dissolve: f64,
+    texture: Option<Arc<ImageTexture>>,
+}
+
+impl Default for MtlMaterial {
+    fn default() -> Self {
+        Self {
+            diffuse: Color::new(0.8, 0.8, 0.8),
+            specular: Color::default(),
+            emission: Color::default(),
+            exponent: 0.0,
+            index: None,
+            dissolve: 1.0,
+            texture: None,
+        }
+    }
+}
+
+impl MtlMaterial {
+    fn build(self) -> Arc<dyn Material> {
+        if self.emission != Color::default() {
+            Arc::new(DiffuseLight::new_from_color(self.emission))
+        } else if self.dissolve < 1.0 {
+            Arc::new(Dielectric::new(self.index.unwrap_or(1.5)))
+        } else if luminance(&self.specular) > luminance(&self.diffuse) {
+            // a common mapping of the Phong exponent onto roughness
+            let fuzz = (2.0 / (self.exponent.max(0.0) + 2.0)).sqrt();
+            Arc::new(Metal::new_from_color(self.specular, fuzz))
+        } else if let Some(texture) = self.texture {
+            Arc::new(Lambertian::new(texture))
+        } else {
+            Arc::new(Lambertian::new_from_color(self.diffuse))
+        }
+    }
+}
+
+// one vertex of a face: indices into positions, texture coordinates and normals
+type FaceVertex = (usize, Option<usize>, Option<usize>);
+
+struct MeshBuilder {
+    group: String,
+    material: Option<String>,
+    mesh: Mesh,
+    vertices: HashMap<FaceVertex, u32>,
+    missing_uvs: bool,
+    missing_normals: bool,
+}
+
+impl MeshBuilder {
+    fn new(group: &str, material: Option<String>) -> Self {
+        Self {
+            group: group.to_string(),
+            material,
+            mesh: Mesh::default(),
+            vertices: HashMap::new(),
+            missing_uvs: false,
+            missing_normals: false,
+        }
+    }
+
+    fn add(
+        &mut self,
+        triangle: [FaceVertex; 3],
+        positions: &[Point3],
+        uvs: &[(f64, f64)],
+        normals: &[Vec3],
+    ) {
+        let [a, b, c] = triangle.map(|(p, _, _)| &positions[p]);
+        // degenerate triangles can never be hit
+        if (b - a).cross(&(c - a)).length_squared() == 0.0 {
+            return;
+        }
+        let indices = triangle.map(|vertex| {
+            *self.vertices.entry(vertex).or_insert_with(|| {
+                let (p, uv, normal) = vertex;
+                self.mesh.positions.push(positions[p].clone());
+                self.mesh.uvs.push(uv.map_or((0.0, 0.0), |uv| uvs[uv]));
+                self.mesh
+                    .normals
+                    .push(normal.map_or(Vec3::default(), |n| normals[n].make_unit_vector()));
+                self.missing_uvs |= uv.is_none();
+                self.missing_normals |= normal.is_none();
+                (self.mesh.positions.len() - 1) as u32
+            })
+        });
+        self.mesh.indices.push(indices);
+    }
+
+    // the mesh, None if it has no triangles
+    fn finish(mut self) -> Option<ObjMesh> {
+        if self.mesh.indices.is_empty() {
+            return None;
+        }
+        // attributes some vertices lack are left out for the whole mesh
+        if self.missing_uvs {
+            self.mesh.uvs.clear();
+        }
+        if self.missing_normals {
+            self.mesh.normals.clear();
+        }
+        Some(ObjMesh {
+            group: self.group,
+            material: self.material,
+            mesh: self.mesh,
+        })
+    }
+}
+
+// a face vertex `v`, `v/vt`, `v//vn` or `v/vt/vn`, with 1-based or negative (counting back from
+// the last one) indices; `counts` are the numbers of positions, uvs and normals so far
+fn face_vertex(arg: &str, counts: (usize, usize, usize)) -> Result<FaceVertex, String> {
+    let parts: Vec<&str> = arg.split('/').collect();
+    if parts.len() > 3 {
+        return Err(format!("malformed face vertex `{}`", arg));
+    }
+    let index = |part: Option<&&str>, count: usize, what: &str| -> Result<Option<usize>, String> {
+        match part {
+            None | Some(&"") => Ok(None),
+            Some(part) => {
+                let n: i64 = part
+                    .parse()
+                    .map_err(|_| format!("malformed face vertex `{}`", arg))?;
+                let index = if n < 0 { count as i64 + n } else { n - 1 };
+                if n == 0 || index < 0 || index >= count as i64 {
+                    Err(format!("{} index {} is out of range", what, n))
+                } else {
+                    Ok(Some(index as usize))
+                }
+            }
+        }
+    };
+    let position = index(parts.first(), counts.0, "vertex")?
+        .ok_or_else(|| format!("malformed face vertex `{}`", arg))?;
+    let uv = index(parts.get(1), counts.1, "texture coordinate")?;
+    let normal = index(parts.get(2), counts.2, "normal")?;
+    Ok((position, uv, normal))
+}
+
+// between `min` and `max` numbers
+fn numbers(args: &[&str], min: usize, max: usize) -> Result<Vec<f64>, String> {
+    let numbers: Option<Vec<f64>> = args.iter().map(|arg| arg.parse().ok()).collect();
+    match numbers {
+        Some(numbers) if (min..=max).contains(&numbers.len()) => Ok(numbers),
+        _ if min == max => Err(format!("expected {} numbers", min)),
+        _ => Err(format!("expected {} to {} numbers", min, max)),
+    }
+}
+
+fn read(path: &Path) -> LoadResult<String> {
+    fs::read_to_string(path).map_err(|e| SceneError {
+        file: path.display().to_string(),
+        line: 0,
+        message: e.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interval::Interval;
+    use crate::utils::INFINITY;
+    use crate::{RandomSampler, Ray};
+
+    fn parse(source: &str) -> LoadResult<Obj> {
+        Obj::parse(source, "test.obj", Path::new("."))
+    }
+
+    #[test]
+    fn test_parse_obj() {
+        let obj = parse(
+            "# a quad and a triangle in two groups
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 2
+g square
+f 1/1/1 2/2/1 3/3/1 4/4/1
+g roof
+s 1
+f -4//-1 -2//-1 -1//-1
+",
+        )
+        .unwrap();
+        assert_eq!(obj.meshes.len(), 2);
+        let square = &obj.meshes[0];
+        assert_eq!(square.group, "square");
+        assert_eq!(square.material, None);
+        assert_eq!(square.mesh.indices, vec![[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(square.mesh.uvs[2], (1.0, 1.0));
+        assert_eq!(square.mesh.normals[0], Vec3::new(0.0, 0.0, 1.0));
+        let roof = &obj.meshes[1];
+        assert_eq!(roof.mesh.positions.len(), 3);
+        assert_eq!(roof.mesh.positions[1], Point3::new(1.0, 1.0, 0.0));
+        assert!(roof.mesh.uvs.is_empty());
+
+        let material = Arc::new(Lambertian::new_from_color(Color::new(0.5, 0.5, 0.5)));
+        let world = obj.build(material, false);
+        let ray = Ray::new(Point3::new(0.75, 0.25, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let rec = world
+            .hit(
+                &ray,
+                &mut Interval::new(0.001, INFINITY),
+                &mut RandomSampler::new(0),
+            )
+            .unwrap();
+        assert!((rec.u - 0.75).abs() < 1e-12 && (rec.v - 0.25).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_obj_errors() {
+        let err = parse("v 0 0 0\nv 1 0\n").err().unwrap();
+        assert_eq!(
+            (err.line, err.message.as_str()),
+            (2, "expected 3 to 4 numbers")
+        );
+        let err = parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n").err().unwrap();
+        assert_eq!(
+            (err.line, err.message.as_str()),
+            (4, "vertex index 4 is out of range")
+        );
+        let err = parse("v 0 0 0\nf 1 1/x 1\n").err().unwrap();
+        assert_eq!(err.message, "malformed face vertex `1/x`");
+        let err = parse("mtllib missing.mtl\n").err().unwrap();
+        assert_eq!(err.line, 1);
+        assert!(err.message.starts_with("cannot read `missing.mtl`"));
+        let err = parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl red\nf 1 2 3\n")
+            .err()
+            .unwrap();
+        assert_eq!(
+            (err.line, err.message.as_str()),
+            (4, "material `red` is not defined in any `mtllib`")
+        );
+        assert_eq!(
+            parse("v 0 0 0\n").err().unwrap().message,
+            "the file has no faces"
+        );
+    }
+
+    #[test]
+    fn test_mtl() {
+        let dir = std::env::temp_dir().join(format!("obj_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("materials.mtl"),
+            "newmtl lamp\nKe 4 4 4\nnewmtl glass\nd 0.2\nNi 1.3\n\
+             newmtl chrome\nKd 0.1 0.1 0.1\nKs 0.9\nNs 998\nnewmtl paint\nKd 0.2 0.3 0.4\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("model.obj"),
+            "mtllib materials.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\n\
+             usemtl lamp\nf 1 2 3\nusemtl paint\nf 3 2 1\n",
+        )
+        .unwrap();
+        fs::write(dir.join("broken.mtl"), "newmtl x\nKd 1 2\n").unwrap();
+        let obj = Obj::load(dir.join("model.obj"));
+        let broken = parse_mtl(
+            &fs::read_to_string(dir.join("broken.mtl")).unwrap(),
+            "broken.mtl",
+            &dir,
+            &mut Vec::new(),
+        );
+        fs::remove_dir_all(&dir).unwrap();
+
+        let obj = obj.unwrap();
+        assert_eq!(obj.materials.len(), 4);
+        assert!(obj.materials["lamp"].is_emissive());
+        assert!(obj.materials["glass"].is_specular() && !obj.materials["glass"].is_emissive());
+        assert!(obj.materials["chrome"].is_specular());
+        assert!(!obj.materials["paint"].is_specular());
+        assert_eq!(obj.files, [dir.join("materials.mtl")]);
+        assert_eq!(obj.meshes.len(), 2);
+        assert_eq!(obj.meshes[0].material.as_deref(), Some("lamp"));
+        let lights = obj
+            .build(
+                Arc::new(Lambertian::new_from_color(Color::default())),
+                false,
+            )
+            .emitters();
+        assert_eq!(lights.len(), 1);
+
+        let err = broken.err().unwrap();
+        assert_eq!(
+            (err.line, err.message.as_str()),
+            (2, "expected 1 or 3 numbers")
+        );
+    }
+}
@@ -0,0 +1,46 @@
+use crate::Vec3;
+
+// orthonormal basis around a direction `w`, to turn directions sampled around the z axis into
+// directions around `w`
+#[derive(Debug, Clone)]
+pub struct Onb {
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl Onb {
+    pub fn new(w: &Vec3) -> Self {
+        let w = w.make_unit_vector();
+        // any vector not parallel to w will do
+        let a = if w.x().abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = w.cross(&a).make_unit_vector();
+        let u = w.cross(&v);
+        Self { u, v, w }
+    }
+
+    // the direction with coordinates `a` in this basis
+    pub fn transform(&self, a: &Vec3) -> Vec3 {
+        &self.u * a.x() + &self.v * a.y() + &self.w * a.z()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_onb() {
+        let onb = Onb::new(&Vec3::new(1.0, 2.0, -2.0));
+        let w = onb.transform(&Vec3::new(0.0, 0.0, 3.0));
+        assert!((&w - &Vec3::new(1.0, 2.0, -2.0)).length() < 1e-12);
+        let u = onb.transform(&Vec3::new(1.0, 0.0, 0.0));
+        let v = onb.transform(&Vec3::new(0.0, 1.0, 0.0));
+        assert!((u.length() - 1.0).abs() < 1e-12 && (v.length() - 1.0).abs() < 1e-12);
+        assert!(u.dot(&v).abs() < 1e-12 && u.dot(&w).abs() < 1e-12);
+    }
+}
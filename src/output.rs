@@ -0,0 +1,572 @@
+use crate::aov::Aov;
+use crate::color::{to_rgb16, to_rgb8, write_color};
+use crate::framebuffer::Framebuffer;
+use crate::Color;
+use crate::Result;
+use image::codecs::hdr::HdrEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::{ColorType, ImageBuffer, ImageFormat, Rgb};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+const JPEG_QUALITY: u8 = 90;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Png8,
+    Png16,
+    Jpeg,
+    Ppm8,        // binary P6
+    Ppm16,       // binary P6 with 16 bit samples
+    Exr,         // linear 32 bit float, keeps values above 1.0
+    Pfm,         // linear 32 bit float, keeps values above 1.0
+    RadianceHdr, // linear shared-exponent RGBE, keeps values above 1.0
+}
+
+impl OutputFormat {
+    // pick the encoder from the file extension, `bit_depth` only matters for png and ppm
+    pub fn from_path<P: AsRef<Path>>(path: P, bit_depth: u8) -> Result<Self> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let format = match (extension.as_str(), bit_depth) {
+            ("png", 8) => OutputFormat::Png8,
+            ("png", 16) => OutputFormat::Png16,
+            ("ppm", 8) => OutputFormat::Ppm8,
+            ("ppm", 16) => OutputFormat::Ppm16,
+            ("jpg" | "jpeg", 8) => OutputFormat::Jpeg,
+            ("exr", _) => OutputFormat::Exr,
+            ("pfm", _) => OutputFormat::Pfm,
+            ("hdr", _) => OutputFormat::RadianceHdr,
+            ("png" | "ppm" | "jpg" | "jpeg", _) => {
+                return Err(format!(
+                    "bit depth {} is not supported for .{} files, use 8{}",
+                    bit_depth,
+                    extension,
+                    if extension.starts_with('p') { " or 16" } else { "" }
+                )
+                .into())
+            }
+            _ => {
+                return Err(format!(
+                    "cannot tell the image format of `{}`, use one of .png, .jpg, .ppm, .exr, .pfm, .hdr",
+                    path.display()
+                )
+                .into())
+            }
+        };
+        Ok(format)
+    }
+
+    // whether the format stores linear radiance rather than colors encoded for display
+    pub fn is_linear(&self) -> bool {
+        matches!(
+            self,
+            OutputFormat::Exr | OutputFormat::Pfm | OutputFormat::RadianceHdr
+        )
+    }
+}
+
+// encode the average radiance of every pixel of `framebuffer` into a file
+pub fn write_image<P: AsRef<Path>>(
+    path: P,
+    format: OutputFormat,
+    framebuffer: &Framebuffer,
+) -> Result<()> {
+    let path = path.as_ref();
+    let (width, height) = (framebuffer.width(), framebuffer.height());
+    let pixels = framebuffer.pixels();
+    match format {
+        OutputFormat::Png8 => {
+            let data = pixels.iter().flat_map(to_rgb8).collect();
+            let image = ImageBuffer::<Rgb<u8>, Vec<u8>>::from_raw(width, height, data).unwrap();
+            image.save_with_format(path, ImageFormat::Png)?;
+        }
+        OutputFormat::Png16 => {
+            let data = pixels.iter().flat_map(to_rgb16).collect();
+            let image = ImageBuffer::<Rgb<u16>, Vec<u16>>::from_raw(width, height, data).unwrap();
+            image.save_with_format(path, ImageFormat::Png)?;
+        }
+        OutputFormat::Jpeg => {
+            let data: Vec<u8> = pixels.iter().flat_map(to_rgb8).collect();
+            let mut writer = BufWriter::new(File::create(path)?);
+            JpegEncoder::new_with_quality(&mut writer, JPEG_QUALITY).encode(
+                &data,
+                width,
+                height,
+                ColorType::Rgb8,
+            )?;
+        }
+        OutputFormat::Ppm8 => {
+            let mut writer = BufWriter::new(File::create(path)?);
+            write!(writer, "P6\n{} {}\n255\n", width, height)?;
+            let data: Vec<u8> = pixels.iter().flat_map(to_rgb8).collect();
+            writer.write_all(&data)?;
+            writer.flush()?;
+        }
+        OutputFormat::Ppm16 => {
+            // 16 bit samples are stored most significant byte first
+            let mut writer = BufWriter::new(File::create(path)?);
+            write!(writer, "P6\n{} {}\n65535\n", width, height)?;
+            let data: Vec<u8> = pixels
+                .iter()
+                .flat_map(to_rgb16)
+                .flat_map(u16::to_be_bytes)
+                .collect();
+            writer.write_all(&data)?;
+            writer.flush()?;
+        }
+        OutputFormat::Exr => {
+            let data = pixels.iter().flat_map(to_rgb_f32).collect();
+            let image = ImageBuffer::<Rgb<f32>, Vec<f32>>::from_raw(width, height, data).unwrap();
+            image.save_with_format(path, ImageFormat::OpenExr)?;
+        }
+        OutputFormat::Pfm => {
+            // little endian (negative scale), rows are stored from the bottom up
+            let mut writer = BufWriter::new(File::create(path)?);
+            write!(writer, "PF\n{} {}\n-1.0\n", width, height)?;
+            for row in pixels.chunks(width as usize).rev() {
+                let data: Vec<u8> = row
+                    .iter()
+                    .flat_map(to_rgb_f32)
+                    .flat_map(f32::to_le_bytes)
+                    .collect();
+                writer.write_all(&data)?;
+            }
+            writer.flush()?;
+        }
+        OutputFormat::RadianceHdr => {
+            // shared exponents cannot store negative values
+            let data: Vec<Rgb<f32>> = pixels
+                .iter()
+                .map(|c| Rgb(to_rgb_f32(c).map(|x| x.max(0.0))))
+                .collect();
+            let writer = BufWriter::new(File::create(path)?);
+            HdrEncoder::new(writer).encode(&data, width as usize, height as usize)?;
+        }
+    }
+    Ok(())
+}
+
+// an OpenEXR file with the image in R, G and B and every pass as channels named after it, like
+// `depth.Z` or `normal.X`, which compositors show as layers. Passes keep negative values
+pub fn write_exr_layers<P: AsRef<Path>>(
+    path: P,
+    framebuffer: &Framebuffer,
+    aovs: &[(Aov, Framebuffer)],
+) -> Result<()> {
+    use exr::prelude::{AnyChannel, AnyChannels, Encoding, FlatSamples, Image, Layer};
+    use exr::prelude::{LayerAttributes, SmallVec, WritableImage};
+
+    let (width, height) = (framebuffer.width(), framebuffer.height());
+    let mut channels = SmallVec::new();
+    let beauty: Vec<[f32; 3]> = framebuffer.pixels().iter().map(to_rgb_f32).collect();
+    for (k, name) in ["R", "G", "B"].into_iter().enumerate() {
+        let samples = beauty.iter().map(|c| c[k]).collect();
+        channels.push(AnyChannel::new(name, FlatSamples::F32(samples)));
+    }
+    for (aov, buffer) in aovs {
+        if (buffer.width(), buffer.height()) != (width, height) {
+            return Err(format!("the {} pass does not match the image size", aov).into());
+        }
+        let pixels = buffer.pixels();
+        for (k, name) in aov.channels().iter().enumerate() {
+            let samples = pixels.iter().map(|c| c[k] as f32).collect();
+            let name = format!("{}.{}", aov, name);
+            channels.push(AnyChannel::new(name.as_str(), FlatSamples::F32(samples)));
+        }
+    }
+    let layer = Layer::new(
+        (width as usize, height as usize),
+        LayerAttributes::default(),
+        Encoding::FAST_LOSSLESS,
+        AnyChannels::sort(channels),
+    );
+    Image::from_layer(layer)
+        .write()
+        .to_file(path)
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// read a linear floating point image, `.exr`, `.pfm` or `.hdr`, into a framebuffer with one
+// sample per pixel
+pub fn read_image<P: AsRef<Path>>(path: P) -> Result<Framebuffer> {
+    let path = path.as_ref();
+    match OutputFormat::from_path(path, 8)? {
+        OutputFormat::Exr => Ok(read_exr_layers(path)?.0),
+        OutputFormat::Pfm => read_pfm(path),
+        OutputFormat::RadianceHdr => {
+            let image = image::open(path)?.to_rgb32f();
+            let mut framebuffer = Framebuffer::new(image.width(), image.height());
+            for (i, j, pixel) in image.enumerate_pixels() {
+                let [r, g, b] = pixel.0.map(|c| c as f64);
+                framebuffer.set_pixel(i, j, Color::new(r, g, b));
+            }
+            Ok(framebuffer)
+        }
+        _ => Err(format!(
+            "`{}` is not a linear image, use one of .exr, .pfm, .hdr",
+            path.display()
+        )
+        .into()),
+    }
+}
+
+// the image in the R, G and B channels of an OpenEXR file and the passes among its other channels,
+// as `write_exr_layers` names them
+pub fn read_exr_layers<P: AsRef<Path>>(path: P) -> Result<(Framebuffer, Vec<(Aov, Framebuffer)>)> {
+    let path = path.as_ref();
+    let image = exr::prelude::read_first_flat_layer_from_file(path).map_err(|e| e.to_string())?;
+    let size = image.layer_data.size;
+    let (width, height) = (size.0 as u32, size.1 as u32);
+    let mut framebuffer = None;
+    let mut aovs: Vec<(Aov, Framebuffer)> = Vec::new();
+    for channel in &image.layer_data.channel_data.list {
+        let name = channel.name.to_string();
+        // the image is the unnamed layer, and single channel passes fill all three components
+        let (target, components) = match name.split_once('.') {
+            None => {
+                let Some(k) = ["R", "G", "B"].iter().position(|c| *c == name) else {
+                    continue;
+                };
+                let buffer = framebuffer.get_or_insert_with(|| Framebuffer::new(width, height));
+                (buffer, k..k + 1)
+            }
+            Some((layer, channel)) => {
+                let Ok(aov) = layer.parse::<Aov>() else {
+                    continue;
+                };
+                let Some(k) = aov.channels().iter().position(|c| *c == channel) else {
+                    continue;
+                };
+                let index = match aovs.iter().position(|(a, _)| *a == aov) {
+                    Some(index) => index,
+                    None => {
+                        aovs.push((aov, Framebuffer::new(width, height)));
+                        aovs.len() - 1
+                    }
+                };
+                let components = if aov.channels().len() == 1 {
+                    0..3
+                } else {
+                    k..k + 1
+                };
+                (&mut aovs[index].1, components)
+            }
+        };
+        for (index, value) in channel.sample_data.values_as_f32().enumerate() {
+            let (i, j) = (index as u32 % width, index as u32 / width);
+            let mut color = target.pixel(i, j);
+            for k in components.clone() {
+                color[k] = value as f64;
+            }
+            target.set_pixel(i, j, color);
+        }
+    }
+    let framebuffer =
+        framebuffer.ok_or_else(|| format!("`{}` has no R, G and B channels", path.display()))?;
+    Ok((framebuffer, aovs))
+}
+
+// a Portable Float Map with three channels (`PF`) or one (`Pf`) in either byte order
+fn read_pfm(path: &Path) -> Result<Framebuffer> {
+    let bytes = std::fs::read(path)?;
+    let invalid = || format!("`{}` is not a valid PFM file", path.display());
+    // four whitespace separated header fields, then a single whitespace character
+    let mut fields = Vec::new();
+    let mut start = 0;
+    for (k, &byte) in bytes.iter().enumerate() {
+        if byte.is_ascii_whitespace() {
+            if k > start {
+                fields.push(String::from_utf8_lossy(&bytes[start..k]).to_string());
+            }
+            start = k + 1;
+            if fields.len() == 4 {
+                break;
+            }
+        }
+    }
+    let [kind, width, height, scale] = <[String; 4]>::try_from(fields).map_err(|_| invalid())?;
+    let channels = match kind.as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err(invalid().into()),
+    };
+    let (width, height, scale): (u32, u32, f32) =
+        match (width.parse(), height.parse(), scale.parse()) {
+            (Ok(width), Ok(height), Ok(scale)) => (width, height, scale),
+            _ => return Err(invalid().into()),
+        };
+    let data = &bytes[start..];
+    if data.len() < width as usize * height as usize * channels * 4 {
+        return Err(invalid().into());
+    }
+    let mut values = data.chunks_exact(4).map(|b| {
+        let b = [b[0], b[1], b[2], b[3]];
+        let value = if scale < 0.0 {
+            f32::from_le_bytes(b)
+        } else {
+            f32::from_be_bytes(b)
+        };
+        value as f64
+    });
+    // rows are stored from the bottom up
+    let mut framebuffer = Framebuffer::new(width, height);
+    for j in (0..height).rev() {
+        for i in 0..width {
+            let color = match channels {
+                3 => Color::new(
+                    values.next().unwrap_or_default(),
+                    values.next().unwrap_or_default(),
+                    values.next().unwrap_or_default(),
+                ),
+                _ => {
+                    let value = values.next().unwrap_or_default();
+                    Color::new(value, value, value)
+                }
+            };
+            framebuffer.set_pixel(i, j, color);
+        }
+    }
+    Ok(framebuffer)
+}
+
+// the path of the image of `aov` next to `path`, always a linear EXR whatever the format of the
+// image, e.g. `images/box_depth.exr` for `images/box.png`
+pub fn aov_path(path: &str, aov: Aov) -> String {
+    format!("{}_{}.exr", split_extension(path).0, aov)
+}
+
+// `path` with `suffix` added to the file name before the extension
+pub fn with_suffix(path: &str, suffix: &str) -> String {
+    let (stem, extension) = split_extension(path);
+    format!("{}{}{}", stem, suffix, extension)
+}
+
+// `path` up to the extension of the file name, and the extension with its dot
+fn split_extension(path: &str) -> (&str, &str) {
+    let file_start = path.rfind(['/', '\\']).map_or(0, |i| i + 1);
+    match path[file_start..].rfind('.') {
+        Some(dot) if dot > 0 => path.split_at(file_start + dot),
+        _ => (path, ""),
+    }
+}
+
+// the plain text P3 format, one pixel per line, as `Camera::render` has always written it
+pub fn write_ppm_ascii<W: Write>(writer: &mut W, framebuffer: &Framebuffer) -> Result<()> {
+    write!(
+        writer,
+        "P3\n{} {}\n255\n",
+        framebuffer.width(),
+        framebuffer.height()
+    )?;
+    for pixel_color in framebuffer.pixels() {
+        write_color(writer, &pixel_color)?;
+    }
+    Ok(())
+}
+
+// a YUV4MPEG2 video stream, one uncompressed 4:4:4 frame after another, that tools like ffmpeg
+// read directly
+pub struct Y4mWriter<W: Write> {
+    writer: W,
+    width: u32,
+    height: u32,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    // write the stream header, a fractional frame rate is kept to a thousandth of a frame
+    pub fn new(mut writer: W, width: u32, height: u32, fps: f64) -> Result<Self> {
+        let (numerator, denominator) = if fps.fract() == 0.0 {
+            (fps as u64, 1)
+        } else {
+            ((fps * 1000.0).round() as u64, 1000)
+        };
+        writeln!(
+            writer,
+            "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444",
+            width, height, numerator, denominator
+        )?;
+        Ok(Self {
+            writer,
+            width,
+            height,
+        })
+    }
+
+    // append a frame, converted to limited range BT.601 like other 8 bit video
+    pub fn write_frame(&mut self, framebuffer: &Framebuffer) -> Result<()> {
+        if (framebuffer.width(), framebuffer.height()) != (self.width, self.height) {
+            return Err("every frame of a video must have the same size".into());
+        }
+        let pixels = framebuffer.pixels();
+        let mut planes: [Vec<u8>; 3] = std::array::from_fn(|_| Vec::with_capacity(pixels.len()));
+        for pixel in &pixels {
+            let [r, g, b] = to_rgb8(pixel).map(|c| c as f64);
+            let y = 16.0 + (65.481 * r + 128.553 * g + 24.966 * b) / 255.0;
+            let u = 128.0 + (-37.797 * r - 74.203 * g + 112.0 * b) / 255.0;
+            let v = 128.0 + (112.0 * r - 93.786 * g - 18.214 * b) / 255.0;
+            for (plane, value) in planes.iter_mut().zip([y, u, v]) {
+                plane.push(value.round().clamp(0.0, 255.0) as u8);
+            }
+        }
+        self.writer.write_all(b"FRAME\n")?;
+        for plane in &planes {
+            self.writer.write_all(plane)?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+// linear components for the floating point formats, NaN values become 0. Negative values are only
+// kept for passes like the normals, radiance is never negative
+fn to_rgb_f32(c: &Color) -> [f32; 3] {
+    [c.x(), c.y(), c.z()].map(|x| if x.is_nan() { 0.0 } else { x as f32 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(
+            OutputFormat::from_path("a/b.png", 8).unwrap(),
+            OutputFormat::Png8
+        );
+        assert_eq!(
+            OutputFormat::from_path("b.PNG", 16).unwrap(),
+            OutputFormat::Png16
+        );
+        assert_eq!(
+            OutputFormat::from_path("b.jpeg", 8).unwrap(),
+            OutputFormat::Jpeg
+        );
+        assert_eq!(
+            OutputFormat::from_path("b.exr", 8).unwrap(),
+            OutputFormat::Exr
+        );
+        assert!(OutputFormat::from_path("b.jpg", 16).is_err());
+        assert!(OutputFormat::from_path("b.gif", 8).is_err());
+        assert!(OutputFormat::from_path("b", 8).is_err());
+    }
+
+    #[test]
+    fn test_y4m() {
+        let mut framebuffer = Framebuffer::new(3, 2);
+        framebuffer.set_pixel(0, 0, Color::new(1.0, 1.0, 1.0));
+        let mut data = Vec::new();
+        let mut video = Y4mWriter::new(&mut data, 3, 2, 23.976).unwrap();
+        video.write_frame(&framebuffer).unwrap();
+        video.write_frame(&framebuffer).unwrap();
+        assert!(video.write_frame(&Framebuffer::new(2, 2)).is_err());
+
+        let header = "YUV4MPEG2 W3 H2 F23976:1000 Ip A1:1 C444\n";
+        let frame = 6 + 3 * 6;
+        assert_eq!(data.len(), header.len() + 2 * frame);
+        assert!(data.starts_with(header.as_bytes()));
+        // white and black at the ends of the limited luma range, neutral chroma
+        let planes = &data[header.len() + 6..header.len() + frame];
+        assert_eq!(
+            planes,
+            [235, 16, 16, 16, 16, 16, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128]
+        );
+    }
+
+    #[test]
+    fn test_exr_layers() {
+        let dir = std::env::temp_dir().join(format!("raytracing_layers_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut pixels = Framebuffer::new(2, 1);
+        pixels.set_pixel(1, 0, Color::new(0.5, 0.25, 2.0));
+        let mut depth = Framebuffer::new(2, 1);
+        depth.set_pixel(0, 0, Color::new(3.0, 3.0, 3.0));
+        let mut normal = Framebuffer::new(2, 1);
+        normal.set_pixel(1, 0, Color::new(0.0, -1.0, 0.0));
+
+        let path = dir.join("layers.exr");
+        let aovs = [(Aov::Depth, depth), (Aov::Normal, normal)];
+        write_exr_layers(&path, &pixels, &aovs).unwrap();
+        let image = exr::prelude::read_first_flat_layer_from_file(&path).unwrap();
+        let channels = &image.layer_data.channel_data.list;
+        let names: Vec<String> = channels.iter().map(|c| c.name.to_string()).collect();
+        assert_eq!(
+            names,
+            ["B", "G", "R", "depth.Z", "normal.X", "normal.Y", "normal.Z"]
+        );
+        let values: Vec<Vec<f32>> = channels
+            .iter()
+            .map(|c| c.sample_data.values_as_f32().collect())
+            .collect();
+        assert_eq!(values[0], [0.0, 2.0]);
+        assert_eq!(values[3], [3.0, 0.0]);
+        // passes keep negative values
+        assert_eq!(values[5], [0.0, -1.0]);
+        let (image, layers) = read_exr_layers(&path).unwrap();
+        assert_eq!(image.pixel(1, 0), Color::new(0.5, 0.25, 2.0));
+        assert_eq!(layers[0].0, Aov::Depth);
+        assert_eq!(layers[0].1.pixel(0, 0), Color::new(3.0, 3.0, 3.0));
+        assert_eq!(layers[1].1.pixel(1, 0), Color::new(0.0, -1.0, 0.0));
+
+        let too_small = [(Aov::Uv, Framebuffer::new(1, 1))];
+        assert!(write_exr_layers(&path, &pixels, &too_small).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            aov_path("images/box.exr", Aov::Depth),
+            "images/box_depth.exr"
+        );
+        assert_eq!(
+            aov_path("a.b/box.png", Aov::ObjectId),
+            "a.b/box_object_id.exr"
+        );
+        assert_eq!(aov_path("a.b/box", Aov::Uv), "a.b/box_uv.exr");
+    }
+
+    #[test]
+    fn test_write_and_read_back() {
+        let dir = std::env::temp_dir().join(format!("raytracing_output_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // one bright pixel above the displayable range and one dark grey pixel, which the sRGB
+        // transfer function encodes as 137
+        let mut pixels = Framebuffer::new(2, 1);
+        pixels.set_pixel(0, 0, Color::new(4.0, 0.0, 1.0));
+        pixels.add_samples(1, 0, &Color::new(1.0, 1.0, 1.0), 4);
+
+        let png = dir.join("t.png");
+        write_image(&png, OutputFormat::Png8, &pixels).unwrap();
+        let image = image::open(&png).unwrap().to_rgb8();
+        assert_eq!(image.get_pixel(0, 0).0, [255, 0, 255]);
+        assert_eq!(image.get_pixel(1, 0).0, [137, 137, 137]);
+
+        let exr = dir.join("t.exr");
+        write_image(&exr, OutputFormat::Exr, &pixels).unwrap();
+        let image = image::open(&exr).unwrap().to_rgb32f();
+        assert_eq!(image.get_pixel(0, 0).0, [4.0, 0.0, 1.0]);
+
+        let ppm = dir.join("t.ppm");
+        write_image(&ppm, OutputFormat::Ppm8, &pixels).unwrap();
+        let bytes = fs::read(&ppm).unwrap();
+        assert_eq!(&bytes[..11], b"P6\n2 1\n255\n");
+        assert_eq!(&bytes[11..], &[255, 0, 255, 137, 137, 137]);
+
+        let pfm = dir.join("t.pfm");
+        write_image(&pfm, OutputFormat::Pfm, &pixels).unwrap();
+        let bytes = fs::read(&pfm).unwrap();
+        assert_eq!(&bytes[..12], b"PF\n2 1\n-1.0\n");
+        assert_eq!(&bytes[12..16], &4.0f32.to_le_bytes());
+        for path in [&pfm, &exr] {
+            let image = read_image(path).unwrap();
+            assert_eq!(image.pixels(), pixels.pixels());
+        }
+        assert!(read_image(&png).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
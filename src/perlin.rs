@@ -1,5 +1,4 @@
-use crate::utils::*;
-use crate::{Point3, Vec3};
+use crate::{Point3, Sampler, Vec3};
 
 #[derive(Debug)]
 pub struct Perlin {
@@ -10,23 +9,16 @@ pub struct Perlin {
 }
 
 static POINT_COUNT: usize = 256;
-impl Default for Perlin {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
 impl Perlin {
-    pub fn new() -> Self {
+    pub fn new(sampler: &mut dyn Sampler) -> Self {
         let ranvec = (0..POINT_COUNT)
-            .into_iter()
-            .map(|_| Vec3::random(0.0, 1.0))
+            .map(|_| Vec3::random(0.0, 1.0, sampler))
             .collect::<Vec<Vec3>>();
         Self {
             ranvec,
-            perm_x: Self::perlin_generate_perm(),
-            perm_y: Self::perlin_generate_perm(),
-            perm_z: Self::perlin_generate_perm(),
+            perm_x: Self::perlin_generate_perm(sampler),
+            perm_y: Self::perlin_generate_perm(sampler),
+            perm_z: Self::perlin_generate_perm(sampler),
         }
     }
 
@@ -41,6 +33,7 @@ impl Perlin {
         }
         accum.abs()
     }
+    #[allow(clippy::needless_range_loop)]
     pub fn noise(&self, p: &Point3) -> f64 {
         let u = p.x() - p.x().floor();
         let v = p.y() - p.y().floor();
@@ -66,6 +59,7 @@ impl Perlin {
         Self::trilinear_interp(c, u, v, w)
     }
 
+    #[allow(clippy::needless_range_loop)]
     fn trilinear_interp(c: Vec<Vec<Vec<Vec3>>>, u: f64, v: f64, w: f64) -> f64 {
         let uu = u * u * (3.0 - 2.0 * u);
         let vv = v * v * (3.0 - 2.0 * v);
@@ -84,7 +78,7 @@ impl Perlin {
         }
         accum
     }
-    fn perlin_generate_perm() -> Vec<i32> {
+    fn perlin_generate_perm(sampler: &mut dyn Sampler) -> Vec<i32> {
         let mut p = vec![0; POINT_COUNT];
         for i in 0..POINT_COUNT {
             if let Some(elem) = p.get_mut(i) {
@@ -92,12 +86,12 @@ impl Perlin {
             }
         }
 
-        Self::permute(&mut p, POINT_COUNT);
+        Self::permute(&mut p, POINT_COUNT, sampler);
         p
     }
-    fn permute(p: &mut Vec<i32>, n: usize) {
+    fn permute(p: &mut [i32], n: usize, sampler: &mut dyn Sampler) {
         for i in (1..n).rev() {
-            let target = random_range(0, i);
+            let target = sampler.random_usize(0, i);
             p.swap(i, target);
         }
     }
@@ -0,0 +1,193 @@
+// How the camera maps pixels to rays.
+//
+// The perspective projection is the thin lens camera set up by `vfov`, `focus_dist` and
+// `defocus_angle`. The others are pinhole cameras: an orthographic camera sends parallel rays from
+// a plane through `look_from`, an equidistant fisheye maps the angle from the view direction
+// linearly to the distance from the image center, an equirectangular panorama covers every
+// direction with longitude across and latitude down the image, and a cube map puts six 90 degree
+// views side by side.
+use crate::utils::{degrees_to_radians, PI};
+use std::fmt;
+use std::str::FromStr;
+
+pub const DEFAULT_FISHEYE_FOV: f64 = 180.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Projection {
+    #[default]
+    Perspective,
+    Orthographic {
+        height: Option<f64>, // of the view in scene units, by default what `vfov` sees at `look_at`
+    },
+    Fisheye {
+        fov: f64, // in degrees, across the image circle
+    },
+    Equirectangular,
+    CubeMap, // right, left, up, down, front and back faces from left to right
+}
+
+pub const PROJECTION_NAMES: [&str; 5] = [
+    "perspective",
+    "orthographic",
+    "fisheye",
+    "equirectangular",
+    "cube_map",
+];
+
+// the camera relative direction of a face of a cube map: forward, right and up, each as
+// (right, up, forward) components
+const CUBE_FACES: [[[f64; 3]; 3]; 6] = [
+    [[1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, 1.0, 0.0]],
+    [[-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]],
+    [[0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]],
+    [[0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]],
+    [[0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+    [[0.0, 0.0, -1.0], [-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+];
+
+impl Projection {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Projection::Perspective => "perspective",
+            Projection::Orthographic { .. } => "orthographic",
+            Projection::Fisheye { .. } => "fisheye",
+            Projection::Equirectangular => "equirectangular",
+            Projection::CubeMap => "cube_map",
+        }
+    }
+
+    // the aspect ratio that fits the whole projection, if it needs one
+    pub fn aspect_ratio(&self) -> Option<f64> {
+        match self {
+            Projection::Equirectangular => Some(2.0),
+            Projection::CubeMap => Some(6.0),
+            _ => None,
+        }
+    }
+
+    // the direction of the ray through image position (x, y), in pixels from the top left of a
+    // width by height image, as (right, up, forward) components in the camera frame. None where
+    // the projection does not cover the image, like outside a fisheye's image circle. Only for
+    // the projections that do not place rays with the viewport of the thin lens camera
+    pub fn direction(&self, x: f64, y: f64, width: f64, height: f64) -> Option<[f64; 3]> {
+        match *self {
+            Projection::Perspective | Projection::Orthographic { .. } => None,
+            Projection::Fisheye { fov } => {
+                let radius = width.min(height) / 2.0;
+                let (dx, dy) = ((x - width / 2.0) / radius, (height / 2.0 - y) / radius);
+                let r = (dx * dx + dy * dy).sqrt();
+                if r > 1.0 {
+                    return None;
+                }
+                let theta = r * degrees_to_radians(fov) / 2.0;
+                let phi = dy.atan2(dx);
+                Some([
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                ])
+            }
+            Projection::Equirectangular => {
+                let longitude = (x / width - 0.5) * 2.0 * PI;
+                let latitude = (0.5 - y / height) * PI;
+                Some([
+                    latitude.cos() * longitude.sin(),
+                    latitude.sin(),
+                    latitude.cos() * longitude.cos(),
+                ])
+            }
+            Projection::CubeMap => {
+                let face_width = width / 6.0;
+                let face = ((x / face_width) as usize).min(5);
+                let a = (x - face as f64 * face_width) / face_width * 2.0 - 1.0;
+                let b = 1.0 - y / height * 2.0;
+                let [forward, right, up] = CUBE_FACES[face];
+                Some([0, 1, 2].map(|k| forward[k] + a * right[k] + b * up[k]))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Projection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Projection::Orthographic {
+                height: Some(height),
+            } => write!(f, "orthographic, {} high", height),
+            Projection::Fisheye { fov } => write!(f, "fisheye, {} degrees", fov),
+            _ => f.write_str(self.name()),
+        }
+    }
+}
+
+impl FromStr for Projection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "perspective" => Ok(Projection::Perspective),
+            "orthographic" => Ok(Projection::Orthographic { height: None }),
+            "fisheye" => Ok(Projection::Fisheye {
+                fov: DEFAULT_FISHEYE_FOV,
+            }),
+            "equirectangular" => Ok(Projection::Equirectangular),
+            "cube_map" => Ok(Projection::CubeMap),
+            _ => Err(format!(
+                "unknown projection `{}`, expected one of: {}",
+                s,
+                PROJECTION_NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_direction(projection: Projection, x: f64, y: f64, expected: [f64; 3]) {
+        let d = projection.direction(x, y, 600.0, 100.0).unwrap();
+        let length = (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt();
+        for k in 0..3 {
+            assert!((d[k] / length - expected[k]).abs() < 1e-9, "{:?}", d);
+        }
+    }
+
+    #[test]
+    fn test_projections() {
+        for name in PROJECTION_NAMES {
+            assert_eq!(name.parse::<Projection>().unwrap().name(), name);
+        }
+        assert!("stereographic".parse::<Projection>().is_err());
+
+        // the image center looks forward, the edges of a panorama look back
+        let panorama = Projection::Equirectangular;
+        assert_direction(panorama, 300.0, 50.0, [0.0, 0.0, 1.0]);
+        assert_direction(panorama, 0.0, 50.0, [0.0, 0.0, -1.0]);
+        assert_direction(panorama, 450.0, 50.0, [1.0, 0.0, 0.0]);
+        assert_direction(panorama, 300.0, 0.0, [0.0, 1.0, 0.0]);
+
+        // a 180 degree fisheye sees sideways at the rim of its circle and nothing outside it
+        let fisheye = Projection::Fisheye { fov: 180.0 };
+        assert_direction(fisheye, 300.0, 50.0, [0.0, 0.0, 1.0]);
+        assert_direction(fisheye, 350.0, 50.0, [1.0, 0.0, 0.0]);
+        assert_direction(fisheye, 300.0, 25.0, [0.0, 0.5f64.sqrt(), 0.5f64.sqrt()]);
+        assert!(fisheye.direction(360.0, 50.0, 600.0, 100.0).is_none());
+
+        // face centers of a cube map, and the left edges of the right and front faces
+        let cube = Projection::CubeMap;
+        let centers = [
+            [1.0, 0.0, 0.0],
+            [-1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, -1.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [0.0, 0.0, -1.0],
+        ];
+        for (face, center) in centers.into_iter().enumerate() {
+            assert_direction(cube, face as f64 * 100.0 + 50.0, 50.0, center);
+        }
+        assert_direction(cube, 0.0, 50.0, [0.5f64.sqrt(), 0.0, 0.5f64.sqrt()]);
+        assert_direction(cube, 400.0, 50.0, [-(0.5f64.sqrt()), 0.0, 0.5f64.sqrt()]);
+    }
+}
@@ -1,6 +1,8 @@
 use crate::aabb::AABB;
+use crate::interval::Interval;
+use crate::utils::INFINITY;
 use crate::Hittables;
-use crate::{HitRecord, Hittable, Material, Point3, Ray, Vec3};
+use crate::{HitRecord, Hittable, Material, Point3, Ray, Sampler, Vec3};
 use std::sync::Arc;
 
 pub struct Quad {
@@ -12,6 +14,7 @@ pub struct Quad {
     d: f64,       // distance from origin to the quad along normal
     bbox: AABB,
     w: Vec3,
+    area: f64,
 }
 // box is a reserved keyword for rust
 pub fn create_box(a: Point3, b: Point3, material: Arc<dyn Material>) -> Arc<Hittables> {
@@ -79,21 +82,23 @@ impl Quad {
             d,
             bbox,
             w,
+            area: n.length(),
         }
     }
 
     // if 0<=a<=1 and 0 <=b<=1 then it is internal, otherwise false
     fn is_interior(a: f64, b: f64) -> bool {
-        if a < 0.0 || a > 1.0 || b < 0.0 || b > 1.0 {
-            false
-        } else {
-            true
-        }
+        (0.0..=1.0).contains(&a) && (0.0..=1.0).contains(&b)
     }
 }
 
 impl Hittable for Quad {
-    fn hit(&self, ray: &Ray, ray_t: &mut crate::interval::Interval) -> Option<HitRecord> {
+    fn hit(
+        &self,
+        ray: &Ray,
+        ray_t: &mut crate::interval::Interval,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<HitRecord> {
         let denom = self.normal.dot(&ray.dir);
         // no hit if the ray is parallel to the plane
         if denom.abs() < 1e-8 {
@@ -116,7 +121,7 @@ impl Hittable for Quad {
         let mut hit_record = HitRecord::new(ray.at(t), self.material.clone(), t, 0.0, 0.0);
         hit_record.u = alpha;
         hit_record.v = beta;
-        hit_record.set_face_normal(&ray, &self.normal);
+        hit_record.set_face_normal(ray, &self.normal);
 
         Some(hit_record)
     }
@@ -124,4 +129,23 @@ impl Hittable for Quad {
     fn bounding_box(&self) -> AABB {
         self.bbox.clone()
     }
+
+    fn is_emitter(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    // uniform over the area, converted to solid angle
+    fn pdf_value(&self, ray: &Ray, sampler: &mut dyn Sampler) -> f64 {
+        let Some(rec) = self.hit(ray, &mut Interval::new(0.001, INFINITY), sampler) else {
+            return 0.0;
+        };
+        let distance_squared = rec.t * rec.t * ray.dir.length_squared();
+        let cosine = (ray.dir.dot(&self.normal) / ray.dir.length()).abs();
+        distance_squared / (cosine * self.area)
+    }
+
+    fn random_direction(&self, origin: &Point3, _time: f64, sampler: &mut dyn Sampler) -> Vec3 {
+        let (a, b) = sampler.get_2d();
+        &(&self.q + &(&self.u * a + &self.v * b)) - origin
+    }
 }
@@ -0,0 +1,346 @@
+use crate::low_discrepancy::{self, PRIMES, SOBOL_DIMENSIONS};
+use crate::traits::Sampler;
+use std::fmt;
+use std::str::FromStr;
+
+// dimensions the camera takes for the pixel position, the lens position and the time
+const CAMERA_DIMENSIONS: u32 = 5;
+// dimensions set aside for each bounce
+const BOUNCE_DIMENSIONS: u32 = 8;
+
+// independent uniform random numbers from a SplitMix64 generator, which is small, fast and gives
+// the same sequence on every platform
+#[derive(Debug, Clone)]
+pub struct RandomSampler {
+    state: u64,
+}
+
+impl RandomSampler {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    // generator for one sample of one pixel, so the result does not depend on which thread
+    // renders the pixel or in which order the samples are taken
+    pub fn for_sample(seed: u64, pixel_index: u64, sample_index: u64) -> Self {
+        Self::new(mix(mix(mix(seed) ^ pixel_index) ^ sample_index))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        mix(self.state)
+    }
+}
+
+impl Sampler for RandomSampler {
+    fn get_1d(&mut self) -> f64 {
+        // the top 53 bits fill the mantissa of a float in [0, 1)
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+// how the samples of a pixel are distributed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SamplerKind {
+    #[default]
+    Random, // independent uniform random numbers
+    Stratified, // one jittered sample per stratum, strata visited in random order
+    Halton,     // Halton sequence, digits scrambled per pixel
+    Sobol,      // Sobol sequence, scrambled per pixel
+    BlueNoise,  // rank-1 lattice offset by a blue-noise mask, so errors look like fine grain
+}
+
+pub const SAMPLER_KINDS: [SamplerKind; 5] = [
+    SamplerKind::Random,
+    SamplerKind::Stratified,
+    SamplerKind::Halton,
+    SamplerKind::Sobol,
+    SamplerKind::BlueNoise,
+];
+
+impl SamplerKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SamplerKind::Random => "random",
+            SamplerKind::Stratified => "stratified",
+            SamplerKind::Halton => "halton",
+            SamplerKind::Sobol => "sobol",
+            SamplerKind::BlueNoise => "blue-noise",
+        }
+    }
+}
+
+impl fmt::Display for SamplerKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for SamplerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SAMPLER_KINDS
+            .into_iter()
+            .find(|kind| kind.name() == s)
+            .ok_or_else(|| format!("unknown sampler `{}`", s))
+    }
+}
+
+// the numbers of one sample of one pixel, dimension by dimension. Dimensions that a sequence
+// does not cover fall back to independent random numbers
+#[derive(Debug, Clone)]
+pub struct PixelSampler {
+    kind: SamplerKind,
+    seed: u64,
+    pixel: (u32, u32),
+    pixel_index: u64,
+    sample_index: u32,
+    samples_per_pixel: u32, // the strata of the stratified sampler are laid out for this many
+    dimension: u32,
+    bounce_end: u32, // first dimension past the budget of the current bounce
+    random: RandomSampler,
+}
+
+impl PixelSampler {
+    pub fn new(
+        kind: SamplerKind,
+        seed: u64,
+        pixel: (u32, u32),
+        image_width: u32,
+        sample_index: u32,
+        samples_per_pixel: u32,
+    ) -> Self {
+        let pixel_index = pixel.1 as u64 * image_width as u64 + pixel.0 as u64;
+        Self {
+            kind,
+            seed,
+            pixel,
+            pixel_index,
+            sample_index,
+            samples_per_pixel: samples_per_pixel.max(1),
+            dimension: 0,
+            bounce_end: CAMERA_DIMENSIONS,
+            random: RandomSampler::for_sample(seed, pixel_index, sample_index as u64),
+        }
+    }
+
+    // random number fixed for this pixel and dimension, the same for all samples
+    fn pixel_hash(&self, dimension: u32, round: u32) -> u64 {
+        let dimension_round = ((round as u64) << 32) | dimension as u64;
+        mix(mix(mix(self.seed) ^ self.pixel_index) ^ dimension_round)
+    }
+
+    // `strata` strata visited in a random order per pixel, dimension and round of `strata`
+    // samples: the stratum of the current sample
+    fn stratum(&self, dimension: u32, strata: u32) -> u32 {
+        let round = self.sample_index / strata;
+        let seed = self.pixel_hash(dimension, round) as u32;
+        low_discrepancy::permutation_element(self.sample_index % strata, strata, seed)
+    }
+
+    // offset into the blue-noise mask, different for every dimension
+    fn blue_noise(&self, dimension: u32) -> f64 {
+        let offset = self.pixel_hash(dimension, 0) ^ mix(self.seed);
+        let x = self.pixel.0.wrapping_add(offset as u32);
+        let y = self.pixel.1.wrapping_add((offset >> 32) as u32);
+        low_discrepancy::blue_noise(x, y)
+    }
+
+    // a number past the budget of the bounce, hashed from the bounce and the draw so it does not
+    // take a dimension of the next bounce
+    fn extra_dimension(&self, dimension: u32) -> f64 {
+        let draw = ((self.bounce_end as u64) << 32) | (dimension - self.bounce_end) as u64;
+        let sample = mix(mix(mix(self.seed) ^ self.pixel_index) ^ self.sample_index as u64);
+        RandomSampler::new(mix(sample ^ draw)).get_1d()
+    }
+}
+
+impl Sampler for PixelSampler {
+    fn get_1d(&mut self) -> f64 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        match self.kind {
+            SamplerKind::Random => self.random.get_1d(),
+            _ if dimension >= self.bounce_end => self.extra_dimension(dimension),
+            SamplerKind::Stratified => {
+                let stratum = self.stratum(dimension, self.samples_per_pixel);
+                (stratum as f64 + self.random.get_1d()) / self.samples_per_pixel as f64
+            }
+            SamplerKind::Halton if (dimension as usize) < PRIMES.len() => {
+                low_discrepancy::scrambled_radical_inverse(
+                    PRIMES[dimension as usize],
+                    self.sample_index as u64,
+                    self.pixel_hash(dimension, 0),
+                )
+            }
+            SamplerKind::Sobol if (dimension as usize) < SOBOL_DIMENSIONS => {
+                let value = low_discrepancy::sobol(self.sample_index, dimension as usize)
+                    ^ self.pixel_hash(dimension, 0) as u32;
+                value as f64 / (1u64 << 32) as f64
+            }
+            SamplerKind::BlueNoise => {
+                // golden ratio sequence
+                let step = 0.618_033_988_749_894_9 * self.sample_index as f64;
+                fract(self.blue_noise(dimension) + step)
+            }
+            SamplerKind::Halton | SamplerKind::Sobol => self.random.get_1d(),
+        }
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        match self.kind {
+            _ if self.dimension + 2 > self.bounce_end => (self.get_1d(), self.get_1d()),
+            SamplerKind::Stratified => {
+                let dimension = self.dimension;
+                self.dimension += 2;
+                let n = (self.samples_per_pixel as f64).sqrt() as u32;
+                let stratum = self.stratum(dimension, n * n);
+                let x = (stratum % n) as f64 + self.random.get_1d();
+                let y = (stratum / n) as f64 + self.random.get_1d();
+                (x / n as f64, y / n as f64)
+            }
+            SamplerKind::BlueNoise => {
+                let dimension = self.dimension;
+                self.dimension += 2;
+                // R2 sequence, the two dimensional golden ratio sequence
+                let i = self.sample_index as f64;
+                (
+                    fract(self.blue_noise(dimension) + 0.754_877_666_246_692_7 * i),
+                    fract(self.blue_noise(dimension + 1) + 0.569_840_290_998_053_3 * i),
+                )
+            }
+            _ => (self.get_1d(), self.get_1d()),
+        }
+    }
+
+    fn start_bounce(&mut self, bounce: u32) {
+        self.dimension = CAMERA_DIMENSIONS + bounce * BOUNCE_DIMENSIONS;
+        self.bounce_end = self.dimension + BOUNCE_DIMENSIONS;
+    }
+}
+
+fn fract(x: f64) -> f64 {
+    x - x.floor()
+}
+
+// the SplitMix64 output function, a bijection that scrambles all bits
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_sequence() {
+        let mut a = RandomSampler::new(42);
+        let mut b = RandomSampler::new(42);
+        let mut c = RandomSampler::new(43);
+        let xs: Vec<f64> = (0..100).map(|_| a.get_1d()).collect();
+        let ys: Vec<f64> = (0..100).map(|_| b.get_1d()).collect();
+        let zs: Vec<f64> = (0..100).map(|_| c.get_1d()).collect();
+        assert_eq!(xs, ys);
+        assert_ne!(xs, zs);
+        assert!(xs.iter().all(|x| (0.0..1.0).contains(x)));
+    }
+
+    #[test]
+    fn test_known_values() {
+        // reference output of SplitMix64 seeded with 0
+        let mut s = RandomSampler::new(0);
+        assert_eq!(s.next_u64(), 0xe220_a839_7b1d_cdaf);
+        assert_eq!(s.next_u64(), 0x6e78_9e6a_a1b9_65f4);
+    }
+
+    #[test]
+    fn test_per_sample_streams_differ() {
+        let first = |pixel, sample| RandomSampler::for_sample(1, pixel, sample).get_1d();
+        assert_eq!(first(5, 7), first(5, 7));
+        assert_ne!(first(5, 7), first(7, 5));
+        assert_ne!(first(5, 7), first(5, 8));
+    }
+
+    fn samples_2d(kind: SamplerKind, spp: u32) -> Vec<(f64, f64)> {
+        (0..spp)
+            .map(|s| PixelSampler::new(kind, 1, (3, 4), 10, s, spp).get_2d())
+            .collect()
+    }
+
+    #[test]
+    fn test_pixel_samplers() {
+        for kind in SAMPLER_KINDS {
+            assert_eq!(kind.name().parse::<SamplerKind>(), Ok(kind));
+            let mut sampler = PixelSampler::new(kind, 0, (1, 2), 8, 5, 16);
+            for bounce in 0..4 {
+                sampler.start_bounce(bounce);
+                for _ in 0..BOUNCE_DIMENSIONS / 2 {
+                    let (x, y) = sampler.get_2d();
+                    let z = sampler.get_1d();
+                    assert!([x, y, z].iter().all(|v| (0.0..1.0).contains(v)), "{}", kind);
+                }
+            }
+        }
+        // a bounce gets the same dimensions however many numbers the previous one took
+        for kind in [
+            SamplerKind::Halton,
+            SamplerKind::Sobol,
+            SamplerKind::BlueNoise,
+        ] {
+            let mut a = PixelSampler::new(kind, 0, (1, 2), 8, 5, 16);
+            let mut b = a.clone();
+            a.get_2d();
+            b.get_1d();
+            a.start_bounce(1);
+            b.start_bounce(1);
+            assert_eq!(a.get_1d(), b.get_1d(), "{}", kind);
+        }
+        assert!("poisson".parse::<SamplerKind>().is_err());
+    }
+
+    #[test]
+    fn test_long_bounce_keeps_to_its_dimensions() {
+        // numbers past the budget of a bounce neither repeat nor take those of the next bounce
+        for kind in &SAMPLER_KINDS[1..] {
+            let kind = *kind;
+            let mut sampler = PixelSampler::new(kind, 0, (1, 2), 8, 5, 16);
+            sampler.start_bounce(1);
+            let mut draws: Vec<f64> = (0..3 * BOUNCE_DIMENSIONS)
+                .map(|_| sampler.get_1d())
+                .collect();
+            let (x, y) = sampler.get_2d();
+            sampler.start_bounce(2);
+            draws.extend((0..BOUNCE_DIMENSIONS).map(|_| sampler.get_1d()));
+            draws.extend([x, y]);
+            assert!(draws.iter().all(|v| (0.0..1.0).contains(v)), "{}", kind);
+            for (i, x) in draws.iter().enumerate() {
+                assert!(!draws[i + 1..].contains(x), "{}", kind);
+            }
+        }
+    }
+
+    #[test]
+    fn test_stratified_and_sobol_cover_the_pixel() {
+        // 16 samples put one point into each cell of a 4x4 grid
+        for kind in [SamplerKind::Stratified, SamplerKind::Sobol] {
+            let mut cells: Vec<u32> = samples_2d(kind, 16)
+                .iter()
+                .map(|(x, y)| (y * 4.0) as u32 * 4 + (x * 4.0) as u32)
+                .collect();
+            cells.sort();
+            assert_eq!(cells, (0..16).collect::<Vec<u32>>(), "{}", kind);
+        }
+    }
+
+    #[test]
+    fn test_random_usize() {
+        let mut s = RandomSampler::new(3);
+        for _ in 0..1000 {
+            let n = s.random_usize(2, 5);
+            assert!((2..5).contains(&n));
+        }
+    }
+}
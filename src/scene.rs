@@ -0,0 +1,1897 @@
+// Loader for the text scene format.
+//
+// A scene file is TOML with a `[camera]` table, named `[textures]` and `[materials]`, and a list
+// of `[[objects]]`. Every error is reported with the line of the offending value, e.g.
+// `scenes/cornell_box.toml:12: material `whte` is not defined`. See `scenes/` for examples.
+use crate::animation::{Animation, CameraKeyframe};
+use crate::aov::{Aov, IdMaterial, IdObject};
+use crate::aperture::{Aperture, ApertureMask};
+use crate::background::{Background, Environment, EnvironmentMap};
+use crate::bvh::{Bvh, BvhBuilder, BvhStats, BVH_BUILDERS};
+use crate::camera::Camera;
+use crate::checkpoint::Fingerprint;
+use crate::constant_medium::ConstantMedium;
+use crate::effects::{Bloom, Effects, StarGlare, Vignetting};
+use crate::hittables::Hittables;
+use crate::material::{Dielectric, DiffuseLight, Isotropic, Lambertian, Metal};
+use crate::obj::Obj;
+use crate::projection::Projection;
+use crate::quad::{self, Quad};
+use crate::shutter::Shutter;
+use crate::sphere::Sphere;
+use crate::texture::{CheckerTexture, ImageTexture, NoiseTexture, SolidColor};
+use crate::tonemap::WHITE_BALANCE_RANGE;
+use crate::traits::{Hittable, Material, Sampler, Texture};
+use crate::transform::{AnimatedTransform, Keyframe, Quaternion, Transform, TransformedHittable};
+use crate::triangle::{Mesh, Triangle, TriangleMesh};
+use crate::{Color, Vec3};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use toml_edit::{ImDocument, Item, TableLike, Value};
+
+// a world together with the camera set up to look at it
+pub struct Scene {
+    pub world: Hittables,
+    pub camera: Camera,
+    pub fingerprint: u64, // hash of the scene source and its files, to recognise it in checkpoints
+    pub bvh_reports: Vec<(String, BvhStats)>, // quality of the BVHs the scene asked for
+    pub animation: Option<Animation>, // frame rate and frames, for animated scenes
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SceneError {
+    pub file: String,
+    pub line: usize, // 1-based, 0 if the error is not about a particular line
+    pub message: String,
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}: {}", self.file, self.message)
+        } else {
+            write!(f, "{}:{}: {}", self.file, self.line, self.message)
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+type LoadResult<T> = std::result::Result<T, SceneError>;
+
+const CAMERA_KEYS: [&str; 33] = [
+    "aspect_ratio",
+    "image_width",
+    "samples_per_pixel",
+    "max_depth",
+    "vfov",
+    "look_from",
+    "look_at",
+    "vup",
+    "defocus_angle",
+    "focus_dist",
+    "background",
+    "sampler",
+    "shutter_open",
+    "shutter_close",
+    "shutter",
+    "shutter_exposure",
+    "keyframes",
+    "projection",
+    "ortho_height",
+    "fisheye_fov",
+    "aperture_blades",
+    "aperture_rotation",
+    "aperture_mask",
+    "cat_eye",
+    "tilt",
+    "tilt_rotation",
+    "aovs",
+    "exposure",
+    "white_balance",
+    "tone_map",
+    "bloom",
+    "star_glare",
+    "vignetting",
+];
+// applied to any object in this order: scale, rotations, translation, and then the animation
+const TRANSFORM_KEYS: [&str; 7] = [
+    "scale",
+    "rotate_x",
+    "rotate_y",
+    "rotate_z",
+    "rotate",
+    "translate",
+    "keyframes",
+];
+
+impl Scene {
+    // load a scene file, relative paths inside it are resolved against its directory. `sampler`
+    // provides the random numbers for noise textures and BVH construction. Objects and materials
+    // only carry the IDs of the ID passes if the camera of the scene renders one of them
+    pub fn load<P: AsRef<Path>>(path: P, sampler: &mut dyn Sampler) -> LoadResult<Scene> {
+        Self::load_scene(path.as_ref(), sampler, false)
+    }
+
+    // load a scene file whose objects and materials carry their IDs for the ID passes, for
+    // cameras given ID passes after loading
+    pub fn load_with_ids<P: AsRef<Path>>(path: P, sampler: &mut dyn Sampler) -> LoadResult<Scene> {
+        Self::load_scene(path.as_ref(), sampler, true)
+    }
+
+    // parse scene source text, `file` is only used in error messages
+    pub fn parse(
+        source: &str,
+        file: &str,
+        base_dir: &Path,
+        sampler: &mut dyn Sampler,
+    ) -> LoadResult<Scene> {
+        Self::parse_scene(source, file, base_dir, sampler, false)
+    }
+
+    // parse scene source text like `parse`, with IDs for the ID passes like `load_with_ids`
+    pub fn parse_with_ids(
+        source: &str,
+        file: &str,
+        base_dir: &Path,
+        sampler: &mut dyn Sampler,
+    ) -> LoadResult<Scene> {
+        Self::parse_scene(source, file, base_dir, sampler, true)
+    }
+
+    fn load_scene(path: &Path, sampler: &mut dyn Sampler, ids: bool) -> LoadResult<Scene> {
+        let source = fs::read_to_string(path).map_err(|e| SceneError {
+            file: path.display().to_string(),
+            line: 0,
+            message: e.to_string(),
+        })?;
+        let base_dir = path.parent().unwrap_or(Path::new("."));
+        Self::parse_scene(&source, &path.display().to_string(), base_dir, sampler, ids)
+    }
+
+    fn parse_scene(
+        source: &str,
+        file: &str,
+        base_dir: &Path,
+        sampler: &mut dyn Sampler,
+        ids: bool,
+    ) -> LoadResult<Scene> {
+        let loader = Loader {
+            source,
+            file,
+            base_dir,
+            sampler: RefCell::new(sampler),
+            textures: HashMap::new(),
+            materials: HashMap::new(),
+            fingerprint: RefCell::new(Fingerprint::default()),
+            bvh_reports: RefCell::new(Vec::new()),
+            material_ids: Cell::new(0),
+            ids,
+        };
+        loader.fingerprint.borrow_mut().add_bytes(source.as_bytes());
+        loader.load()
+    }
+}
+
+// a regular or inline table, with the position it starts at for error messages
+#[derive(Clone, Copy)]
+struct Table<'a> {
+    entries: &'a dyn TableLike,
+    pos: usize,
+}
+
+struct Loader<'a> {
+    source: &'a str,
+    file: &'a str,
+    base_dir: &'a Path,
+    sampler: RefCell<&'a mut dyn Sampler>,
+    textures: HashMap<String, Arc<dyn Texture>>,
+    materials: HashMap<String, Arc<dyn Material>>,
+    fingerprint: RefCell<Fingerprint>, // of the source and every file it reads
+    bvh_reports: RefCell<Vec<(String, BvhStats)>>,
+    material_ids: Cell<u32>, // the last ID given to a material
+    ids: bool,               // whether objects and materials are wrapped with their IDs
+}
+
+impl<'a> Loader<'a> {
+    fn load(mut self) -> LoadResult<Scene> {
+        let document = ImDocument::parse(self.source).map_err(|e| {
+            let pos = e.span().map(|span| span.start).unwrap_or(0);
+            self.error(pos, e.message().trim_end().replace('\n', ", "))
+        })?;
+        let root = Table {
+            entries: document.as_table(),
+            pos: 0,
+        };
+        self.check_keys(
+            root,
+            &[
+                "camera",
+                "animation",
+                "textures",
+                "materials",
+                "objects",
+                "bvh",
+            ],
+        )?;
+
+        let camera = match root.entries.get("camera") {
+            Some(item) => self.camera(self.table(item, root.pos, "camera")?)?,
+            None => return Err(self.error(0, "missing `[camera]` table")),
+        };
+        // the wrappers cost a call on every hit, so they are only added for the ID passes
+        self.ids |= camera.aovs.iter().any(Aov::is_id);
+        let animation = match root.entries.get("animation") {
+            Some(item) => {
+                Some(self.animation_settings(self.table(item, root.pos, "animation")?)?)
+            }
+            None => None,
+        };
+
+        if let Some(item) = root.entries.get("textures") {
+            let textures = self.table(item, root.pos, "textures")?;
+            for (name, item) in textures.entries.iter() {
+                let pos = self.pos_of(item, textures.pos);
+                let texture = self.texture(self.table(item, pos, "a texture")?)?;
+                self.textures.insert(name.to_string(), texture);
+            }
+        }
+
+        if let Some(item) = root.entries.get("materials") {
+            let materials = self.table(item, root.pos, "materials")?;
+            for (name, item) in materials.entries.iter() {
+                let pos = self.pos_of(item, materials.pos);
+                let material = self.material(self.table(item, pos, "a material")?)?;
+                self.materials.insert(name.to_string(), material);
+            }
+        }
+
+        let mut world = Hittables::default();
+        if let Some(item) = root.entries.get("objects") {
+            for (index, table) in self
+                .tables(item, root.pos, "objects")?
+                .into_iter()
+                .enumerate()
+            {
+                let object = self.object(table)?;
+                if self.ids {
+                    world.add(Arc::new(IdObject::new(object, index as u32 + 1)));
+                } else {
+                    world.add(object);
+                }
+            }
+        }
+        if let Some(builder) = self.bvh_field(root)? {
+            if !world.objects.is_empty() {
+                world = Hittables::new(self.bvh(&world, builder, "world".to_string()));
+            }
+        }
+        Ok(Scene {
+            world,
+            camera,
+            fingerprint: self.fingerprint.borrow().finish(),
+            bvh_reports: self.bvh_reports.into_inner(),
+            animation,
+        })
+    }
+
+    fn camera(&self, table: Table) -> LoadResult<Camera> {
+        self.check_keys(table, &CAMERA_KEYS)?;
+        let projection: Projection = match table.entries.get("projection") {
+            Some(item) => self
+                .required_str(table, "projection")?
+                .parse()
+                .map_err(|e: String| self.error(self.pos_of(item, table.pos), e))?,
+            None => Projection::default(),
+        };
+        // panoramas get the aspect ratio that fits them unless told otherwise
+        let mut camera = Camera::new(
+            self.f64_field(table, "aspect_ratio")?
+                .or(projection.aspect_ratio())
+                .unwrap_or(1.0),
+            self.positive_field(table, "image_width")?.unwrap_or(400),
+            self.positive_field(table, "samples_per_pixel")?
+                .unwrap_or(100),
+            self.positive_field(table, "max_depth")?.unwrap_or(50),
+            self.f64_field(table, "vfov")?.unwrap_or(90.0),
+        );
+        if let Some(look_from) = self.vec3_field(table, "look_from")? {
+            camera.look_from = look_from;
+        }
+        if let Some(look_at) = self.vec3_field(table, "look_at")? {
+            camera.look_at = look_at;
+        }
+        if let Some(vup) = self.vec3_field(table, "vup")? {
+            camera.vup = vup;
+        }
+        if let Some(defocus_angle) = self.f64_field(table, "defocus_angle")? {
+            camera.defocus_angle = defocus_angle;
+        }
+        if let Some(focus_dist) = self.f64_field(table, "focus_dist")? {
+            camera.focus_dist = focus_dist;
+        }
+        if let Some(blades) = self.positive_field::<u32>(table, "aperture_blades")? {
+            if blades < 3 {
+                let pos = self.pos_of(table.entries.get("aperture_blades").unwrap(), table.pos);
+                return Err(self.error(pos, "`aperture_blades` must be at least 3"));
+            }
+            camera.aperture = Aperture::Polygon {
+                blades,
+                rotation: self.f64_field(table, "aperture_rotation")?.unwrap_or(0.0),
+            };
+        } else if let Some(item) = table.entries.get("aperture_rotation") {
+            let pos = self.pos_of(item, table.pos);
+            return Err(self.error(pos, "`aperture_rotation` needs `aperture_blades`"));
+        }
+        if let Some(item) = table.entries.get("aperture_mask") {
+            let pos = self.pos_of(item, table.pos);
+            if table.entries.contains_key("aperture_blades") {
+                return Err(self.error(
+                    pos,
+                    "`aperture_mask` and `aperture_blades` exclude each other",
+                ));
+            }
+            let path = self
+                .base_dir
+                .join(self.required_str(table, "aperture_mask")?);
+            let mask = ApertureMask::open(&path).map_err(|e| {
+                self.error(
+                    pos,
+                    format!("cannot load aperture mask `{}`: {}", path.display(), e),
+                )
+            })?;
+            self.add_file(&path);
+            camera.aperture = Aperture::Mask(Arc::new(mask));
+        }
+        if let Some(cat_eye) = self.f64_field(table, "cat_eye")? {
+            if cat_eye < 0.0 {
+                let pos = self.pos_of(table.entries.get("cat_eye").unwrap(), table.pos);
+                return Err(self.error(pos, "`cat_eye` must not be negative"));
+            }
+            camera.cat_eye = cat_eye;
+        }
+        if let Some(tilt) = self.f64_field(table, "tilt")? {
+            if tilt.abs() >= 90.0 {
+                let pos = self.pos_of(table.entries.get("tilt").unwrap(), table.pos);
+                return Err(self.error(pos, "`tilt` must be between -90 and 90 degrees"));
+            }
+            camera.tilt = tilt;
+        }
+        if let Some(tilt_rotation) = self.f64_field(table, "tilt_rotation")? {
+            camera.tilt_rotation = tilt_rotation;
+        }
+        if let Some(item) = table.entries.get("background") {
+            camera.background = self.background(item, table.pos)?;
+        }
+        if let Some(item) = table.entries.get("sampler") {
+            let name = self.required_str(table, "sampler")?;
+            camera.sampler = name
+                .parse()
+                .map_err(|e: String| self.error(self.pos_of(item, table.pos), e))?;
+        }
+        if let Some(open) = self.f64_field(table, "shutter_open")? {
+            camera.shutter_open = open;
+        }
+        if let Some(close) = self.f64_field(table, "shutter_close")? {
+            camera.shutter_close = close;
+        }
+        if camera.shutter_close < camera.shutter_open {
+            let item = table.entries.get("shutter_close");
+            let pos = item.map_or(table.pos, |item| self.pos_of(item, table.pos));
+            return Err(self.error(pos, "`shutter_close` must not be before `shutter_open`"));
+        }
+        if let Some(item) = table.entries.get("shutter") {
+            let name = self.required_str(table, "shutter")?;
+            camera.shutter = name
+                .parse()
+                .map_err(|e: String| self.error(self.pos_of(item, table.pos), e))?;
+        }
+        if let Some(item) = table.entries.get("shutter_exposure") {
+            let pos = self.pos_of(item, table.pos);
+            let Shutter::Rolling { exposure } = &mut camera.shutter else {
+                return Err(self.error(pos, "`shutter_exposure` needs `shutter = \"rolling\"`"));
+            };
+            *exposure = self.f64(item, table.pos, "shutter_exposure")?;
+            if *exposure <= 0.0 || *exposure > 1.0 {
+                return Err(self.error(pos, "`shutter_exposure` must be in (0, 1]"));
+            }
+        }
+        camera.projection = projection;
+        if let Some(item) = table.entries.get("ortho_height") {
+            let pos = self.pos_of(item, table.pos);
+            let Projection::Orthographic { height } = &mut camera.projection else {
+                return Err(self.error(pos, "`ortho_height` needs `projection = \"orthographic\"`"));
+            };
+            let value = self.f64(item, table.pos, "ortho_height")?;
+            if value <= 0.0 {
+                return Err(self.error(pos, "`ortho_height` must be positive"));
+            }
+            *height = Some(value);
+        }
+        if let Some(item) = table.entries.get("fisheye_fov") {
+            let pos = self.pos_of(item, table.pos);
+            let Projection::Fisheye { fov } = &mut camera.projection else {
+                return Err(self.error(pos, "`fisheye_fov` needs `projection = \"fisheye\"`"));
+            };
+            *fov = self.f64(item, table.pos, "fisheye_fov")?;
+            if *fov <= 0.0 || *fov > 360.0 {
+                return Err(self.error(pos, "`fisheye_fov` must be in (0, 360]"));
+            }
+        }
+        if let Some(item) = table.entries.get("aovs") {
+            let pos = self.pos_of(item, table.pos);
+            let names = item
+                .as_array()
+                .and_then(|array| {
+                    array
+                        .iter()
+                        .map(|name| name.as_str())
+                        .collect::<Option<Vec<_>>>()
+                })
+                .ok_or_else(|| self.error(pos, "`aovs` must be a list of pass names"))?;
+            for name in names {
+                let aov = name.parse().map_err(|e: String| self.error(pos, e))?;
+                if !camera.aovs.contains(&aov) {
+                    camera.aovs.push(aov);
+                }
+            }
+        }
+        if let Some(exposure) = self.f64_field(table, "exposure")? {
+            camera.tone_mapping.exposure = exposure;
+        }
+        if let Some(white_balance) = self.f64_field(table, "white_balance")? {
+            if !WHITE_BALANCE_RANGE.contains(&white_balance) {
+                let pos = self.pos_of(table.entries.get("white_balance").unwrap(), table.pos);
+                return Err(self.error(
+                    pos,
+                    "`white_balance` must be a temperature from 1667 to 25000 kelvin",
+                ));
+            }
+            camera.tone_mapping.white_balance = white_balance;
+        }
+        if let Some(item) = table.entries.get("tone_map") {
+            let name = self.required_str(table, "tone_map")?;
+            camera.tone_mapping.tone_map = name
+                .parse()
+                .map_err(|e: String| self.error(self.pos_of(item, table.pos), e))?;
+        }
+        camera.effects = self.effects(table)?;
+        if let Some(item) = table.entries.get("keyframes") {
+            for frame in self.tables(item, table.pos, "keyframes")? {
+                self.check_keys(
+                    frame,
+                    &[
+                        "time",
+                        "look_from",
+                        "look_at",
+                        "vfov",
+                        "focus_dist",
+                        "defocus_angle",
+                    ],
+                )?;
+                camera.keyframes.push(CameraKeyframe {
+                    time: self.required_f64(frame, "time")?,
+                    look_from: self.vec3_field(frame, "look_from")?,
+                    look_at: self.vec3_field(frame, "look_at")?,
+                    vfov: self.f64_field(frame, "vfov")?,
+                    focus_dist: self.f64_field(frame, "focus_dist")?,
+                    defocus_angle: self.f64_field(frame, "defocus_angle")?,
+                });
+            }
+            camera.keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        }
+        if camera.aspect_ratio <= 0.0 {
+            return Err(self.error(table.pos, "`aspect_ratio` must be positive"));
+        }
+        Ok(camera)
+    }
+
+    // a color, or a table with an environment `map` and its `rotation` and `intensity`
+    fn background(&self, item: &Item, fallback: usize) -> LoadResult<Background> {
+        if item.as_table_like().is_none() {
+            return Ok(Background::Color(self.vec3(
+                item,
+                fallback,
+                "background",
+            )?));
+        }
+        let table = self.table(item, fallback, "`background`")?;
+        self.check_keys(table, &["map", "rotation", "intensity"])?;
+        let path = self.base_dir.join(self.required_str(table, "map")?);
+        let map = EnvironmentMap::open(&path).map_err(|e| {
+            self.error(
+                self.pos_of(table.entries.get("map").unwrap(), table.pos),
+                format!("cannot load environment map `{}`: {}", path.display(), e),
+            )
+        })?;
+        self.add_file(&path);
+        let mut environment = Environment::new(Arc::new(map));
+        if let Some(rotation) = self.f64_field(table, "rotation")? {
+            environment.rotation = rotation;
+        }
+        if let Some(intensity) = self.f64_field(table, "intensity")? {
+            if intensity < 0.0 {
+                let pos = self.pos_of(table.entries.get("intensity").unwrap(), table.pos);
+                return Err(self.error(pos, "`intensity` must not be negative"));
+            }
+            environment.intensity = intensity;
+        }
+        Ok(Background::Environment(environment))
+    }
+
+    // the lens effects in the `bloom`, `star_glare` and `vignetting` tables of the camera
+    fn effects(&self, camera: Table) -> LoadResult<Effects> {
+        // a number in an effect's table that `valid` accepts
+        let field = |table: Table, key: &str, valid: fn(f64) -> bool, expected: &str| match self
+            .f64_field(table, key)?
+        {
+            Some(value) if !valid(value) => {
+                let pos = self.pos_of(table.entries.get(key).unwrap(), table.pos);
+                Err(self.error(pos, format!("`{}` must be {}", key, expected)))
+            }
+            value => Ok(value),
+        };
+        let threshold = |table| field(table, "threshold", |x| x >= 0.0, "at least 0");
+        let strength = |table| field(table, "strength", |x| (0.0..=1.0).contains(&x), "in [0, 1]");
+
+        let mut effects = Effects::default();
+        if let Some(item) = camera.entries.get("bloom") {
+            let table = self.table(item, camera.pos, "`bloom`")?;
+            self.check_keys(table, &["threshold", "strength", "radius"])?;
+            let mut bloom = Bloom::default();
+            bloom.threshold = threshold(table)?.unwrap_or(bloom.threshold);
+            bloom.strength = strength(table)?.unwrap_or(bloom.strength);
+            bloom.radius = field(table, "radius", |x| x > 0.0, "positive")?.unwrap_or(bloom.radius);
+            effects.bloom = Some(bloom);
+        }
+        if let Some(item) = camera.entries.get("star_glare") {
+            let table = self.table(item, camera.pos, "`star_glare`")?;
+            self.check_keys(
+                table,
+                &["threshold", "strength", "spokes", "length", "rotation"],
+            )?;
+            let mut star_glare = StarGlare::default();
+            star_glare.threshold = threshold(table)?.unwrap_or(star_glare.threshold);
+            star_glare.strength = strength(table)?.unwrap_or(star_glare.strength);
+            star_glare.spokes = self
+                .positive_field(table, "spokes")?
+                .unwrap_or(star_glare.spokes);
+            star_glare.length =
+                field(table, "length", |x| x > 0.0, "positive")?.unwrap_or(star_glare.length);
+            star_glare.rotation = self
+                .f64_field(table, "rotation")?
+                .unwrap_or(star_glare.rotation);
+            effects.star_glare = Some(star_glare);
+        }
+        if let Some(item) = camera.entries.get("vignetting") {
+            let table = self.table(item, camera.pos, "`vignetting`")?;
+            self.check_keys(table, &["corner_angle"])?;
+            let mut vignetting = Vignetting::default();
+            let valid = |x| x > 0.0 && x < 90.0;
+            vignetting.corner_angle = field(table, "corner_angle", valid, "between 0 and 90")?
+                .unwrap_or(vignetting.corner_angle);
+            effects.vignetting = Some(vignetting);
+        }
+        Ok(effects)
+    }
+
+    fn animation_settings(&self, table: Table) -> LoadResult<Animation> {
+        self.check_keys(table, &["fps", "shutter_angle", "frames"])?;
+        let mut animation = Animation::default();
+        if let Some(fps) = self.f64_field(table, "fps")? {
+            if fps <= 0.0 {
+                let pos = self.pos_of(table.entries.get("fps").unwrap(), table.pos);
+                return Err(self.error(pos, "`fps` must be positive"));
+            }
+            animation.fps = fps;
+        }
+        if let Some(angle) = self.f64_field(table, "shutter_angle")? {
+            if !(0.0..=360.0).contains(&angle) {
+                let pos = self.pos_of(table.entries.get("shutter_angle").unwrap(), table.pos);
+                return Err(self.error(pos, "`shutter_angle` must be between 0 and 360"));
+            }
+            animation.shutter_angle = angle;
+        }
+        if let Some(item) = table.entries.get("frames") {
+            let frames = item
+                .as_array()
+                .filter(|array| array.len() == 2)
+                .and_then(|array| {
+                    let first = u32::try_from(array.get(0)?.as_integer()?).ok()?;
+                    let last = u32::try_from(array.get(1)?.as_integer()?).ok()?;
+                    (first <= last).then_some((first, last))
+                });
+            animation.frames = Some(frames.ok_or_else(|| {
+                self.error(
+                    self.pos_of(item, table.pos),
+                    "`frames` must be [first, last] with 0 <= first <= last",
+                )
+            })?);
+        }
+        Ok(animation)
+    }
+
+    fn texture(&self, table: Table) -> LoadResult<Arc<dyn Texture>> {
+        let kind = self.type_field(table)?;
+        let texture: Arc<dyn Texture> = match kind {
+            "solid" => {
+                self.check_keys(table, &["type", "color"])?;
+                Arc::new(SolidColor::new(self.required_vec3(table, "color")?))
+            }
+            "checker" => {
+                self.check_keys(table, &["type", "scale", "even", "odd"])?;
+                Arc::new(CheckerTexture::new(
+                    self.required_f64(table, "scale")?,
+                    self.texture_field(table, "even")?,
+                    self.texture_field(table, "odd")?,
+                ))
+            }
+            "image" => {
+                self.check_keys(table, &["type", "file"])?;
+                let file = self.required_str(table, "file")?;
+                let path = self.base_dir.join(file);
+                let texture = ImageTexture::new(&path).map_err(|e| {
+                    let pos = self.pos_of(table.entries.get("file").unwrap(), table.pos);
+                    self.error(
+                        pos,
+                        format!("cannot load image `{}`: {}", path.display(), e),
+                    )
+                })?;
+                self.add_file(&path);
+                Arc::new(texture)
+            }
+            "noise" => {
+                self.check_keys(table, &["type", "scale"])?;
+                {
+                    let scale = self.required_f64(table, "scale")?;
+                    Arc::new(NoiseTexture::new(scale, *self.sampler.borrow_mut()))
+                }
+            }
+            _ => {
+                return Err(self.unknown_type(table, kind, "solid, checker, image, noise"));
+            }
+        };
+        Ok(texture)
+    }
+
+    fn material(&self, table: Table) -> LoadResult<Arc<dyn Material>> {
+        let kind = self.type_field(table)?;
+        let material: Arc<dyn Material> = match kind {
+            "lambertian" => {
+                self.check_keys(table, &["type", "albedo"])?;
+                Arc::new(Lambertian::new(self.texture_field(table, "albedo")?))
+            }
+            "metal" => {
+                self.check_keys(table, &["type", "albedo", "fuzz"])?;
+                Arc::new(Metal::new(
+                    self.texture_field(table, "albedo")?,
+                    self.f64_field(table, "fuzz")?.unwrap_or(0.0),
+                ))
+            }
+            "dielectric" => {
+                self.check_keys(table, &["type", "ir"])?;
+                Arc::new(Dielectric::new(self.required_f64(table, "ir")?))
+            }
+            "diffuse_light" => {
+                self.check_keys(table, &["type", "emit"])?;
+                Arc::new(DiffuseLight::new(self.texture_field(table, "emit")?))
+            }
+            "isotropic" => {
+                self.check_keys(table, &["type", "albedo"])?;
+                Arc::new(Isotropic::new(self.texture_field(table, "albedo")?))
+            }
+            _ => {
+                return Err(self.unknown_type(
+                    table,
+                    kind,
+                    "lambertian, metal, dielectric, diffuse_light, isotropic",
+                ));
+            }
+        };
+        Ok(self.identify(material))
+    }
+
+    // give the material the next ID for the material ID pass. Materials are numbered the same
+    // whether or not the IDs are kept
+    fn identify(&self, material: Arc<dyn Material>) -> Arc<dyn Material> {
+        self.material_ids.set(self.material_ids.get() + 1);
+        if self.ids {
+            Arc::new(IdMaterial::new(material, self.material_ids.get()))
+        } else {
+            material
+        }
+    }
+
+    fn object(&self, table: Table) -> LoadResult<Arc<dyn Hittable>> {
+        let kind = self.type_field(table)?;
+        let keys: &[&str] = match kind {
+            "sphere" => &["type", "center", "center1", "radius", "material"],
+            "quad" => &["type", "q", "u", "v", "material"],
+            "box" => &["type", "a", "b", "material"],
+            "triangle" => &["type", "vertices", "normals", "uvs", "material"],
+            "mesh" => &["type", "positions", "indices", "normals", "uvs", "material"],
+            "obj" => &["type", "file", "material"],
+            "constant_medium" => &["type", "boundary", "density", "albedo"],
+            "group" => &["type", "objects", "bvh"],
+            _ => {
+                return Err(self.unknown_type(
+                    table,
+                    kind,
+                    "sphere, quad, box, triangle, mesh, obj, constant_medium, group",
+                ));
+            }
+        };
+        let allowed: Vec<&str> = keys.iter().chain(TRANSFORM_KEYS.iter()).copied().collect();
+        self.check_keys(table, &allowed)?;
+
+        let mut object: Arc<dyn Hittable> = match kind {
+            "sphere" => {
+                let center = self.required_vec3(table, "center")?;
+                let radius = self.required_f64(table, "radius")?;
+                let material = self.material_field(table, "material")?;
+                match self.vec3_field(table, "center1")? {
+                    Some(center1) => {
+                        Arc::new(Sphere::new_moving(center, center1, radius, material))
+                    }
+                    None => Arc::new(Sphere::new(center, radius, material)),
+                }
+            }
+            "quad" => Arc::new(Quad::new(
+                self.required_vec3(table, "q")?,
+                self.required_vec3(table, "u")?,
+                self.required_vec3(table, "v")?,
+                self.material_field(table, "material")?,
+            )),
+            "box" => quad::create_box(
+                self.required_vec3(table, "a")?,
+                self.required_vec3(table, "b")?,
+                self.material_field(table, "material")?,
+            ),
+            "triangle" => {
+                let vertices: Vec<Vec3> = self
+                    .required_tuples::<3>(table, "vertices", Some(3))?
+                    .into_iter()
+                    .map(Vec3::from)
+                    .collect();
+                let mut triangle = Triangle::new(
+                    vertices.try_into().unwrap(),
+                    self.material_field(table, "material")?,
+                );
+                if let Some(normals) = self.tuples_field::<3>(table, "normals", Some(3))? {
+                    let normals: Vec<Vec3> = normals.into_iter().map(Vec3::from).collect();
+                    triangle = triangle.with_normals(normals.try_into().unwrap());
+                }
+                if let Some(uvs) = self.tuples_field::<2>(table, "uvs", Some(3))? {
+                    let uvs: Vec<(f64, f64)> = uvs.into_iter().map(|[u, v]| (u, v)).collect();
+                    triangle = triangle.with_uvs(uvs.try_into().unwrap());
+                }
+                Arc::new(triangle)
+            }
+            "mesh" => {
+                let item = self.required(table, "indices")?;
+                let mut indices = Vec::new();
+                for triple in self.tuples::<3>(item, table.pos, "indices", None)? {
+                    if triple
+                        .iter()
+                        .any(|&i| i < 0.0 || i.fract() != 0.0 || i > u32::MAX as f64)
+                    {
+                        let message = "`indices` must be whole numbers from 0";
+                        return Err(self.error(self.pos_of(item, table.pos), message));
+                    }
+                    indices.push(triple.map(|i| i as u32));
+                }
+                let mut mesh = Mesh {
+                    positions: self
+                        .required_tuples::<3>(table, "positions", None)?
+                        .into_iter()
+                        .map(Vec3::from)
+                        .collect(),
+                    indices,
+                    ..Default::default()
+                };
+                if let Some(normals) = self.tuples_field::<3>(table, "normals", None)? {
+                    mesh.normals = normals.into_iter().map(Vec3::from).collect();
+                }
+                if let Some(uvs) = self.tuples_field::<2>(table, "uvs", None)? {
+                    mesh.uvs = uvs.into_iter().map(|[u, v]| (u, v)).collect();
+                }
+                if let Err(message) = mesh.validate() {
+                    return Err(self.error(table.pos, message));
+                }
+                Arc::new(TriangleMesh::new(
+                    mesh,
+                    self.material_field(table, "material")?,
+                ))
+            }
+            "obj" => {
+                let file = self.required_str(table, "file")?;
+                let path = self.base_dir.join(file);
+                let source = fs::read_to_string(&path).map_err(|e| {
+                    let pos = self.pos_of(table.entries.get("file").unwrap(), table.pos);
+                    self.error(pos, format!("cannot read `{}`: {}", path.display(), e))
+                })?;
+                // errors in the model point into the model file
+                let base_dir = path.parent().unwrap_or(Path::new("."));
+                let mut obj = Obj::parse(&source, &path.display().to_string(), base_dir)?;
+                self.fingerprint
+                    .borrow_mut()
+                    .add_u64(source.len() as u64)
+                    .add_bytes(source.as_bytes());
+                obj.files.iter().for_each(|file| self.add_file(file));
+                // `material` replaces the materials of the model, which otherwise default to gray
+                let replace = table.entries.contains_key("material");
+                let material = if replace {
+                    self.material_field(table, "material")?
+                } else {
+                    let gray = Lambertian::new_from_color(Color::new(0.73, 0.73, 0.73));
+                    let mut names: Vec<String> = obj.materials.keys().cloned().collect();
+                    names.sort();
+                    for name in names {
+                        let material = obj.materials[&name].clone();
+                        obj.materials.insert(name, self.identify(material));
+                    }
+                    self.identify(Arc::new(gray))
+                };
+                obj.build(material, replace)
+            }
+            "constant_medium" => {
+                let item = self.required(table, "boundary")?;
+                let boundary = self.table(item, table.pos, "the boundary object")?;
+                let density = self.required_f64(table, "density")?;
+                if density <= 0.0 {
+                    let pos = self.pos_of(table.entries.get("density").unwrap(), table.pos);
+                    return Err(self.error(pos, "`density` must be positive"));
+                }
+                Arc::new(ConstantMedium::new(
+                    self.object(boundary)?,
+                    density,
+                    self.texture_field(table, "albedo")?,
+                ))
+            }
+            _ => {
+                let mut group = Hittables::default();
+                for child in self.tables(self.required(table, "objects")?, table.pos, "objects")? {
+                    group.add(self.object(child)?);
+                }
+                if group.objects.is_empty() {
+                    return Err(self.error(table.pos, "a group needs at least one object"));
+                }
+                match self.bvh_field(table)? {
+                    Some(builder) => {
+                        let line = self.line_of(table.pos);
+                        self.bvh(&group, builder, format!("group at line {}", line))
+                    }
+                    None => Arc::new(group),
+                }
+            }
+        };
+
+        if let Some(transform) = self.transform(table)? {
+            object = Arc::new(TransformedHittable::new(object, transform));
+        }
+        if let Some(animation) = self.animation(table)? {
+            object = Arc::new(TransformedHittable::animated(object, animation));
+        }
+        Ok(object)
+    }
+
+    // the transform keys of an object composed into one transform, if it has any
+    fn transform(&self, table: Table) -> LoadResult<Option<Transform>> {
+        let mut transforms = Vec::new();
+        if let Some(factors) = self.scale_field(table)? {
+            transforms.push(Transform::scale(&factors));
+        }
+        for (axis, angle) in self.rotations(table)? {
+            transforms.push(Transform::rotate(&axis, angle));
+        }
+        if let Some(offset) = self.vec3_field(table, "translate")? {
+            transforms.push(Transform::translate(&offset));
+        }
+        Ok(transforms.into_iter().reduce(|a, b| a.then(&b)))
+    }
+
+    // one factor for all axes or one per axis
+    fn scale_field(&self, table: Table) -> LoadResult<Option<Vec3>> {
+        let Some(item) = table.entries.get("scale") else {
+            return Ok(None);
+        };
+        let factors = match item.as_value().and_then(Self::number) {
+            Some(factor) => Vec3::new(factor, factor, factor),
+            None => self.vec3(item, table.pos, "scale")?,
+        };
+        if (0..3).any(|i| factors[i] == 0.0) {
+            return Err(self.error(self.pos_of(item, table.pos), "`scale` must not be zero"));
+        }
+        Ok(Some(factors))
+    }
+
+    // the axes and angles of the rotation keys, in the order they apply
+    fn rotations(&self, table: Table) -> LoadResult<Vec<(Vec3, f64)>> {
+        let mut rotations = Vec::new();
+        for (key, axis) in [
+            ("rotate_x", Vec3::new(1.0, 0.0, 0.0)),
+            ("rotate_y", Vec3::new(0.0, 1.0, 0.0)),
+            ("rotate_z", Vec3::new(0.0, 0.0, 1.0)),
+        ] {
+            if let Some(angle) = self.f64_field(table, key)? {
+                rotations.push((axis, angle));
+            }
+        }
+        if let Some(item) = table.entries.get("rotate") {
+            let pos = self.pos_of(item, table.pos);
+            let rotation = self.table(item, pos, "a rotation")?;
+            self.check_keys(rotation, &["axis", "angle"])?;
+            let axis = self.required_vec3(rotation, "axis")?;
+            if axis.near_zero() {
+                return Err(self.error(pos, "the rotation `axis` must not be zero"));
+            }
+            rotations.push((axis, self.required_f64(rotation, "angle")?));
+        }
+        Ok(rotations)
+    }
+
+    // `keyframes = [{ time = 0.0, translate = [...], rotate_y = 90.0, scale = 2.0 }, ...]`, in
+    // scene time like the camera shutter
+    fn animation(&self, table: Table) -> LoadResult<Option<AnimatedTransform>> {
+        let Some(item) = table.entries.get("keyframes") else {
+            return Ok(None);
+        };
+        let mut keyframes = Vec::new();
+        for frame in self.tables(item, table.pos, "keyframes")? {
+            self.check_keys(
+                frame,
+                &[
+                    "time",
+                    "scale",
+                    "rotate_x",
+                    "rotate_y",
+                    "rotate_z",
+                    "rotate",
+                    "translate",
+                ],
+            )?;
+            let mut keyframe = Keyframe::new(self.required_f64(frame, "time")?);
+            if let Some(factors) = self.scale_field(frame)? {
+                keyframe.scale = factors;
+            }
+            for (axis, angle) in self.rotations(frame)? {
+                let rotation = Quaternion::from_axis_angle(&axis, angle);
+                keyframe.rotation = keyframe.rotation.then(&rotation);
+            }
+            if let Some(offset) = self.vec3_field(frame, "translate")? {
+                keyframe.translation = offset;
+            }
+            keyframes.push(keyframe);
+        }
+        if keyframes.is_empty() {
+            let pos = self.pos_of(item, table.pos);
+            return Err(self.error(pos, "`keyframes` needs at least one keyframe"));
+        }
+        Ok(Some(AnimatedTransform::new(keyframes)))
+    }
+
+    // fold the contents of a file the scene reads into its fingerprint, so a checkpoint is not
+    // resumed after the file changed
+    fn add_file(&self, path: &Path) {
+        // the file was just read, if it cannot be read again it is hashed as empty
+        let bytes = fs::read(path).unwrap_or_default();
+        self.fingerprint
+            .borrow_mut()
+            .add_u64(bytes.len() as u64)
+            .add_bytes(&bytes);
+    }
+
+    // a texture given as a color `[r, g, b]`, the name of a texture, or an inline texture table
+    fn texture_field(&self, table: Table, key: &str) -> LoadResult<Arc<dyn Texture>> {
+        let item = self.required(table, key)?;
+        let pos = self.pos_of(item, table.pos);
+        if let Some(name) = item.as_str() {
+            return match self.textures.get(name) {
+                Some(texture) => Ok(texture.clone()),
+                None => Err(self.error(pos, format!("texture `{}` is not defined", name))),
+            };
+        }
+        if item.is_table_like() {
+            return self.texture(self.table(item, pos, "a texture")?);
+        }
+        let color = self.vec3(item, table.pos, key)?;
+        Ok(Arc::new(SolidColor::new(color)))
+    }
+
+    // a material given by name or as an inline material table
+    fn material_field(&self, table: Table, key: &str) -> LoadResult<Arc<dyn Material>> {
+        let item = self.required(table, key)?;
+        let pos = self.pos_of(item, table.pos);
+        if let Some(name) = item.as_str() {
+            return match self.materials.get(name) {
+                Some(material) => Ok(material.clone()),
+                None => Err(self.error(pos, format!("material `{}` is not defined", name))),
+            };
+        }
+        self.material(self.table(item, pos, "a material name or table")?)
+    }
+
+    fn type_field<'t>(&self, table: Table<'t>) -> LoadResult<&'t str> {
+        self.required_str(table, "type")
+    }
+
+    fn unknown_type(&self, table: Table, kind: &str, expected: &str) -> SceneError {
+        let pos = self.pos_of(table.entries.get("type").unwrap(), table.pos);
+        self.error(
+            pos,
+            format!("unknown type `{}`, expected one of: {}", kind, expected),
+        )
+    }
+
+    // error on the first key that is not in `allowed`, most likely a typo
+    fn check_keys(&self, table: Table, allowed: &[&str]) -> LoadResult<()> {
+        for (key, item) in table.entries.iter() {
+            if !allowed.contains(&key) {
+                let pos = table
+                    .entries
+                    .key(key)
+                    .and_then(|k| k.span())
+                    .map(|span| span.start)
+                    .unwrap_or_else(|| self.pos_of(item, table.pos));
+                return Err(self.error(
+                    pos,
+                    format!(
+                        "unknown key `{}`, expected one of: {}",
+                        key,
+                        allowed.join(", ")
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn table<'t>(&self, item: &'t Item, fallback: usize, what: &str) -> LoadResult<Table<'t>> {
+        let pos = self.pos_of(item, fallback);
+        match item.as_table_like() {
+            Some(entries) => Ok(Table { entries, pos }),
+            None => Err(self.error(pos, format!("expected a table for {}", what))),
+        }
+    }
+
+    // an array of tables, either `[[objects]]` or `objects = [{ ... }, { ... }]`
+    fn tables<'t>(
+        &self,
+        item: &'t Item,
+        fallback: usize,
+        what: &str,
+    ) -> LoadResult<Vec<Table<'t>>> {
+        let pos = self.pos_of(item, fallback);
+        if let Some(array) = item.as_array_of_tables() {
+            return Ok(array
+                .iter()
+                .map(|table| Table {
+                    entries: table,
+                    pos: table.span().map(|span| span.start).unwrap_or(pos),
+                })
+                .collect());
+        }
+        if let Some(array) = item.as_array() {
+            return array
+                .iter()
+                .map(|value| {
+                    let pos = value.span().map(|span| span.start).unwrap_or(pos);
+                    match value.as_inline_table() {
+                        Some(entries) => Ok(Table { entries, pos }),
+                        None => Err(self.error(pos, format!("expected a table in {}", what))),
+                    }
+                })
+                .collect();
+        }
+        Err(self.error(pos, format!("expected an array of tables for {}", what)))
+    }
+
+    fn required<'t>(&self, table: Table<'t>, key: &str) -> LoadResult<&'t Item> {
+        table
+            .entries
+            .get(key)
+            .ok_or_else(|| self.error(table.pos, format!("missing `{}`", key)))
+    }
+
+    fn required_f64(&self, table: Table, key: &str) -> LoadResult<f64> {
+        self.f64(self.required(table, key)?, table.pos, key)
+    }
+
+    fn required_vec3(&self, table: Table, key: &str) -> LoadResult<Vec3> {
+        self.vec3(self.required(table, key)?, table.pos, key)
+    }
+
+    fn required_str<'t>(&self, table: Table<'t>, key: &str) -> LoadResult<&'t str> {
+        let item = self.required(table, key)?;
+        item.as_str().ok_or_else(|| {
+            self.error(
+                self.pos_of(item, table.pos),
+                format!("`{}` must be a string", key),
+            )
+        })
+    }
+
+    fn f64_field(&self, table: Table, key: &str) -> LoadResult<Option<f64>> {
+        table
+            .entries
+            .get(key)
+            .map(|item| self.f64(item, table.pos, key))
+            .transpose()
+    }
+
+    fn vec3_field(&self, table: Table, key: &str) -> LoadResult<Option<Vec3>> {
+        table
+            .entries
+            .get(key)
+            .map(|item| self.vec3(item, table.pos, key))
+            .transpose()
+    }
+
+    // `bvh = true` builds the default BVH, a builder name picks the builder
+    fn bvh_field(&self, table: Table) -> LoadResult<Option<BvhBuilder>> {
+        let Some(item) = table.entries.get("bvh") else {
+            return Ok(None);
+        };
+        if let Some(bvh) = item.as_bool() {
+            return Ok(bvh.then(BvhBuilder::default));
+        }
+        item.as_str()
+            .and_then(|name| name.parse().ok())
+            .map(Some)
+            .ok_or_else(|| {
+                let names: Vec<&str> = BVH_BUILDERS.iter().map(BvhBuilder::name).collect();
+                self.error(
+                    self.pos_of(item, table.pos),
+                    format!("`bvh` must be true, false or one of {}", names.join(", ")),
+                )
+            })
+    }
+
+    fn bvh(&self, objects: &Hittables, builder: BvhBuilder, label: String) -> Arc<dyn Hittable> {
+        let bvh = Bvh::with_builder(&objects.objects, builder);
+        self.bvh_reports.borrow_mut().push((label, bvh.stats()));
+        Arc::new(bvh)
+    }
+
+    fn positive_field<T: TryFrom<i64>>(&self, table: Table, key: &str) -> LoadResult<Option<T>> {
+        table
+            .entries
+            .get(key)
+            .map(|item| {
+                match item
+                    .as_integer()
+                    .filter(|n| *n > 0)
+                    .and_then(|n| T::try_from(n).ok())
+                {
+                    Some(n) => Ok(n),
+                    None => Err(self.error(
+                        self.pos_of(item, table.pos),
+                        format!("`{}` must be a positive integer", key),
+                    )),
+                }
+            })
+            .transpose()
+    }
+
+    fn f64(&self, item: &Item, fallback: usize, key: &str) -> LoadResult<f64> {
+        match item.as_value().and_then(Self::number) {
+            Some(x) => Ok(x),
+            None => Err(self.error(
+                self.pos_of(item, fallback),
+                format!("`{}` must be a number", key),
+            )),
+        }
+    }
+
+    fn vec3(&self, item: &Item, fallback: usize, key: &str) -> LoadResult<Vec3> {
+        let numbers = item
+            .as_array()
+            .map(|array| array.iter().filter_map(Self::number).collect::<Vec<_>>());
+        match numbers {
+            Some(n) if n.len() == 3 && item.as_array().unwrap().len() == 3 => {
+                Ok(Color::new(n[0], n[1], n[2]))
+            }
+            _ => Err(self.error(
+                self.pos_of(item, fallback),
+                format!("`{}` must be an array of three numbers", key),
+            )),
+        }
+    }
+
+    // an array of arrays of N numbers each, exactly `count` of them if given
+    fn tuples<const N: usize>(
+        &self,
+        item: &Item,
+        fallback: usize,
+        key: &str,
+        count: Option<usize>,
+    ) -> LoadResult<Vec<[f64; N]>> {
+        let tuples = item.as_array().and_then(|array| {
+            array
+                .iter()
+                .map(|value| {
+                    let numbers: Vec<f64> = value
+                        .as_array()?
+                        .iter()
+                        .map(Self::number)
+                        .collect::<Option<_>>()?;
+                    numbers.try_into().ok()
+                })
+                .collect::<Option<Vec<_>>>()
+        });
+        match tuples {
+            Some(tuples) if count.is_none_or(|count| tuples.len() == count) => Ok(tuples),
+            _ => {
+                let count = count.map(|count| format!("{} ", count)).unwrap_or_default();
+                Err(self.error(
+                    self.pos_of(item, fallback),
+                    format!(
+                        "`{}` must be an array of {}arrays of {} numbers",
+                        key, count, N
+                    ),
+                ))
+            }
+        }
+    }
+
+    fn required_tuples<const N: usize>(
+        &self,
+        table: Table,
+        key: &str,
+        count: Option<usize>,
+    ) -> LoadResult<Vec<[f64; N]>> {
+        self.tuples(self.required(table, key)?, table.pos, key, count)
+    }
+
+    fn tuples_field<const N: usize>(
+        &self,
+        table: Table,
+        key: &str,
+        count: Option<usize>,
+    ) -> LoadResult<Option<Vec<[f64; N]>>> {
+        table
+            .entries
+            .get(key)
+            .map(|item| self.tuples(item, table.pos, key, count))
+            .transpose()
+    }
+
+    fn number(value: &Value) -> Option<f64> {
+        value
+            .as_float()
+            .or_else(|| value.as_integer().map(|n| n as f64))
+    }
+
+    fn pos_of(&self, item: &Item, fallback: usize) -> usize {
+        item.span().map(|span| span.start).unwrap_or(fallback)
+    }
+
+    fn line_of(&self, pos: usize) -> usize {
+        let end = pos.min(self.source.len());
+        self.source[..end].matches('\n').count() + 1
+    }
+
+    fn error(&self, pos: usize, message: impl Into<String>) -> SceneError {
+        SceneError {
+            file: self.file.to_string(),
+            line: self.line_of(pos),
+            message: message.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framebuffer::Framebuffer;
+    use crate::output::OutputFormat;
+    use crate::sampler::SamplerKind;
+    use crate::tonemap::{ToneMap, ToneMapping};
+    use crate::utils::INFINITY;
+    use crate::{Hittable, Interval, Point3, RandomSampler, Ray};
+
+    fn parse(source: &str) -> LoadResult<Scene> {
+        Scene::parse(
+            source,
+            "test.toml",
+            Path::new("."),
+            &mut RandomSampler::new(0),
+        )
+    }
+
+    #[test]
+    fn test_minimal_scene() {
+        let scene = parse(
+            r#"
+[camera]
+image_width = 64
+look_from = [0, 0, 5]
+sampler = "sobol"
+shutter = "rolling"
+shutter_exposure = 0.5
+shutter_close = 0.25
+
+[materials]
+red = { type = "lambertian", albedo = [0.9, 0.1, 0.1] }
+
+[[objects]]
+type = "sphere"
+center = [0.0, 0.0, 0.0]
+radius = 1.0
+material = "red"
+translate = [1.0, 0.0, 0.0]
+"#,
+        )
+        .unwrap();
+        assert_eq!(scene.camera.image_width, 64);
+        assert_eq!(scene.camera.sampler, SamplerKind::Sobol);
+        assert_eq!(scene.camera.shutter, Shutter::Rolling { exposure: 0.5 });
+        assert_eq!(
+            (scene.camera.shutter_open, scene.camera.shutter_close),
+            (0.0, 0.25)
+        );
+        assert_eq!(scene.camera.look_from, Vec3::new(0.0, 0.0, 5.0));
+        assert_eq!(scene.world.objects.len(), 1);
+        let bbox = scene.world.bounding_box();
+        assert_eq!((bbox.x.min, bbox.x.max), (0.0, 2.0));
+    }
+
+    #[test]
+    fn test_keyframes() {
+        let source = r#"[camera]
+[materials]
+red = { type = "lambertian", albedo = [0.9, 0.1, 0.1] }
+[[objects]]
+type = "box"
+a = [-1.0, -1.0, -1.0]
+b = [1.0, 1.0, 1.0]
+material = "red"
+translate = [2.0, 0.0, 0.0]
+keyframes = [
+    { time = 0.0 },
+    { time = 1.0, translate = [0.0, 5.0, 0.0], rotate_z = 90.0 },
+]
+"#;
+        let scene = parse(source).unwrap();
+        // the box swings from x = 2 up to y = 2 while rising by 5
+        let bbox = scene.world.bounding_box();
+        assert!(bbox.x.min <= -1.0 && bbox.x.max >= 3.0);
+        assert!(bbox.y.min <= -1.0 && bbox.y.max >= 8.0);
+        let mut sampler = RandomSampler::new(0);
+        let ray = Ray::new(Point3::new(0.0, 20.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 1.0);
+        let rec = scene
+            .world
+            .hit(&ray, &mut Interval::new(0.001, INFINITY), &mut sampler)
+            .unwrap();
+        assert!((rec.t - 12.0).abs() < 1e-9);
+
+        let err = parse(&source.replace("{ time = 0.0 }", "{ tiem = 0.0 }"))
+            .err()
+            .unwrap();
+        assert_eq!(err.line, 11);
+        assert!(err.message.starts_with("unknown key `tiem`"));
+        let err =
+            parse(&(source.split("keyframes").next().unwrap().to_string() + "keyframes = []\n"))
+                .err()
+                .unwrap();
+        assert_eq!(err.message, "`keyframes` needs at least one keyframe");
+    }
+
+    #[test]
+    fn test_projections() {
+        let scene = parse("[camera]\nprojection = \"fisheye\"\nfisheye_fov = 220\n").unwrap();
+        assert_eq!(scene.camera.projection, Projection::Fisheye { fov: 220.0 });
+        assert_eq!(scene.camera.aspect_ratio, 1.0);
+        // panoramas fit their aspect ratio unless the scene sets one
+        let scene = parse("[camera]\nprojection = \"equirectangular\"\n").unwrap();
+        assert_eq!(scene.camera.aspect_ratio, 2.0);
+        let scene = parse("[camera]\nprojection = \"cube_map\"\naspect_ratio = 3.0\n").unwrap();
+        assert_eq!(scene.camera.aspect_ratio, 3.0);
+        let scene = parse("[camera]\nprojection = \"orthographic\"\northo_height = 10\n").unwrap();
+        assert_eq!(
+            scene.camera.projection,
+            Projection::Orthographic { height: Some(10.0) }
+        );
+    }
+
+    #[test]
+    fn test_lens() {
+        let source =
+            "[camera]\naperture_blades = 6\naperture_rotation = 30\ncat_eye = 0.4\ntilt = -5\n";
+        let camera = parse(source).unwrap().camera;
+        assert_eq!(
+            camera.aperture,
+            Aperture::Polygon {
+                blades: 6,
+                rotation: 30.0
+            }
+        );
+        assert_eq!(
+            (camera.cat_eye, camera.tilt, camera.tilt_rotation),
+            (0.4, -5.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn test_tone_mapping() {
+        let source = "[camera]\nexposure = -1.5\nwhite_balance = 3200\ntone_map = \"agx\"\n";
+        let camera = parse(source).unwrap().camera;
+        assert_eq!(
+            camera.tone_mapping,
+            ToneMapping {
+                exposure: -1.5,
+                white_balance: 3200.0,
+                tone_map: ToneMap::Agx,
+            }
+        );
+        assert!(parse("[camera]\n")
+            .unwrap()
+            .camera
+            .tone_mapping
+            .is_identity());
+    }
+
+    #[test]
+    fn test_effects() {
+        let source = r#"[camera]
+bloom = { threshold = 2.0, radius = 0.1 }
+star_glare = { spokes = 4, rotation = 45 }
+vignetting = {}
+"#;
+        let effects = parse(source).unwrap().camera.effects;
+        assert_eq!(
+            effects.bloom,
+            Some(Bloom {
+                threshold: 2.0,
+                radius: 0.1,
+                ..Bloom::default()
+            })
+        );
+        assert_eq!(
+            effects.star_glare,
+            Some(StarGlare {
+                spokes: 4,
+                rotation: 45.0,
+                ..StarGlare::default()
+            })
+        );
+        assert_eq!(effects.vignetting, Some(Vignetting::default()));
+        assert!(parse("[camera]\n").unwrap().camera.effects.is_none());
+    }
+
+    #[test]
+    fn test_aovs() {
+        let source = r#"[camera]
+look_from = [0.0, 0.0, 10.0]
+look_at = [0.0, 0.0, 0.0]
+image_width = 8
+samples_per_pixel = 8
+defocus_angle = 0.0
+aovs = ["object_id", "material_id", "depth"]
+[materials]
+red = { type = "lambertian", albedo = [0.9, 0.1, 0.1] }
+blue = { type = "lambertian", albedo = [0.1, 0.1, 0.9] }
+[[objects]]
+type = "sphere"
+center = [0.0, 0.0, -100.0]
+radius = 1.0
+material = "red"
+[[objects]]
+type = "sphere"
+center = [0.0, 0.0, 0.0]
+radius = 2.0
+material = { type = "metal", albedo = [0.5, 0.5, 0.5] }
+"#;
+        let mut scene = parse(source).unwrap();
+        let camera = &mut scene.camera;
+        assert_eq!(camera.aovs, [Aov::ObjectId, Aov::MaterialId, Aov::Depth]);
+        let aovs = camera.render_to_buffer(&scene.world).aov_images();
+        // the second object with the inline material, which comes after the named ones, fills
+        // the middle of the image
+        let center = |k: usize| aovs[k].1.pixel(4, 4);
+        assert_eq!(center(0), Color::new(2.0, 2.0, 2.0));
+        assert_eq!(center(1), Color::new(3.0, 3.0, 3.0));
+        assert!((8.0..8.5).contains(&center(2).x()), "{}", center(2));
+        assert_eq!(aovs[2].1.pixel(0, 0).x(), INFINITY);
+        // pixels on the edge of the sphere get its ID even if their first sample misses it
+        for j in 0..8 {
+            for i in 0..8 {
+                let hit = aovs[2].1.pixel(i, j).x() < INFINITY;
+                assert_eq!(aovs[0].1.pixel(i, j).x() > 0.0, hit, "{} {}", i, j);
+            }
+        }
+
+        // without an ID pass the scene carries no IDs, unless it is asked for them
+        let source = source.replace("\"object_id\", \"material_id\", ", "");
+        for (ids, id) in [(false, 0.0), (true, 2.0)] {
+            let mut sampler = RandomSampler::new(0);
+            let mut scene = match ids {
+                true => Scene::parse_with_ids(&source, "test.toml", Path::new("."), &mut sampler),
+                false => Scene::parse(&source, "test.toml", Path::new("."), &mut sampler),
+            }
+            .unwrap();
+            scene.camera.aovs = vec![Aov::ObjectId, Aov::MaterialId];
+            let aovs = scene.camera.render_to_buffer(&scene.world).aov_images();
+            assert_eq!(aovs[0].1.pixel(4, 4).x(), id);
+            assert_eq!(aovs[1].1.pixel(4, 4).x(), id * 1.5);
+        }
+
+        let err = parse(&source.replace("\"depth\"]", "\"position\"]"))
+            .err()
+            .unwrap();
+        assert_eq!(err.line, 7);
+        assert!(err.message.starts_with("unknown AOV `position`"));
+    }
+
+    #[test]
+    fn test_camera_animation() {
+        let source = r#"[animation]
+fps = 12
+frames = [0, 23]
+[camera]
+look_at = [0.0, 0.0, 0.0]
+keyframes = [
+    { time = 0.0, look_from = [0.0, 0.0, 10.0], vfov = 30.0 },
+    { time = 2.0, look_from = [10.0, 0.0, 0.0] },
+]
+"#;
+        let mut scene = parse(source).unwrap();
+        let animation = scene.animation.unwrap();
+        assert_eq!(animation.fps, 12.0);
+        assert_eq!(animation.shutter_angle, 180.0);
+        assert_eq!(animation.frames, Some((0, 23)));
+        let camera = &mut scene.camera;
+        assert_eq!(camera.keyframes.len(), 2);
+        camera.apply_keyframes(1.0);
+        assert_eq!(camera.look_from, Point3::new(5.0, 0.0, 5.0));
+        assert_eq!(camera.vfov, 30.0);
+
+        let err = parse(&source.replace("[0, 23]", "[5, 2]")).err().unwrap();
+        assert_eq!(err.line, 3);
+        assert_eq!(
+            err.message,
+            "`frames` must be [first, last] with 0 <= first <= last"
+        );
+        let err = parse(&source.replace("fps = 12", "fps = 0")).err().unwrap();
+        assert_eq!(err.message, "`fps` must be positive");
+        let err = parse(&source.replace(", vfov", ", fov")).err().unwrap();
+        assert_eq!(err.line, 7);
+        assert!(err.message.starts_with("unknown key `fov`"));
+    }
+
+    #[test]
+    fn test_transforms() {
+        let source = r#"[camera]
+[materials]
+red = { type = "lambertian", albedo = [0.9, 0.1, 0.1] }
+[[objects]]
+type = "sphere"
+center = [0.0, 0.0, 0.0]
+radius = 1.0
+material = "red"
+scale = [2.0, 1.0, 1.0]
+rotate_z = 90.0
+translate = [0.0, 0.0, 3.0]
+"#;
+        let scene = parse(source).unwrap();
+        // scaled along x, then turned upright
+        let bbox = scene.world.bounding_box();
+        assert!((bbox.x.min + 1.0).abs() < 1e-9 && (bbox.x.max - 1.0).abs() < 1e-9);
+        assert!((bbox.y.min + 2.0).abs() < 1e-9 && (bbox.y.max - 2.0).abs() < 1e-9);
+        assert!((bbox.z.min - 2.0).abs() < 1e-9 && (bbox.z.max - 4.0).abs() < 1e-9);
+
+        let source = source.replace(
+            "rotate_z = 90.0",
+            "rotate = { axis = [0, 0, 1], angle = 90 }",
+        );
+        let bbox = parse(&source).unwrap().world.bounding_box();
+        assert!((bbox.y.max - 2.0).abs() < 1e-9);
+
+        let err = parse(&source.replace("[2.0, 1.0, 1.0]", "0"))
+            .err()
+            .unwrap();
+        assert_eq!(err.line, 9);
+        assert_eq!(err.message, "`scale` must not be zero");
+        let err = parse(&source.replace("axis", "axes")).err().unwrap();
+        assert_eq!(err.line, 10);
+        assert!(err.message.starts_with("unknown key `axes`"));
+    }
+
+    #[test]
+    fn test_undefined_material_line() {
+        let err = parse(
+            r#"[camera]
+
+[[objects]]
+type = "quad"
+q = [0, 0, 0]
+u = [1, 0, 0]
+v = [0, 1, 0]
+material = "missing"
+"#,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(err.line, 8);
+        assert_eq!(err.message, "material `missing` is not defined");
+        assert_eq!(
+            err.to_string(),
+            "test.toml:8: material `missing` is not defined"
+        );
+    }
+
+    #[test]
+    fn test_unknown_key_line() {
+        let err = parse("[camera]\nimage_width = 10\nfvov = 20.0\n")
+            .err()
+            .unwrap();
+        assert_eq!(err.line, 3);
+        assert!(err.message.starts_with("unknown key `fvov`"));
+    }
+
+    #[test]
+    fn test_bad_values() {
+        let err = parse("[camera]\nlook_at = [1, 2]\n").err().unwrap();
+        assert_eq!(err.line, 2);
+        assert_eq!(err.message, "`look_at` must be an array of three numbers");
+
+        let err = parse("[camera]\n\n[materials]\nx = { type = \"plastic\" }\n")
+            .err()
+            .unwrap();
+        assert_eq!(err.line, 4);
+        assert!(err.message.starts_with("unknown type `plastic`"));
+
+        let err = parse("[camera]\nimage_width = -3\n").err().unwrap();
+        assert_eq!(err.line, 2);
+
+        let err = parse("[camera]\nshutter_open = 1.0\nshutter_close = 0.5\n")
+            .err()
+            .unwrap();
+        assert_eq!(err.line, 3);
+        assert_eq!(
+            err.message,
+            "`shutter_close` must not be before `shutter_open`"
+        );
+        let err = parse("[camera]\nshutter_exposure = 0.5\n").err().unwrap();
+        assert_eq!(
+            err.message,
+            "`shutter_exposure` needs `shutter = \"rolling\"`"
+        );
+        let err = parse("[camera]\nshutter = \"rolling\"\nshutter_exposure = 2\n")
+            .err()
+            .unwrap();
+        assert_eq!(err.line, 3);
+        let err = parse("[camera]\naperture_blades = 2\n").err().unwrap();
+        assert_eq!(err.message, "`aperture_blades` must be at least 3");
+        let err = parse("[camera]\naperture_rotation = 10\n").err().unwrap();
+        assert_eq!(err.message, "`aperture_rotation` needs `aperture_blades`");
+        let err = parse("[camera]\naperture_mask = \"missing.png\"\n")
+            .err()
+            .unwrap();
+        assert_eq!(err.line, 2);
+        assert!(err.message.starts_with("cannot load aperture mask"));
+        let err = parse("[camera]\ntilt = 90\n").err().unwrap();
+        assert_eq!(err.message, "`tilt` must be between -90 and 90 degrees");
+        let err = parse("[camera]\nfisheye_fov = 90\n").err().unwrap();
+        assert_eq!(
+            err.message,
+            "`fisheye_fov` needs `projection = \"fisheye\"`"
+        );
+        let err = parse("[camera]\nprojection = \"orthographic\"\northo_height = -1\n")
+            .err()
+            .unwrap();
+        assert_eq!(err.line, 3);
+        assert_eq!(err.message, "`ortho_height` must be positive");
+        let err = parse("[camera]\nprojection = \"mercator\"\n")
+            .err()
+            .unwrap();
+        assert_eq!(err.line, 2);
+        let err = parse("[camera]\nwhite_balance = 800\n").err().unwrap();
+        assert_eq!(
+            err.message,
+            "`white_balance` must be a temperature from 1667 to 25000 kelvin"
+        );
+        let err = parse("[camera]\n\ntone_map = \"filmic\"\n").err().unwrap();
+        assert_eq!(err.line, 3);
+        assert!(err.message.starts_with("unknown tone mapping `filmic`"));
+        let err = parse("[camera]\nbloom = { strength = 1.5 }\n")
+            .err()
+            .unwrap();
+        assert_eq!(err.line, 2);
+        assert_eq!(err.message, "`strength` must be in [0, 1]");
+        let err = parse("[camera]\nstar_glare = { spokes = 0 }\n")
+            .err()
+            .unwrap();
+        assert_eq!(err.message, "`spokes` must be a positive integer");
+
+        let err = parse("bvh = \"octree\"\n[camera]\n").err().unwrap();
+        assert_eq!(err.line, 1);
+        assert_eq!(
+            err.message,
+            "`bvh` must be true, false or one of sah, median"
+        );
+    }
+
+    #[test]
+    fn test_bvh_reports() {
+        let source = r#"
+bvh = "median"
+[camera]
+[materials]
+m = { type = "lambertian", albedo = [0.5, 0.5, 0.5] }
+[[objects]]
+type = "group"
+bvh = true
+objects = [
+    { type = "sphere", center = [0.0, 0.0, 0.0], radius = 1.0, material = "m" },
+    { type = "sphere", center = [3.0, 0.0, 0.0], radius = 1.0, material = "m" },
+]
+[[objects]]
+type = "sphere"
+center = [0.0, 5.0, 0.0]
+radius = 1.0
+material = "m"
+"#;
+        let scene = parse(source).unwrap();
+        let labels: Vec<&str> = scene.bvh_reports.iter().map(|r| r.0.as_str()).collect();
+        assert_eq!(labels, ["group at line 6", "world"]);
+        assert_eq!(scene.bvh_reports[0].1.objects, 2);
+        assert_eq!(scene.bvh_reports[1].1.objects, 2);
+        assert_eq!(scene.bvh_reports[1].1.leaves, 2);
+    }
+
+    #[test]
+    fn test_triangles() {
+        let source = r#"
+[camera]
+
+[materials]
+gray = { type = "lambertian", albedo = [0.5, 0.5, 0.5] }
+
+[[objects]]
+type = "triangle"
+vertices = [[0, 0, 0], [1, 0, 0], [0, 1, 0]]
+uvs = [[0, 0], [1, 0], [0, 1]]
+material = "gray"
+
+[[objects]]
+type = "mesh"
+positions = [[0, 0, -1], [1, 0, -1], [1, 1, -1], [0, 1, -1]]
+indices = [[0, 1, 2], [0, 2, 3]]
+material = "gray"
+"#;
+        let scene = parse(source).unwrap();
+        assert_eq!(scene.world.objects.len(), 2);
+
+        let err = parse(&source.replace("[0, 2, 3]", "[0, 2, 4]"))
+            .err()
+            .unwrap();
+        assert_eq!(err.line, 13);
+        assert_eq!(
+            err.message,
+            "vertex index 4 is out of range for 4 positions"
+        );
+        let err = parse(&source.replace("[0, 2, 3]", "[0, 2]")).err().unwrap();
+        assert_eq!(err.line, 16);
+        assert_eq!(
+            err.message,
+            "`indices` must be an array of arrays of 3 numbers"
+        );
+        let err = parse(&source.replace("[[0, 0], [1, 0], [0, 1]]", "[[0, 0]]"))
+            .err()
+            .unwrap();
+        assert_eq!(err.line, 10);
+        assert_eq!(
+            err.message,
+            "`uvs` must be an array of 3 arrays of 2 numbers"
+        );
+    }
+
+    #[test]
+    fn test_syntax_error_line() {
+        let err = parse("[camera]\nvfov = 20.0\nlook_at = [0, 0 x]\n")
+            .err()
+            .unwrap();
+        assert_eq!(err.line, 3);
+        assert_eq!(err.message, "invalid array, expected `]`");
+    }
+
+    #[test]
+    fn test_missing_image() {
+        let err = parse(
+            "[camera]\n[textures]\nmap = { type = \"image\", file = \"no/such/file.png\" }\n",
+        )
+        .err()
+        .unwrap();
+        assert_eq!(err.line, 3);
+        assert!(err.message.starts_with("cannot load image"));
+    }
+
+    #[test]
+    fn test_image_fingerprint() {
+        let dir = std::env::temp_dir().join(format!("raytracing_scene_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut image = crate::Framebuffer::new(2, 2);
+        crate::output::write_image(dir.join("map.png"), OutputFormat::Png8, &image).unwrap();
+        let source = "[camera]\n[textures]\nmap = { type = \"image\", file = \"map.png\" }\n";
+        let parse = || Scene::parse(source, "test.toml", &dir, &mut RandomSampler::new(0));
+        let scene = parse().unwrap();
+        // the same scene with another image in the same file is a different scene for checkpoints
+        image.set_pixel(0, 0, Color::new(1.0, 1.0, 1.0));
+        crate::output::write_image(dir.join("map.png"), OutputFormat::Png8, &image).unwrap();
+        let changed = parse().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_ne!(scene.fingerprint, changed.fingerprint);
+    }
+
+    #[test]
+    fn test_environment_map() {
+        let err = parse("[camera]\nbackground = { map = \"no/such/sky.hdr\" }\n")
+            .err()
+            .unwrap();
+        assert_eq!(err.line, 2);
+        assert!(err.message.starts_with("cannot load environment map"));
+
+        let dir = std::env::temp_dir().join(format!("scene_environment_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut sky = Framebuffer::new(4, 2);
+        sky.map_pixels(|_| Color::new(0.5, 0.7, 1.0));
+        crate::output::write_image(dir.join("sky.pfm"), OutputFormat::Pfm, &sky).unwrap();
+        let source = "[camera]\nbackground = { map = \"sky.pfm\", rotation = 90, intensity = 2 }\n";
+        let scene = Scene::parse(source, "test.toml", &dir, &mut RandomSampler::new(0)).unwrap();
+        // the same scene with another sky in the same file is a different scene for checkpoints
+        sky.set_pixel(0, 0, Color::new(0.0, 0.0, 0.0));
+        crate::output::write_image(dir.join("sky.pfm"), OutputFormat::Pfm, &sky).unwrap();
+        let changed = Scene::parse(source, "test.toml", &dir, &mut RandomSampler::new(0)).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_ne!(scene.fingerprint, changed.fingerprint);
+        assert_ne!(scene.camera.fingerprint(), changed.camera.fingerprint());
+        let Background::Environment(environment) = scene.camera.background else {
+            panic!("expected an environment map");
+        };
+        assert_eq!((environment.rotation, environment.intensity), (90.0, 2.0));
+        let up = environment.radiance(&Vec3::new(0.0, 1.0, 0.0));
+        assert!((up.z() - 2.0).abs() < 1e-6);
+
+        let err = parse("[camera]\nbackground = { rotation = 90 }\n")
+            .err()
+            .unwrap();
+        assert_eq!(err.line, 2);
+    }
+
+    #[test]
+    fn test_obj_object() {
+        let err = parse("[camera]\n[[objects]]\ntype = \"obj\"\nfile = \"no/such/model.obj\"\n")
+            .err()
+            .unwrap();
+        assert_eq!(err.line, 4);
+        assert!(err.message.starts_with("cannot read `./no/such/model.obj`"));
+
+        let dir = std::env::temp_dir().join(format!("scene_obj_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("model.obj"),
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\nf 1 2 9\n",
+        )
+        .unwrap();
+        let source = "[camera]\n[[objects]]\ntype = \"obj\"\nfile = \"model.obj\"\n";
+        let err = Scene::parse(source, "test.toml", &dir, &mut RandomSampler::new(0))
+            .err()
+            .unwrap();
+        fs::write(
+            dir.join("model.obj"),
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n",
+        )
+        .unwrap();
+        let scene = Scene::parse(source, "test.toml", &dir, &mut RandomSampler::new(0));
+        fs::remove_dir_all(&dir).unwrap();
+        // errors in the model point into the model file
+        assert_eq!(err.file, dir.join("model.obj").display().to_string());
+        assert_eq!(err.line, 5);
+        assert_eq!(scene.unwrap().world.objects.len(), 1);
+    }
+}
@@ -0,0 +1,123 @@
+// How the shutter exposes the image over time.
+//
+// Every camera ray carries an absolute scene time between `shutter_open` and `shutter_close`, picked
+// from one random number by the shutter shape. A box shutter exposes the whole interval evenly, a
+// triangle shutter opens and closes gradually and weighs the middle most, and a rolling shutter
+// exposes the rows one after another, top first, each for a fraction of the interval.
+use std::fmt;
+use std::str::FromStr;
+
+// the fraction of the interval each row of a rolling shutter sees, unless set otherwise
+pub const DEFAULT_ROLLING_EXPOSURE: f64 = 0.25;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Shutter {
+    #[default]
+    Box,
+    Triangle,
+    Rolling {
+        exposure: f64, // in (0, 1], the fraction of the interval each row is open
+    },
+}
+
+pub const SHUTTER_NAMES: [&str; 3] = ["box", "triangle", "rolling"];
+
+impl Shutter {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Shutter::Box => "box",
+            Shutter::Triangle => "triangle",
+            Shutter::Rolling { .. } => "rolling",
+        }
+    }
+
+    // the time of a ray through `row` of `rows` for a uniform random number `u` in [0, 1)
+    pub fn sample_time(&self, open: f64, close: f64, row: u32, rows: u32, u: f64) -> f64 {
+        let fraction = match *self {
+            Shutter::Box => u,
+            // inverse of the cumulative distribution of a tent over [0, 1]
+            Shutter::Triangle => {
+                if u < 0.5 {
+                    (2.0 * u).sqrt() / 2.0
+                } else {
+                    1.0 - (2.0 * (1.0 - u)).sqrt() / 2.0
+                }
+            }
+            Shutter::Rolling { exposure } => {
+                let start = if rows > 1 {
+                    row as f64 / (rows - 1) as f64 * (1.0 - exposure)
+                } else {
+                    0.0
+                };
+                start + u * exposure
+            }
+        };
+        open + fraction * (close - open)
+    }
+}
+
+impl fmt::Display for Shutter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Shutter::Rolling { exposure } => write!(f, "rolling, rows exposed {}", exposure),
+            _ => f.write_str(self.name()),
+        }
+    }
+}
+
+impl FromStr for Shutter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "box" => Ok(Shutter::Box),
+            "triangle" => Ok(Shutter::Triangle),
+            "rolling" => Ok(Shutter::Rolling {
+                exposure: DEFAULT_ROLLING_EXPOSURE,
+            }),
+            _ => Err(format!(
+                "unknown shutter `{}`, expected one of: {}",
+                s,
+                SHUTTER_NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the times of n evenly spread random numbers
+    fn times(shutter: Shutter, row: u32, rows: u32) -> Vec<f64> {
+        let n = 1000;
+        (0..n)
+            .map(|i| shutter.sample_time(2.0, 4.0, row, rows, (i as f64 + 0.5) / n as f64))
+            .collect()
+    }
+
+    #[test]
+    fn test_shutters() {
+        for name in SHUTTER_NAMES {
+            assert_eq!(name.parse::<Shutter>().unwrap().name(), name);
+        }
+        assert!("global".parse::<Shutter>().is_err());
+
+        let mean = |times: &[f64]| times.iter().sum::<f64>() / times.len() as f64;
+        let box_times = times(Shutter::Box, 0, 1);
+        assert!(box_times.iter().all(|t| (2.0..4.0).contains(t)));
+        assert!((mean(&box_times) - 3.0).abs() < 1e-9);
+
+        // a tent has a quarter of its weight in the outer half of each side
+        let tent = times(Shutter::Triangle, 0, 1);
+        assert!((mean(&tent) - 3.0).abs() < 1e-9);
+        let outer = tent.iter().filter(|&&t| !(2.5..3.5).contains(&t)).count();
+        assert!((outer as f64 / 1000.0 - 0.25).abs() < 0.01);
+
+        let rolling = Shutter::Rolling { exposure: 0.1 };
+        let (top, bottom) = (times(rolling, 0, 11), times(rolling, 10, 11));
+        assert!(top.iter().all(|t| (2.0..2.2).contains(t)));
+        assert!(bottom.iter().all(|t| (3.8..4.0).contains(t)));
+        assert!((mean(&times(rolling, 5, 11)) - 3.0).abs() < 1e-9);
+    }
+}
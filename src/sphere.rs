@@ -1,10 +1,12 @@
 use crate::aabb::AABB;
 use crate::interval::Interval;
-use crate::utils::PI;
+use crate::onb::Onb;
+use crate::utils::{INFINITY, PI};
 use crate::HitRecord;
 use crate::Hittable;
 use crate::Material;
 use crate::Ray;
+use crate::Sampler;
 use crate::{Point3, Vec3};
 use std::sync::Arc;
 
@@ -50,10 +52,11 @@ impl Sphere {
     }
 
     // linearly interpolate from center0 to center1 according to time
-    // where time = 0 yields center0 and time = 1 yields center1
+    // where time = 0 yields center0 and time = 1 yields center1. the sphere rests there before
+    // and after, so it stays inside its bounding box whatever the shutter interval
     pub fn center(&self, time: f64) -> Point3 {
         if let Some(ref center_vec) = self.center_vec {
-            &self.center0 + time * center_vec
+            &self.center0 + time.clamp(0.0, 1.0) * center_vec
         } else {
             self.center0.clone()
         }
@@ -65,6 +68,18 @@ impl Sphere {
         let v = theta / PI;
         (u, v)
     }
+
+    // cosine of the half angle of the cone the sphere fills as seen from `origin`, None from
+    // inside the sphere
+    fn cos_theta_max(&self, origin: &Point3, time: f64) -> Option<f64> {
+        let distance_squared = (&self.center(time) - origin).length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            None
+        } else {
+            Some((1.0 - radius_squared / distance_squared).sqrt())
+        }
+    }
 }
 
 impl Hittable for Sphere {
@@ -72,7 +87,12 @@ impl Hittable for Sphere {
         self.bbox.clone()
     }
 
-    fn hit(&self, ray: &Ray, ray_t: &mut Interval) -> Option<HitRecord> {
+    fn hit(
+        &self,
+        ray: &Ray,
+        ray_t: &mut Interval,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<HitRecord> {
         let center = self.center(ray.tm);
         let oc = &ray.orig - &center;
         let a = ray.dir.dot(&ray.dir);
@@ -80,7 +100,7 @@ impl Hittable for Sphere {
         let c = oc.dot(&oc) - self.radius * self.radius;
         let discriminant = half_b * half_b - a * c;
         if discriminant < 0.0 {
-            return None;
+            None
         } else {
             // find the nearest root that lies in the acceptable range
             let mut root = (-half_b - discriminant.sqrt()) / a;
@@ -93,9 +113,43 @@ impl Hittable for Sphere {
             let mut hit_record =
                 HitRecord::new(ray.at(root), self.material.clone(), root, 0.0, 0.0);
             let outward_normal = (&hit_record.point - &center) / self.radius;
-            hit_record.set_face_normal(&ray, &outward_normal);
+            hit_record.set_face_normal(ray, &outward_normal);
             (hit_record.u, hit_record.v) = Self::get_shpere_uv(&outward_normal);
             Some(hit_record)
         }
     }
+
+    fn is_emitter(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    // uniform over the cone of directions the sphere fills
+    fn pdf_value(&self, ray: &Ray, sampler: &mut dyn Sampler) -> f64 {
+        let Some(cos_theta_max) = self.cos_theta_max(&ray.orig, ray.tm) else {
+            return 0.0;
+        };
+        if self
+            .hit(ray, &mut Interval::new(0.001, INFINITY), sampler)
+            .is_none()
+        {
+            return 0.0;
+        }
+        1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+    }
+
+    fn random_direction(&self, origin: &Point3, time: f64, sampler: &mut dyn Sampler) -> Vec3 {
+        let Some(cos_theta_max) = self.cos_theta_max(origin, time) else {
+            // every direction hits the sphere from inside, `pdf_value` is zero
+            return Vec3::random_unit_vec3(sampler);
+        };
+        let (r1, r2) = sampler.get_2d();
+        let z = 1.0 + r2 * (cos_theta_max - 1.0);
+        let phi = 2.0 * PI * r1;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        Onb::new(&(&self.center(time) - origin)).transform(&Vec3::new(
+            r * phi.cos(),
+            r * phi.sin(),
+            z,
+        ))
+    }
 }
@@ -1,10 +1,12 @@
 use crate::perlin::Perlin;
-use crate::traits::Texture;
+use crate::traits::{Sampler, Texture};
 use crate::Color;
+use crate::Result;
 use crate::{Interval, Point3};
 use image::io::Reader as ImageReader;
 use image::DynamicImage;
 use image::GenericImageView;
+use std::path::Path;
 use std::sync::Arc;
 
 #[derive(Debug, Clone)]
@@ -24,7 +26,7 @@ impl SolidColor {
 }
 
 impl Texture for SolidColor {
-    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+    fn value(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
         self.color_value.clone()
     }
 }
@@ -71,18 +73,18 @@ pub struct ImageTexture {
 }
 
 impl ImageTexture {
-    pub fn new(file_name: String) -> Self {
-        let image = ImageReader::open(file_name).unwrap().decode().unwrap();
-        Self {
+    pub fn new<P: AsRef<Path>>(file_name: P) -> Result<Self> {
+        let image = ImageReader::open(file_name)?.decode()?;
+        Ok(Self {
             image: Arc::new(image),
-        }
+        })
     }
 }
 
 impl Texture for ImageTexture {
-    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+    fn value(&self, u: f64, v: f64, _p: &Point3) -> Color {
         let (width, height) = self.image.dimensions();
-        if height <= 0 {
+        if height == 0 {
             return Color::new(0.0, 1.0, 1.0);
         }
 
@@ -101,22 +103,22 @@ impl Texture for ImageTexture {
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct NoiseTexture {
     scale: f64,
     noise: Perlin,
 }
 
 impl NoiseTexture {
-    pub fn new(scale: f64) -> Self {
+    pub fn new(scale: f64, sampler: &mut dyn Sampler) -> Self {
         Self {
             scale,
-            noise: Perlin::new(),
+            noise: Perlin::new(sampler),
         }
     }
 }
 impl Texture for NoiseTexture {
-    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+    fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
         let s = self.scale * p;
         //Color::new(1.0, 1.0, 1.0) * 0.5 * (1.0 + self.noise.noise(&(p * self.scale)))
         //Color::new(1.0, 1.0, 1.0) * self.noise.turb(&s, 7)
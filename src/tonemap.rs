@@ -0,0 +1,340 @@
+// How the linear radiance of a render becomes the colors of an image on a display.
+//
+// The radiance is scaled by `2^exposure`, white balanced and mapped into the range a display shows
+// by a tone mapping curve, before the encoder applies the sRGB transfer function. `clamp` simply
+// clips what is too bright, Reinhard compresses the luminance smoothly, ACES is the filmic curve
+// of the Academy Color Encoding System (as fitted by Stephen Hill) and AgX desaturates bright
+// colors towards white like film does (after Troy Sobotka's AgX). Linear formats like .exr keep
+// the radiance as rendered.
+use crate::color::luminance;
+use crate::framebuffer::Framebuffer;
+use crate::Color;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+type Matrix = [[f64; 3]; 3];
+
+const IDENTITY: Matrix = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+// the color temperature in kelvin that the white balance leaves as it is
+pub const NEUTRAL_WHITE_BALANCE: f64 = 6500.0;
+
+// the temperatures the approximation of the Planckian locus covers
+pub const WHITE_BALANCE_RANGE: RangeInclusive<f64> = 1667.0..=25000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ToneMap {
+    #[default]
+    Clamp,
+    Reinhard,
+    Aces,
+    Agx,
+}
+
+pub const TONE_MAP_NAMES: [&str; 4] = ["clamp", "reinhard", "aces", "agx"];
+
+impl ToneMap {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ToneMap::Clamp => "clamp",
+            ToneMap::Reinhard => "reinhard",
+            ToneMap::Aces => "aces",
+            ToneMap::Agx => "agx",
+        }
+    }
+
+    // the display linear color for `c`, from 0 to 1 except for `clamp`, which leaves the clipping
+    // to the encoder
+    pub fn apply(&self, c: &Color) -> Color {
+        match self {
+            ToneMap::Clamp => c.clone(),
+            ToneMap::Reinhard => {
+                let l = luminance(c).max(0.0);
+                clamp(&(c / (1.0 + l)))
+            }
+            ToneMap::Aces => {
+                let v = multiply(&ACES_INPUT, c);
+                let fit = |v: f64| {
+                    (v * (v + 0.0245786) - 0.000090537) / (v * (0.983729 * v + 0.432951) + 0.238081)
+                };
+                clamp(&multiply(&ACES_OUTPUT, &map(&v, fit)))
+            }
+            ToneMap::Agx => {
+                let v = multiply(&AGX_INPUT, &map(c, |x| x.max(1e-10)));
+                // the log encoding spans 16.5 stops around middle gray
+                let (min_ev, max_ev) = (-12.47393, 4.026069);
+                let v = map(&v, |x| {
+                    (x.log2().clamp(min_ev, max_ev) - min_ev) / (max_ev - min_ev)
+                });
+                // a polynomial fit of the sigmoid of the AgX base look
+                let contrast = |x: f64| {
+                    let (x2, x4) = (x * x, x * x * x * x);
+                    15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x
+                        + 0.4298 * x2
+                        + 0.1191 * x
+                        - 0.00232
+                };
+                let v = multiply(&AGX_OUTPUT, &map(&v, contrast));
+                // the curve produces display encoded values, the encoder expects linear ones
+                clamp(&map(&v, |x| x.max(0.0).powf(2.2)))
+            }
+        }
+    }
+}
+
+impl fmt::Display for ToneMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for ToneMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "clamp" => Ok(ToneMap::Clamp),
+            "reinhard" => Ok(ToneMap::Reinhard),
+            "aces" => Ok(ToneMap::Aces),
+            "agx" => Ok(ToneMap::Agx),
+            _ => Err(format!(
+                "unknown tone mapping `{}`, expected one of: {}",
+                s,
+                TONE_MAP_NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+// everything between the linear radiance and the sRGB encoder
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneMapping {
+    pub exposure: f64,      // in stops, each one doubles the brightness
+    pub white_balance: f64, // color temperature in kelvin of the light that should look white
+    pub tone_map: ToneMap,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        Self {
+            exposure: 0.0,
+            white_balance: NEUTRAL_WHITE_BALANCE,
+            tone_map: ToneMap::default(),
+        }
+    }
+}
+
+impl ToneMapping {
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    // the display linear color of `radiance`
+    pub fn apply(&self, radiance: &Color) -> Color {
+        self.apply_with(&self.white_balance_matrix(), radiance)
+    }
+
+    // a copy of `framebuffer` with every pixel tone mapped
+    pub fn apply_to(&self, framebuffer: &Framebuffer) -> Framebuffer {
+        let white_balance = self.white_balance_matrix();
+        let mut display = framebuffer.clone();
+        display.map_pixels(|c| self.apply_with(&white_balance, c));
+        display
+    }
+
+    fn apply_with(&self, white_balance: &Matrix, radiance: &Color) -> Color {
+        let c = multiply(white_balance, radiance) * self.exposure.exp2();
+        self.tone_map.apply(&c)
+    }
+
+    // a von Kries adaptation in the Bradford cone space that turns the white of a black body at
+    // `white_balance` kelvin into the white of one at 6500 kelvin, in linear sRGB
+    fn white_balance_matrix(&self) -> Matrix {
+        if self.white_balance == NEUTRAL_WHITE_BALANCE {
+            return IDENTITY;
+        }
+        let source = lms(self.white_balance);
+        let target = lms(NEUTRAL_WHITE_BALANCE);
+        let mut gains = [[0.0; 3]; 3];
+        for k in 0..3 {
+            gains[k][k] = target[k] / source[k];
+        }
+        let to_lms = product(&BRADFORD, &SRGB_TO_XYZ);
+        let from_lms = product(&XYZ_TO_SRGB, &BRADFORD_INVERSE);
+        product(&from_lms, &product(&gains, &to_lms))
+    }
+}
+
+impl fmt::Display for ToneMapping {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}, exposure {:+} stops, white balance {} K",
+            self.tone_map, self.exposure, self.white_balance
+        )
+    }
+}
+
+// the cone response to the white of a black body at `kelvin`, from the cubic spline approximation
+// of the Planckian locus by Kim et al.
+fn lms(kelvin: f64) -> [f64; 3] {
+    let t = kelvin.clamp(*WHITE_BALANCE_RANGE.start(), *WHITE_BALANCE_RANGE.end());
+    let (t2, t3) = (t * t, t * t * t);
+    let x = if t <= 4000.0 {
+        -0.2661239e9 / t3 - 0.2343589e6 / t2 + 0.8776956e3 / t + 0.179910
+    } else {
+        -3.0258469e9 / t3 + 2.1070379e6 / t2 + 0.2226347e3 / t + 0.240390
+    };
+    let (x2, x3) = (x * x, x * x * x);
+    let y = if t <= 2222.0 {
+        -1.1063814 * x3 - 1.34811020 * x2 + 2.18555832 * x - 0.20219683
+    } else if t <= 4000.0 {
+        -0.9549476 * x3 - 1.37418593 * x2 + 2.09137015 * x - 0.16748867
+    } else {
+        3.0817580 * x3 - 5.87338670 * x2 + 3.75112997 * x - 0.37001483
+    };
+    let xyz = Color::new(x / y, 1.0, (1.0 - x - y) / y);
+    let lms = multiply(&BRADFORD, &xyz);
+    [lms.x(), lms.y(), lms.z()]
+}
+
+const SRGB_TO_XYZ: Matrix = [
+    [0.4124, 0.3576, 0.1805],
+    [0.2126, 0.7152, 0.0722],
+    [0.0193, 0.1192, 0.9505],
+];
+
+const XYZ_TO_SRGB: Matrix = [
+    [3.2406, -1.5372, -0.4986],
+    [-0.9689, 1.8758, 0.0415],
+    [0.0557, -0.2040, 1.0570],
+];
+
+const BRADFORD: Matrix = [
+    [0.8951, 0.2664, -0.1614],
+    [-0.7502, 1.7135, 0.0367],
+    [0.0389, -0.0685, 1.0296],
+];
+
+const BRADFORD_INVERSE: Matrix = [
+    [0.9869929, -0.1470543, 0.1599627],
+    [0.4323053, 0.5183603, 0.0492912],
+    [-0.0085287, 0.0400428, 0.9684867],
+];
+
+// linear sRGB to the ACES working space combined with the RRT saturation
+const ACES_INPUT: Matrix = [
+    [0.59719, 0.35458, 0.04823],
+    [0.07600, 0.90834, 0.01566],
+    [0.02840, 0.13383, 0.83777],
+];
+
+// the ODT saturation combined with the ACES working space to linear sRGB
+const ACES_OUTPUT: Matrix = [
+    [1.60475, -0.53108, -0.07367],
+    [-0.10208, 1.10813, -0.00605],
+    [-0.00327, -0.07276, 1.07602],
+];
+
+// linear sRGB into the AgX working space, which leans the primaries so bright colors bleach
+const AGX_INPUT: Matrix = [
+    [0.842479062253094, 0.0784335999999992, 0.0792237451477643],
+    [0.0423282422610123, 0.878468636469772, 0.0791661274605434],
+    [0.0423756549057051, 0.0784336, 0.879142973793104],
+];
+
+const AGX_OUTPUT: Matrix = [
+    [1.19687900512017, -0.0980208811401368, -0.0990297440797205],
+    [-0.0528968517574562, 1.15190312990417, -0.0989611768448433],
+    [-0.0529716355144438, -0.0980434501171241, 1.15107367264116],
+];
+
+fn multiply(m: &Matrix, c: &Color) -> Color {
+    let row = |r: &[f64; 3]| r[0] * c.x() + r[1] * c.y() + r[2] * c.z();
+    Color::new(row(&m[0]), row(&m[1]), row(&m[2]))
+}
+
+fn product(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut m = [[0.0; 3]; 3];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    m
+}
+
+fn map<F>(c: &Color, f: F) -> Color
+where
+    F: Fn(f64) -> f64,
+{
+    Color::new(f(c.x()), f(c.y()), f(c.z()))
+}
+
+fn clamp(c: &Color) -> Color {
+    map(c, |x| x.clamp(0.0, 1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: &Color, b: &Color, tolerance: f64) {
+        assert!((a - b).length() < tolerance, "{} is not {}", a, b);
+    }
+
+    #[test]
+    fn test_tone_maps() {
+        for name in TONE_MAP_NAMES {
+            assert_eq!(name.parse::<ToneMap>().unwrap().name(), name);
+        }
+        assert!("filmic".parse::<ToneMap>().is_err());
+
+        let gray = Color::new(0.18, 0.18, 0.18);
+        let bright = Color::new(50.0, 10.0, 2.0);
+        for tone_map in [ToneMap::Reinhard, ToneMap::Aces, ToneMap::Agx] {
+            // bright colors stay displayable and keep their order, middle gray stays in the middle
+            let mapped = tone_map.apply(&bright);
+            assert!(mapped.x() <= 1.0 && mapped.x() >= mapped.y() && mapped.y() >= mapped.z());
+            let gray = tone_map.apply(&gray);
+            assert!((0.1..0.3).contains(&gray.y()), "{} {}", tone_map, gray);
+            assert!((gray.x() - gray.z()).abs() < 1e-3);
+            assert!(tone_map.apply(&Color::default()).y() < 0.01);
+        }
+        assert_eq!(ToneMap::Clamp.apply(&bright), bright);
+    }
+
+    #[test]
+    fn test_tone_mapping() {
+        let white = Color::new(1.0, 1.0, 1.0);
+        let neutral = ToneMapping::default();
+        assert!(neutral.is_identity());
+        assert_close(&neutral.apply(&white), &white, 1e-9);
+
+        let brighter = ToneMapping {
+            exposure: 1.0,
+            ..Default::default()
+        };
+        assert_close(&brighter.apply(&(0.25 * &white)), &(0.5 * &white), 1e-9);
+
+        // balancing for warm light makes white bluer, for cold light more orange
+        let warm = ToneMapping {
+            white_balance: 3200.0,
+            ..Default::default()
+        };
+        let c = warm.apply(&white);
+        assert!(c.z() > c.y() && c.y() > c.x(), "{}", c);
+        let cold = ToneMapping {
+            white_balance: 10000.0,
+            ..Default::default()
+        };
+        let c = cold.apply(&white);
+        assert!(c.x() > c.y() && c.y() > c.z(), "{}", c);
+
+        let mut framebuffer = Framebuffer::new(1, 1);
+        framebuffer.add_samples(0, 0, &Color::new(2.0, 2.0, 2.0), 4);
+        let display = brighter.apply_to(&framebuffer);
+        assert_close(&display.pixel(0, 0), &white, 1e-9);
+    }
+}
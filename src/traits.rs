@@ -4,6 +4,8 @@ use crate::Color;
 use crate::HitRecord;
 use crate::Point3;
 use crate::Ray;
+use crate::Vec3;
+use std::sync::Arc;
 
 #[derive(Debug, Clone, Default)]
 pub struct ScatterInfo {
@@ -11,19 +13,104 @@ pub struct ScatterInfo {
     pub ray_scattered: Ray,
 }
 pub trait Hittable: Send + Sync {
-    fn hit(&self, ray: &Ray, ray_t: &mut Interval) -> Option<HitRecord>;
+    fn hit(&self, ray: &Ray, ray_t: &mut Interval, sampler: &mut dyn Sampler) -> Option<HitRecord>;
 
     fn bounding_box(&self) -> AABB; // or ref?
+
+    // whether this is a surface with an emitting material, which light sampling aims at
+    fn is_emitter(&self) -> bool {
+        false
+    }
+
+    // the emitters inside a group or transform, in world space
+    fn emitters(&self) -> Vec<Arc<dyn Hittable>> {
+        Vec::new()
+    }
+
+    // probability density per unit solid angle of `random_direction` picking the direction of
+    // `ray` from its origin, zero if the ray misses the object
+    fn pdf_value(&self, _ray: &Ray, _sampler: &mut dyn Sampler) -> f64 {
+        0.0
+    }
+
+    // a direction from `origin` towards a random point of the object at time `time`
+    fn random_direction(&self, _origin: &Point3, _time: f64, _sampler: &mut dyn Sampler) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
 }
 
 pub trait Material: Send + Sync {
-    fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
+    fn emitted(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
         Color::new(0.0, 0.0, 0.0)
     }
     //TODO: might be better to combine attenuation and ray into one struct?
-    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterInfo>;
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterInfo>;
+
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    // whether `scatter` picks from a few exact directions, like mirrors and glass do. Light
+    // sampling cannot find those directions, so `eval` and `pdf` are only used for other materials
+    fn is_specular(&self) -> bool {
+        true
+    }
+
+    // the BSDF times the cosine term for light arriving along `direction` and leaving towards
+    // the origin of `ray_in`
+    fn eval(&self, _ray_in: &Ray, _rec: &HitRecord, _direction: &Vec3) -> Color {
+        Color::default()
+    }
+
+    // probability density per unit solid angle of `scatter` picking `direction`
+    fn pdf(&self, _ray_in: &Ray, _rec: &HitRecord, _direction: &Vec3) -> f64 {
+        0.0
+    }
+
+    // the color of the surface for the albedo pass, white for materials without one like glass
+    fn albedo(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        Color::new(1.0, 1.0, 1.0)
+    }
+
+    // the ID of the material for the material ID pass, 0 if it has none
+    fn id(&self) -> u32 {
+        0
+    }
 }
 
 pub trait Texture: Send + Sync {
     fn value(&self, u: f64, v: f64, p: &Point3) -> Color;
 }
+
+// source of the random numbers used while building and rendering a scene. While rendering,
+// each call takes the next dimension of the current sample, which stratified and low-discrepancy
+// samplers distribute well over all samples of a pixel
+pub trait Sampler {
+    // a number in [0, 1)
+    fn get_1d(&mut self) -> f64;
+
+    // two numbers in [0, 1), distributed well together e.g. for a point on the lens
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.get_1d(), self.get_1d())
+    }
+
+    // continue with the dimensions set aside for bounce `bounce` of the current path, so each
+    // bounce uses the same dimensions no matter how many numbers earlier bounces took
+    fn start_bounce(&mut self, _bounce: u32) {}
+
+    // a number in [min, max)
+    fn random_f64_range(&mut self, min: f64, max: f64) -> f64 {
+        min + (max - min) * self.get_1d()
+    }
+
+    // an integer in [min, max)
+    fn random_usize(&mut self, min: usize, max: usize) -> usize {
+        let n = min + (self.get_1d() * (max - min) as f64) as usize;
+        n.min(max - 1)
+    }
+}
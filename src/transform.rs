@@ -0,0 +1,819 @@
+// Affine transforms and instances of objects placed by them.
+//
+// A `Transform` keeps its 4x4 matrix together with the inverse, so that inverting is free and
+// normals can be transformed with the inverse transpose. `TransformedHittable` moves rays into the
+// object's space, hits the object there and moves the hit back. Ray directions are not normalized
+// on the way, so the hit distance `t` means the same in both spaces.
+//
+// An `AnimatedTransform` moves an object through keyframes in scene time: translation and scale
+// are interpolated linearly and rotation with quaternion slerp, at the time of each ray.
+use crate::aabb::AABB;
+use crate::hittables::emitters_of;
+use crate::interval::Interval;
+use crate::traits::{Hittable, Sampler};
+use crate::utils::degrees_to_radians;
+use crate::{HitRecord, Point3, Ray, Vec3};
+use std::sync::Arc;
+
+type Matrix = [[f64; 4]; 4];
+
+const IDENTITY: Matrix = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transform {
+    m: Matrix,
+    inv: Matrix,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Self {
+            m: IDENTITY,
+            inv: IDENTITY,
+        }
+    }
+
+    // none if the matrix cannot be inverted
+    pub fn from_matrix(m: Matrix) -> Option<Self> {
+        Some(Self {
+            m,
+            inv: invert(&m)?,
+        })
+    }
+
+    pub fn translate(offset: &Vec3) -> Self {
+        let mut m = IDENTITY;
+        let mut inv = IDENTITY;
+        for i in 0..3 {
+            m[i][3] = offset[i];
+            inv[i][3] = -offset[i];
+        }
+        Self { m, inv }
+    }
+
+    // the factors must not be zero
+    pub fn scale(factors: &Vec3) -> Self {
+        let mut m = IDENTITY;
+        let mut inv = IDENTITY;
+        for i in 0..3 {
+            m[i][i] = factors[i];
+            inv[i][i] = 1.0 / factors[i];
+        }
+        Self { m, inv }
+    }
+
+    // counterclockwise by `angle` degrees, looking from the tip of `axis` towards the origin
+    pub fn rotate(axis: &Vec3, angle: f64) -> Self {
+        let a = axis.make_unit_vector();
+        let (sin, cos) = degrees_to_radians(angle).sin_cos();
+        let mut m = IDENTITY;
+        for i in 0..3 {
+            for j in 0..3 {
+                m[i][j] = a[i] * a[j] * (1.0 - cos) + if i == j { cos } else { 0.0 };
+            }
+        }
+        m[0][1] -= a.z() * sin;
+        m[0][2] += a.y() * sin;
+        m[1][0] += a.z() * sin;
+        m[1][2] -= a.x() * sin;
+        m[2][0] -= a.y() * sin;
+        m[2][1] += a.x() * sin;
+        // a rotation is inverted by its transpose
+        Self {
+            m,
+            inv: transpose(&m),
+        }
+    }
+
+    pub fn rotate_x(angle: f64) -> Self {
+        Self::rotate(&Vec3::new(1.0, 0.0, 0.0), angle)
+    }
+
+    pub fn rotate_y(angle: f64) -> Self {
+        Self::rotate(&Vec3::new(0.0, 1.0, 0.0), angle)
+    }
+
+    pub fn rotate_z(angle: f64) -> Self {
+        Self::rotate(&Vec3::new(0.0, 0.0, 1.0), angle)
+    }
+
+    // places an object at `from` with its z axis pointing at `to` and its y axis towards `up`
+    pub fn look_at(from: &Point3, to: &Point3, up: &Vec3) -> Self {
+        let w = (to - from).make_unit_vector();
+        let u = up.cross(&w).make_unit_vector();
+        let v = w.cross(&u);
+        let mut m = IDENTITY;
+        for i in 0..3 {
+            m[i][0] = u[i];
+            m[i][1] = v[i];
+            m[i][2] = w[i];
+            m[i][3] = from[i];
+        }
+        // the inverse rotates back with the transpose, after moving `from` to the origin
+        let mut inv = transpose(&m);
+        inv[3] = [0.0, 0.0, 0.0, 1.0];
+        for row in inv.iter_mut().take(3) {
+            row[3] = -(row[0] * from[0] + row[1] * from[1] + row[2] * from[2]);
+        }
+        Self { m, inv }
+    }
+
+    // this transform followed by `next`
+    pub fn then(&self, next: &Transform) -> Self {
+        Self {
+            m: multiply(&next.m, &self.m),
+            inv: multiply(&self.inv, &next.inv),
+        }
+    }
+
+    pub fn inverse(&self) -> Self {
+        Self {
+            m: self.inv,
+            inv: self.m,
+        }
+    }
+
+    pub fn matrix(&self) -> &Matrix {
+        &self.m
+    }
+
+    pub fn point(&self, p: &Point3) -> Point3 {
+        let m = &self.m;
+        let row = |i: usize| m[i][0] * p.x() + m[i][1] * p.y() + m[i][2] * p.z() + m[i][3];
+        let w = row(3);
+        let p = Point3::new(row(0), row(1), row(2));
+        if w == 1.0 {
+            p
+        } else {
+            p / w
+        }
+    }
+
+    pub fn vector(&self, v: &Vec3) -> Vec3 {
+        let m = &self.m;
+        let row = |i: usize| m[i][0] * v.x() + m[i][1] * v.y() + m[i][2] * v.z();
+        Vec3::new(row(0), row(1), row(2))
+    }
+
+    // normals stay perpendicular to surfaces under the inverse transpose, the result is not unit
+    pub fn normal(&self, n: &Vec3) -> Vec3 {
+        let inv = &self.inv;
+        let column = |i: usize| inv[0][i] * n.x() + inv[1][i] * n.y() + inv[2][i] * n.z();
+        Vec3::new(column(0), column(1), column(2))
+    }
+
+    // determinant of the linear part, how much volumes grow
+    pub fn determinant(&self) -> f64 {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    // a box around the transformed corners of `bbox`
+    pub fn bbox(&self, bbox: &AABB) -> AABB {
+        if bbox.x.min > bbox.x.max || bbox.y.min > bbox.y.max || bbox.z.min > bbox.z.max {
+            // empty stays empty
+            return bbox.clone();
+        }
+        let mut result = AABB::default();
+        for i in 0..8 {
+            let corner = Point3::new(
+                if i & 1 == 0 { bbox.x.min } else { bbox.x.max },
+                if i & 2 == 0 { bbox.y.min } else { bbox.y.max },
+                if i & 4 == 0 { bbox.z.min } else { bbox.z.max },
+            );
+            let corner = self.point(&corner);
+            result = AABB::merge(&result, &AABB::new(&corner, &corner));
+        }
+        result
+    }
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut m = [[0.0; 4]; 4];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    m
+}
+
+fn transpose(m: &Matrix) -> Matrix {
+    let mut t = [[0.0; 4]; 4];
+    for (i, row) in t.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = m[j][i];
+        }
+    }
+    t
+}
+
+// gauss-jordan elimination with partial pivoting
+fn invert(m: &Matrix) -> Option<Matrix> {
+    let mut a = *m;
+    let mut inv = IDENTITY;
+    for column in 0..4 {
+        let pivot =
+            (column..4).max_by(|&i, &j| a[i][column].abs().total_cmp(&a[j][column].abs()))?;
+        if a[pivot][column].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(column, pivot);
+        inv.swap(column, pivot);
+        let scale = 1.0 / a[column][column];
+        for j in 0..4 {
+            a[column][j] *= scale;
+            inv[column][j] *= scale;
+        }
+        for row in 0..4 {
+            if row != column {
+                let factor = a[row][column];
+                for j in 0..4 {
+                    a[row][j] -= factor * a[column][j];
+                    inv[row][j] -= factor * inv[column][j];
+                }
+            }
+        }
+    }
+    Some(inv)
+}
+
+// a rotation as a unit quaternion
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quaternion {
+    pub w: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Default for Quaternion {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Quaternion {
+    pub fn identity() -> Self {
+        Self {
+            w: 1.0,
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        }
+    }
+
+    // the same rotation as `Transform::rotate(axis, angle)`
+    pub fn from_axis_angle(axis: &Vec3, angle: f64) -> Self {
+        let a = axis.make_unit_vector();
+        let (sin, cos) = (degrees_to_radians(angle) / 2.0).sin_cos();
+        Self {
+            w: cos,
+            x: a.x() * sin,
+            y: a.y() * sin,
+            z: a.z() * sin,
+        }
+    }
+
+    // this rotation followed by `next`
+    pub fn then(&self, next: &Quaternion) -> Self {
+        let (a, b) = (next, self);
+        Self {
+            w: a.w * b.w - a.x * b.x - a.y * b.y - a.z * b.z,
+            x: a.w * b.x + a.x * b.w + a.y * b.z - a.z * b.y,
+            y: a.w * b.y - a.x * b.z + a.y * b.w + a.z * b.x,
+            z: a.w * b.z + a.x * b.y - a.y * b.x + a.z * b.w,
+        }
+    }
+
+    fn dot(&self, other: &Quaternion) -> f64 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    fn scaled(&self, s: f64) -> Self {
+        Self {
+            w: self.w * s,
+            x: self.x * s,
+            y: self.y * s,
+            z: self.z * s,
+        }
+    }
+
+    fn plus(&self, other: &Quaternion) -> Self {
+        Self {
+            w: self.w + other.w,
+            x: self.x + other.x,
+            y: self.y + other.y,
+            z: self.z + other.z,
+        }
+    }
+
+    // constant speed interpolation along the shorter arc from `a` (t = 0) to `b` (t = 1)
+    pub fn slerp(a: &Quaternion, b: &Quaternion, t: f64) -> Self {
+        let mut cos = a.dot(b);
+        // q and -q are the same rotation, take the one closer to `a`
+        let b = if cos < 0.0 {
+            cos = -cos;
+            b.scaled(-1.0)
+        } else {
+            *b
+        };
+        let q = if cos > 0.9995 {
+            // nearly the same rotation, where lerp is as good and stays stable
+            a.scaled(1.0 - t).plus(&b.scaled(t))
+        } else {
+            let theta = cos.acos();
+            let sin = theta.sin();
+            a.scaled(((1.0 - t) * theta).sin() / sin)
+                .plus(&b.scaled((t * theta).sin() / sin))
+        };
+        q.scaled(1.0 / q.dot(&q).sqrt())
+    }
+
+    pub fn to_transform(&self) -> Transform {
+        let Quaternion { w, x, y, z } = *self;
+        let mut m = IDENTITY;
+        m[0] = [
+            1.0 - 2.0 * (y * y + z * z),
+            2.0 * (x * y - w * z),
+            2.0 * (x * z + w * y),
+            0.0,
+        ];
+        m[1] = [
+            2.0 * (x * y + w * z),
+            1.0 - 2.0 * (x * x + z * z),
+            2.0 * (y * z - w * x),
+            0.0,
+        ];
+        m[2] = [
+            2.0 * (x * z - w * y),
+            2.0 * (y * z + w * x),
+            1.0 - 2.0 * (x * x + y * y),
+            0.0,
+        ];
+        Transform {
+            m,
+            inv: transpose(&m),
+        }
+    }
+}
+
+// where an animated object is at `time`: scaled, then rotated, then translated
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keyframe {
+    pub time: f64,
+    pub translation: Vec3,
+    pub rotation: Quaternion,
+    pub scale: Vec3,
+}
+
+impl Keyframe {
+    pub fn new(time: f64) -> Self {
+        Self {
+            time,
+            translation: Vec3::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::identity(),
+            scale: Vec3::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    pub fn transform(&self) -> Transform {
+        Transform::scale(&self.scale)
+            .then(&self.rotation.to_transform())
+            .then(&Transform::translate(&self.translation))
+    }
+}
+
+// keyframes interpolated over time, holding the first and last keyframe outside their range
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimatedTransform {
+    keyframes: Vec<Keyframe>,
+}
+
+impl AnimatedTransform {
+    // panics without keyframes
+    pub fn new(mut keyframes: Vec<Keyframe>) -> Self {
+        assert!(
+            !keyframes.is_empty(),
+            "an animation needs at least one keyframe"
+        );
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Self { keyframes }
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    pub fn at(&self, time: f64) -> Transform {
+        let keyframes = &self.keyframes;
+        // the first keyframe later than `time`
+        let next = keyframes.partition_point(|keyframe| keyframe.time <= time);
+        if next == 0 {
+            return keyframes[0].transform();
+        }
+        if next == keyframes.len() {
+            return keyframes[next - 1].transform();
+        }
+        let (a, b) = (&keyframes[next - 1], &keyframes[next]);
+        let t = (time - a.time) / (b.time - a.time);
+        Keyframe {
+            time,
+            translation: (1.0 - t) * &a.translation + t * &b.translation,
+            rotation: Quaternion::slerp(&a.rotation, &b.rotation, t),
+            scale: (1.0 - t) * &a.scale + t * &b.scale,
+        }
+        .transform()
+    }
+
+    // a box around everywhere `bbox` goes during the animation
+    pub fn bbox(&self, bbox: &AABB) -> AABB {
+        let mut result = AABB::default();
+        for keyframe in &self.keyframes {
+            result = AABB::merge(&result, &keyframe.transform().bbox(bbox));
+        }
+        if bbox.x.min > bbox.x.max || bbox.y.min > bbox.y.max || bbox.z.min > bbox.z.max {
+            return result;
+        }
+        for pair in self.keyframes.windows(2) {
+            let (a, b) = (&pair[0], &pair[1]);
+            if a.rotation.dot(&b.rotation).abs() > 1.0 - 1e-12 {
+                // without rotation every point moves along a line, covered by the keyframe boxes
+                continue;
+            }
+            // while rotating, the scaled object stays within `radius` of the translation
+            let reach = |axis: usize| {
+                let interval = bbox.axis(axis as u32);
+                interval.min.abs().max(interval.max.abs())
+                    * a.scale[axis].abs().max(b.scale[axis].abs())
+            };
+            let diameter = 2.0 * Vec3::new(reach(0), reach(1), reach(2)).length();
+            let path = AABB::new(&a.translation, &b.translation);
+            let swept = AABB {
+                x: path.x.expand(diameter),
+                y: path.y.expand(diameter),
+                z: path.z.expand(diameter),
+            };
+            result = AABB::merge(&result, &swept);
+        }
+        result
+    }
+}
+
+// how a `TransformedHittable` is placed. fixed placements are the common case and stay inline
+#[derive(Clone)]
+#[allow(clippy::large_enum_variant)]
+enum Placement {
+    Fixed {
+        to_world: Transform,
+        to_object: Transform,
+    },
+    Animated(AnimatedTransform),
+}
+
+// an object placed in the world by a transform, fixed or animated
+pub struct TransformedHittable {
+    object: Arc<dyn Hittable>,
+    placement: Placement,
+    bbox: AABB,
+}
+
+impl TransformedHittable {
+    pub fn new(object: Arc<dyn Hittable>, transform: Transform) -> Self {
+        let bbox = transform.bbox(&object.bounding_box());
+        Self {
+            object,
+            placement: Placement::Fixed {
+                to_object: transform.inverse(),
+                to_world: transform,
+            },
+            bbox,
+        }
+    }
+
+    pub fn animated(object: Arc<dyn Hittable>, animation: AnimatedTransform) -> Self {
+        let bbox = animation.bbox(&object.bounding_box());
+        Self {
+            object,
+            placement: Placement::Animated(animation),
+            bbox,
+        }
+    }
+
+    // calls `f` with the transforms to the world and to the object at `time`
+    fn at<R>(&self, time: f64, f: impl FnOnce(&Transform, &Transform) -> R) -> R {
+        match &self.placement {
+            Placement::Fixed {
+                to_world,
+                to_object,
+            } => f(to_world, to_object),
+            Placement::Animated(animation) => {
+                let to_world = animation.at(time);
+                f(&to_world, &to_world.inverse())
+            }
+        }
+    }
+}
+
+fn ray_to_object(ray: &Ray, to_object: &Transform) -> Ray {
+    Ray::new(
+        to_object.point(&ray.orig),
+        to_object.vector(&ray.dir),
+        ray.tm,
+    )
+}
+
+impl Hittable for TransformedHittable {
+    fn bounding_box(&self) -> AABB {
+        self.bbox.clone()
+    }
+
+    fn hit(&self, ray: &Ray, ray_t: &mut Interval, sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        self.at(ray.tm, |to_world, to_object| {
+            let rec = self
+                .object
+                .hit(&ray_to_object(ray, to_object), ray_t, sampler)?;
+            Some(HitRecord {
+                point: to_world.point(&rec.point),
+                normal: to_world.normal(&rec.normal).make_unit_vector(),
+                ..rec
+            })
+        })
+    }
+
+    fn emitters(&self) -> Vec<Arc<dyn Hittable>> {
+        emitters_of(&self.object)
+            .into_iter()
+            .map(|emitter| {
+                let bbox = match &self.placement {
+                    Placement::Fixed { to_world, .. } => to_world.bbox(&emitter.bounding_box()),
+                    Placement::Animated(animation) => animation.bbox(&emitter.bounding_box()),
+                };
+                Arc::new(TransformedHittable {
+                    object: emitter,
+                    placement: self.placement.clone(),
+                    bbox,
+                }) as Arc<dyn Hittable>
+            })
+            .collect()
+    }
+
+    // densities are per solid angle, which a linear map A stretches by |det A| / |A w|^3 around
+    // the object space direction w
+    fn pdf_value(&self, ray: &Ray, sampler: &mut dyn Sampler) -> f64 {
+        self.at(ray.tm, |to_world, to_object| {
+            let object_ray = ray_to_object(ray, to_object);
+            let pdf = self.object.pdf_value(&object_ray, sampler);
+            if pdf == 0.0 {
+                return 0.0;
+            }
+            let w = object_ray.dir.make_unit_vector();
+            pdf * to_world.vector(&w).length().powi(3) / to_world.determinant().abs()
+        })
+    }
+
+    fn random_direction(&self, origin: &Point3, time: f64, sampler: &mut dyn Sampler) -> Vec3 {
+        self.at(time, |to_world, to_object| {
+            let direction = self
+                .object
+                .random_direction(&to_object.point(origin), time, sampler);
+            to_world.vector(&direction)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittables::Hittables;
+    use crate::material::{DiffuseLight, Lambertian};
+    use crate::quad::Quad;
+    use crate::sphere::Sphere;
+    use crate::utils::{INFINITY, PI};
+    use crate::{Color, RandomSampler};
+
+    fn assert_close(a: &Vec3, b: &Vec3) {
+        assert!((a - b).length() < 1e-9, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn test_transform() {
+        let p = Point3::new(1.0, 2.0, 3.0);
+        let t = Transform::rotate_y(90.0);
+        assert_close(
+            &t.point(&Point3::new(1.0, 0.0, 0.0)),
+            &Point3::new(0.0, 0.0, -1.0),
+        );
+        let t = Transform::rotate_z(90.0);
+        assert_close(
+            &t.point(&Point3::new(1.0, 0.0, 0.0)),
+            &Point3::new(0.0, 1.0, 0.0),
+        );
+
+        let t = Transform::scale(&Vec3::new(2.0, 3.0, 4.0))
+            .then(&Transform::rotate(&Vec3::new(1.0, 1.0, 0.0), 30.0))
+            .then(&Transform::translate(&Vec3::new(5.0, 0.0, -1.0)));
+        assert_close(&t.inverse().point(&t.point(&p)), &p);
+        assert_close(
+            &Transform::from_matrix(*t.matrix())
+                .unwrap()
+                .inverse()
+                .point(&p),
+            &t.inverse().point(&p),
+        );
+        assert!((t.determinant() - 24.0).abs() < 1e-9);
+        assert!(Transform::from_matrix([[0.0; 4]; 4]).is_none());
+
+        // normals stay perpendicular to transformed tangents
+        let (tangent, normal) = (Vec3::new(1.0, -1.0, 0.5), Vec3::new(1.0, 1.0, 0.0));
+        assert!(t.vector(&tangent).dot(&t.normal(&normal)).abs() < 1e-9);
+
+        let t = Transform::look_at(&p, &Point3::new(1.0, 2.0, 10.0), &Vec3::new(0.0, 1.0, 0.0));
+        assert_close(&t.point(&Point3::default()), &p);
+        assert_close(
+            &t.vector(&Vec3::new(0.0, 0.0, 1.0)),
+            &Vec3::new(0.0, 0.0, 1.0),
+        );
+        assert_close(&t.inverse().point(&p), &Point3::default());
+    }
+
+    #[test]
+    fn test_quaternion() {
+        let axis = Vec3::new(1.0, 2.0, -0.5);
+        let p = Point3::new(0.3, -1.0, 2.0);
+        let q = Quaternion::from_axis_angle(&axis, 70.0);
+        assert_close(
+            &q.to_transform().point(&p),
+            &Transform::rotate(&axis, 70.0).point(&p),
+        );
+        let q = Quaternion::from_axis_angle(&Vec3::new(1.0, 0.0, 0.0), 90.0).then(
+            &Quaternion::from_axis_angle(&Vec3::new(0.0, 1.0, 0.0), 90.0),
+        );
+        let t = Transform::rotate_x(90.0).then(&Transform::rotate_y(90.0));
+        assert_close(&q.to_transform().point(&p), &t.point(&p));
+
+        // halfway along the arc, also when the quaternions of the ends have opposite signs
+        let (a, b) = (
+            Quaternion::from_axis_angle(&Vec3::new(0.0, 1.0, 0.0), 0.0),
+            Quaternion::from_axis_angle(&Vec3::new(0.0, 1.0, 0.0), 120.0),
+        );
+        for b in [b, b.scaled(-1.0)] {
+            assert_close(
+                &Quaternion::slerp(&a, &b, 0.5).to_transform().point(&p),
+                &Transform::rotate_y(60.0).point(&p),
+            );
+        }
+    }
+
+    #[test]
+    fn test_animated_hittable() {
+        let material = Arc::new(Lambertian::new_from_color(Color::new(0.5, 0.5, 0.5)));
+        let object = crate::quad::create_box(
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(2.0, 1.0, 0.5),
+            material,
+        );
+        let mut keyframes = vec![Keyframe::new(0.0), Keyframe::new(0.5), Keyframe::new(1.0)];
+        keyframes[1].rotation = Quaternion::from_axis_angle(&Vec3::new(0.0, 1.0, 0.0), 90.0);
+        keyframes[1].translation = Vec3::new(0.0, 3.0, 0.0);
+        keyframes[2].rotation = Quaternion::from_axis_angle(&Vec3::new(1.0, 0.0, 1.0), 150.0);
+        keyframes[2].scale = Vec3::new(2.0, 1.0, 0.5);
+        let animation = AnimatedTransform::new(keyframes);
+        let animated = TransformedHittable::animated(object.clone(), animation.clone());
+
+        // the box covers the object all the time, in between keyframes too
+        let bbox = animated.bounding_box();
+        for i in 0..=100 {
+            let moment = animation.at(i as f64 / 100.0).bbox(&object.bounding_box());
+            for axis in 0..3 {
+                assert!(bbox.axis(axis).min <= moment.axis(axis).min + 1e-9);
+                assert!(bbox.axis(axis).max >= moment.axis(axis).max - 1e-9);
+            }
+        }
+
+        // rays hit the object where it is at their time
+        let mut sampler = RandomSampler::new(0);
+        let ray = |time| {
+            Ray::new(
+                Point3::new(1.5, 10.0, 0.25),
+                Vec3::new(0.0, -1.0, 0.0),
+                time,
+            )
+        };
+        let rec = animated
+            .hit(&ray(0.0), &mut Interval::new(0.001, INFINITY), &mut sampler)
+            .unwrap();
+        assert!((rec.t - 9.0).abs() < 1e-9);
+        // at 0.5 the box has turned to x in [0, 0.5] and z in [-2, -1]
+        assert!(animated
+            .hit(&ray(0.5), &mut Interval::new(0.001, INFINITY), &mut sampler)
+            .is_none());
+        let ray = Ray::new(
+            Point3::new(0.25, 10.0, -1.5),
+            Vec3::new(0.0, -1.0, 0.0),
+            0.5,
+        );
+        let rec = animated
+            .hit(&ray, &mut Interval::new(0.001, INFINITY), &mut sampler)
+            .unwrap();
+        assert!((rec.t - 6.0).abs() < 1e-9);
+        assert_close(&rec.normal, &Vec3::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn test_transformed_hittable() {
+        let material = Arc::new(Lambertian::new_from_color(Color::new(0.5, 0.5, 0.5)));
+        let sphere = Arc::new(Sphere::new(Point3::default(), 1.0, material));
+        // an ellipsoid, 2 wide in x, moved to x = 10
+        let transform = Transform::scale(&Vec3::new(2.0, 1.0, 1.0))
+            .then(&Transform::translate(&Vec3::new(10.0, 0.0, 0.0)));
+        let ellipsoid = TransformedHittable::new(sphere, transform);
+        let bbox = ellipsoid.bounding_box();
+        assert!((bbox.x.min - 8.0).abs() < 1e-9 && (bbox.x.max - 12.0).abs() < 1e-9);
+        assert!((bbox.y.min + 1.0).abs() < 1e-9 && (bbox.z.max - 1.0).abs() < 1e-9);
+
+        let mut sampler = RandomSampler::new(0);
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        let rec = ellipsoid
+            .hit(&ray, &mut Interval::new(0.001, INFINITY), &mut sampler)
+            .unwrap();
+        assert!((rec.t - 8.0).abs() < 1e-9);
+        assert_close(&rec.normal, &Vec3::new(-1.0, 0.0, 0.0));
+
+        // off the axis the normal of an ellipsoid is not the direction from its center
+        let ray = Ray::new(
+            Point3::new(10.0 + 2f64.sqrt(), 0.0, 10.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            0.0,
+        );
+        let rec = ellipsoid
+            .hit(&ray, &mut Interval::new(0.001, INFINITY), &mut sampler)
+            .unwrap();
+        assert_close(
+            &rec.point,
+            &Point3::new(10.0 + 2f64.sqrt(), 0.0, 0.5f64.sqrt()),
+        );
+        assert_close(&rec.normal, &Vec3::new(0.5, 0.0, 1.0).make_unit_vector());
+    }
+
+    #[test]
+    fn test_light_sampling() {
+        let light = Arc::new(DiffuseLight::new_from_color(Color::new(4.0, 4.0, 4.0)));
+        let diffuse = Arc::new(Lambertian::new_from_color(Color::new(0.5, 0.5, 0.5)));
+        let mut group = Hittables::default();
+        group.add(Arc::new(Quad::new(
+            Point3::new(-1.0, 2.0, -1.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            light.clone(),
+        )));
+        group.add(Arc::new(Sphere::new(
+            Point3::new(0.0, -3.0, 0.0),
+            1.0,
+            light,
+        )));
+        group.add(Arc::new(Sphere::new(
+            Point3::new(3.0, 0.0, 0.0),
+            1.0,
+            diffuse,
+        )));
+        // non-uniform scale distorts solid angles, which the densities must account for
+        let transform = Transform::scale(&Vec3::new(1.5, 0.5, 1.0))
+            .then(&Transform::rotate_y(30.0))
+            .then(&Transform::translate(&Vec3::new(0.0, 0.0, 1.0)));
+        let world = TransformedHittable::new(Arc::new(group), transform);
+
+        let mut lights = Hittables::default();
+        for emitter in world.emitters() {
+            lights.add(emitter);
+        }
+        assert_eq!(lights.objects.len(), 2);
+
+        // sampled directions hit the lights, and the density integrates to one over the sphere
+        let origin = Point3::new(0.1, 0.2, 0.7);
+        let mut sampler = RandomSampler::new(1);
+        let n = 20000;
+        let mut integral = 0.0;
+        for _ in 0..n {
+            let direction = lights.random_direction(&origin, 0.0, &mut sampler);
+            let ray = Ray::new(origin.clone(), direction, 0.0);
+            assert!(lights.pdf_value(&ray, &mut sampler) > 0.0);
+            let ray = Ray::new(origin.clone(), Vec3::random_unit_vec3(&mut sampler), 0.0);
+            integral += lights.pdf_value(&ray, &mut sampler) * 4.0 * PI;
+        }
+        assert!((integral / n as f64 - 1.0).abs() < 0.05);
+    }
+}
@@ -0,0 +1,431 @@
+use crate::aabb::AABB;
+use crate::bvh::Bvh;
+use crate::interval::Interval;
+use crate::utils::INFINITY;
+use crate::{HitRecord, Hittable, Material, Point3, Ray, Sampler, Vec3};
+use std::sync::Arc;
+
+// a single triangle. Without per-vertex texture coordinates u and v are the barycentric
+// coordinates of the hit point, without per-vertex normals the face normal is used
+#[derive(Clone)]
+pub struct Triangle {
+    vertices: [Point3; 3],
+    normals: Option<[Vec3; 3]>,
+    uvs: Option<[(f64, f64); 3]>,
+    material: Arc<dyn Material>,
+}
+
+impl Triangle {
+    pub fn new(vertices: [Point3; 3], material: Arc<dyn Material>) -> Self {
+        Self {
+            vertices,
+            normals: None,
+            uvs: None,
+            material,
+        }
+    }
+
+    // normals at the vertices, interpolated across the triangle for smooth shading
+    pub fn with_normals(mut self, normals: [Vec3; 3]) -> Self {
+        self.normals = Some(normals);
+        self
+    }
+
+    // texture coordinates at the vertices
+    pub fn with_uvs(mut self, uvs: [(f64, f64); 3]) -> Self {
+        self.uvs = Some(uvs);
+        self
+    }
+
+    fn vertices(&self) -> Vertices<'_> {
+        Vertices {
+            positions: [&self.vertices[0], &self.vertices[1], &self.vertices[2]],
+            normals: self.normals.as_ref().map(|n| [&n[0], &n[1], &n[2]]),
+            uvs: self.uvs,
+            material: &self.material,
+        }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(
+        &self,
+        ray: &Ray,
+        ray_t: &mut Interval,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<HitRecord> {
+        self.vertices().hit(ray, ray_t)
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.vertices().bounding_box()
+    }
+
+    fn is_emitter(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn pdf_value(&self, ray: &Ray, _sampler: &mut dyn Sampler) -> f64 {
+        self.vertices().pdf_value(ray)
+    }
+
+    fn random_direction(&self, origin: &Point3, _time: f64, sampler: &mut dyn Sampler) -> Vec3 {
+        self.vertices().random_direction(origin, sampler)
+    }
+}
+
+// vertex buffers and the triangles indexing into them. `normals` and `uvs` are either empty or
+// hold one entry per position
+#[derive(Debug, Clone, Default)]
+pub struct Mesh {
+    pub positions: Vec<Point3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f64, f64)>,
+    pub indices: Vec<[u32; 3]>,
+}
+
+impl Mesh {
+    // check that the buffers fit together
+    pub fn validate(&self) -> Result<(), String> {
+        if self.indices.is_empty() {
+            return Err("a mesh needs at least one triangle".to_string());
+        }
+        let vertices = self.positions.len();
+        if !self.normals.is_empty() && self.normals.len() != vertices {
+            return Err(format!(
+                "a mesh with {} positions has {} normals",
+                vertices,
+                self.normals.len()
+            ));
+        }
+        if !self.uvs.is_empty() && self.uvs.len() != vertices {
+            return Err(format!(
+                "a mesh with {} positions has {} texture coordinates",
+                vertices,
+                self.uvs.len()
+            ));
+        }
+        if let Some(index) = self
+            .indices
+            .iter()
+            .flatten()
+            .find(|&&i| i as usize >= vertices)
+        {
+            return Err(format!(
+                "vertex index {} is out of range for {} positions",
+                index, vertices
+            ));
+        }
+        Ok(())
+    }
+}
+
+// triangles sharing one set of vertex buffers and one material, with a BVH over the triangles
+pub struct TriangleMesh {
+    bvh: Bvh,
+    triangles: usize,
+}
+
+impl TriangleMesh {
+    // panics if the mesh does not pass `Mesh::validate`
+    pub fn new(mesh: Mesh, material: Arc<dyn Material>) -> Self {
+        if let Err(message) = mesh.validate() {
+            panic!("invalid mesh: {}", message);
+        }
+        let data = Arc::new(MeshData { mesh, material });
+        let triangles: Vec<Arc<dyn Hittable>> = (0..data.mesh.indices.len())
+            .map(|index| {
+                Arc::new(MeshTriangle {
+                    data: data.clone(),
+                    index,
+                }) as Arc<dyn Hittable>
+            })
+            .collect();
+        Self {
+            bvh: Bvh::new(&triangles),
+            triangles: triangles.len(),
+        }
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.triangles
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, ray: &Ray, ray_t: &mut Interval, sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        self.bvh.hit(ray, ray_t, sampler)
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bvh.bounding_box()
+    }
+
+    // every triangle of an emitting mesh is a light of its own
+    fn emitters(&self) -> Vec<Arc<dyn Hittable>> {
+        self.bvh.emitters()
+    }
+}
+
+struct MeshData {
+    mesh: Mesh,
+    material: Arc<dyn Material>,
+}
+
+// one triangle of a mesh, by its index into the shared buffers
+struct MeshTriangle {
+    data: Arc<MeshData>,
+    index: usize,
+}
+
+impl MeshTriangle {
+    fn vertices(&self) -> Vertices<'_> {
+        let mesh = &self.data.mesh;
+        let [a, b, c] = mesh.indices[self.index].map(|i| i as usize);
+        Vertices {
+            positions: [&mesh.positions[a], &mesh.positions[b], &mesh.positions[c]],
+            normals: (!mesh.normals.is_empty())
+                .then(|| [&mesh.normals[a], &mesh.normals[b], &mesh.normals[c]]),
+            uvs: (!mesh.uvs.is_empty()).then(|| [mesh.uvs[a], mesh.uvs[b], mesh.uvs[c]]),
+            material: &self.data.material,
+        }
+    }
+}
+
+impl Hittable for MeshTriangle {
+    fn hit(
+        &self,
+        ray: &Ray,
+        ray_t: &mut Interval,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<HitRecord> {
+        self.vertices().hit(ray, ray_t)
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.vertices().bounding_box()
+    }
+
+    fn is_emitter(&self) -> bool {
+        self.data.material.is_emissive()
+    }
+
+    fn pdf_value(&self, ray: &Ray, _sampler: &mut dyn Sampler) -> f64 {
+        self.vertices().pdf_value(ray)
+    }
+
+    fn random_direction(&self, origin: &Point3, _time: f64, sampler: &mut dyn Sampler) -> Vec3 {
+        self.vertices().random_direction(origin, sampler)
+    }
+}
+
+// the attributes of one triangle, wherever they are stored
+struct Vertices<'a> {
+    positions: [&'a Point3; 3],
+    normals: Option<[&'a Vec3; 3]>,
+    uvs: Option<[(f64, f64); 3]>,
+    material: &'a Arc<dyn Material>,
+}
+
+impl Vertices<'_> {
+    fn edges(&self) -> (Vec3, Vec3) {
+        let [a, b, c] = self.positions;
+        (b - a, c - a)
+    }
+
+    fn bounding_box(&self) -> AABB {
+        let [a, b, c] = self.positions;
+        AABB::merge(&AABB::new(a, b), &AABB::new(a, c)).pad()
+    }
+
+    // Möller-Trumbore intersection
+    fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
+        let (e1, e2) = self.edges();
+        let p = ray.dir.cross(&e2);
+        let det = e1.dot(&p);
+        // no hit if the ray is parallel to the plane
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+        let s = &ray.orig - self.positions[0];
+        let b1 = s.dot(&p) * inv_det;
+        if !(0.0..=1.0).contains(&b1) {
+            return None;
+        }
+        let q = s.cross(&e1);
+        let b2 = ray.dir.dot(&q) * inv_det;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return None;
+        }
+        let t = e2.dot(&q) * inv_det;
+        if !ray_t.contains(t) {
+            return None;
+        }
+
+        let b0 = 1.0 - b1 - b2;
+        let (u, v) = match self.uvs {
+            Some([uv0, uv1, uv2]) => (
+                b0 * uv0.0 + b1 * uv1.0 + b2 * uv2.0,
+                b0 * uv0.1 + b1 * uv1.1 + b2 * uv2.1,
+            ),
+            None => (b1, b2),
+        };
+        let mut rec = HitRecord::new(ray.at(t), self.material.clone(), t, u, v);
+        rec.set_face_normal(ray, &e1.cross(&e2).make_unit_vector());
+        if let Some([n0, n1, n2]) = self.normals {
+            let shading_normal = (n0 * b0 + n1 * b1 + n2 * b2).make_unit_vector();
+            // keep the shading normal on the side of the surface the ray came from
+            rec.normal = if shading_normal.dot(&rec.normal) < 0.0 {
+                -&shading_normal
+            } else {
+                shading_normal
+            };
+        }
+        Some(rec)
+    }
+
+    // uniform over the area, converted to solid angle
+    fn pdf_value(&self, ray: &Ray) -> f64 {
+        let Some(rec) = self.hit(ray, &Interval::new(0.001, INFINITY)) else {
+            return 0.0;
+        };
+        let (e1, e2) = self.edges();
+        let n = e1.cross(&e2);
+        let area = n.length() / 2.0;
+        let distance_squared = rec.t * rec.t * ray.dir.length_squared();
+        let cosine = (ray.dir.dot(&n) / (ray.dir.length() * n.length())).abs();
+        distance_squared / (cosine * area)
+    }
+
+    fn random_direction(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let (mut a, mut b) = sampler.get_2d();
+        // fold the far half of the parallelogram onto the triangle
+        if a + b > 1.0 {
+            (a, b) = (1.0 - a, 1.0 - b);
+        }
+        let (e1, e2) = self.edges();
+        &(self.positions[0] + &(e1 * a + e2 * b)) - origin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::transform::{Transform, TransformedHittable};
+    use crate::{Color, Hittables, RandomSampler};
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new_from_color(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    fn hit(object: &dyn Hittable, ray: &Ray) -> Option<HitRecord> {
+        object.hit(
+            ray,
+            &mut Interval::new(0.001, INFINITY),
+            &mut RandomSampler::new(0),
+        )
+    }
+
+    #[test]
+    fn test_triangle() {
+        let triangle = Triangle::new(
+            [
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(1.0, 0.0, 0.0),
+                Point3::new(0.0, 1.0, 0.0),
+            ],
+            material(),
+        );
+        let ray = Ray::new(Point3::new(0.25, 0.5, 2.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let rec = hit(&triangle, &ray).unwrap();
+        assert!((rec.t - 2.0).abs() < 1e-12);
+        assert_eq!((rec.u, rec.v), (0.25, 0.5));
+        assert_eq!(rec.normal, Vec3::new(0.0, 0.0, 1.0));
+        assert!(rec.front_face);
+        let miss = Ray::new(Point3::new(0.75, 0.5, 2.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!(hit(&triangle, &miss).is_none());
+
+        // interpolated attributes, seen from behind
+        let triangle = triangle
+            .with_uvs([(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)])
+            .with_normals([
+                Vec3::new(0.0, 0.0, 1.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, 1.0),
+            ]);
+        let ray = Ray::new(Point3::new(0.5, 0.25, -1.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let rec = hit(&triangle, &ray).unwrap();
+        assert!(!rec.front_face);
+        assert!((rec.u - 0.75).abs() < 1e-12 && (rec.v - 0.25).abs() < 1e-12);
+        let expected = -&Vec3::new(0.5, 0.0, 0.5).make_unit_vector();
+        assert!((&rec.normal - &expected).length() < 1e-12);
+
+        // light sampling hits the triangle, and the density integrates to one over the sphere
+        let origin = Point3::new(0.2, 0.3, 1.0);
+        let mut sampler = RandomSampler::new(2);
+        let n = 100000;
+        let mut integral = 0.0;
+        for _ in 0..n {
+            let direction = triangle.random_direction(&origin, 0.0, &mut sampler);
+            let ray = Ray::new(origin.clone(), direction, 0.0);
+            assert!(triangle.pdf_value(&ray, &mut sampler) > 0.0);
+            let ray = Ray::new(origin.clone(), Vec3::random_unit_vec3(&mut sampler), 0.0);
+            integral += triangle.pdf_value(&ray, &mut sampler) * 4.0 * crate::utils::PI;
+        }
+        assert!((integral / n as f64 - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_mesh() {
+        // a unit square from two triangles sharing an edge, moved and turned like any object
+        let mesh = Mesh {
+            positions: vec![
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(1.0, 0.0, 0.0),
+                Point3::new(1.0, 1.0, 0.0),
+                Point3::new(0.0, 1.0, 0.0),
+            ],
+            uvs: vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)],
+            indices: vec![[0, 1, 2], [0, 2, 3]],
+            ..Default::default()
+        };
+        let mesh = TriangleMesh::new(mesh, material());
+        assert_eq!(mesh.triangle_count(), 2);
+        let mut world = Hittables::default();
+        let transform =
+            Transform::rotate_y(90.0).then(&Transform::translate(&Vec3::new(0.0, 0.0, 5.0)));
+        world.add(Arc::new(TransformedHittable::new(
+            Arc::new(mesh),
+            transform,
+        )));
+        // rotated by 90 degrees the square lies in the y-z plane at x = 0, facing +x
+        for (y, z, u, v) in [(0.2, 4.1, 0.9, 0.2), (0.7, 4.6, 0.4, 0.7)] {
+            let ray = Ray::new(Point3::new(3.0, y, z), Vec3::new(-1.0, 0.0, 0.0), 0.0);
+            let rec = hit(&world, &ray).unwrap();
+            assert!((rec.t - 3.0).abs() < 1e-9);
+            assert!((rec.u - u).abs() < 1e-9 && (rec.v - v).abs() < 1e-9);
+            assert!((&rec.normal - &Vec3::new(1.0, 0.0, 0.0)).length() < 1e-9);
+        }
+        let miss = Ray::new(Point3::new(3.0, 0.5, 5.5), Vec3::new(-1.0, 0.0, 0.0), 0.0);
+        assert!(hit(&world, &miss).is_none());
+    }
+
+    #[test]
+    fn test_invalid_mesh() {
+        let mut mesh = Mesh {
+            positions: vec![Point3::default(); 3],
+            indices: vec![[0, 1, 3]],
+            ..Default::default()
+        };
+        assert_eq!(
+            mesh.validate(),
+            Err("vertex index 3 is out of range for 3 positions".to_string())
+        );
+        mesh.indices[0] = [0, 1, 2];
+        assert_eq!(mesh.validate(), Ok(()));
+        mesh.normals = vec![Vec3::default()];
+        assert!(mesh.validate().is_err());
+    }
+}
@@ -1,5 +1,3 @@
-use rand::distributions::uniform::SampleUniform;
-use rand::prelude::*;
 pub const INFINITY: f64 = f64::MAX;
 pub const PI: f64 = std::f64::consts::PI;
 
@@ -9,24 +7,15 @@ pub fn degrees_to_radians(d: f64) -> f64 {
 pub fn radians_to_degrees(r: f64) -> f64 {
     r * 180.0 / PI
 }
-// generate random number between 0 and 1
-pub fn random_f64() -> f64 {
-    let mut rng = rand::thread_rng();
-    let y: f64 = rng.gen(); // generates a float between 0 and 1
-    y
-}
 
-pub fn random_range<T>(min: T, max: T) -> T
-where
-    T: SampleUniform + Ord,
-{
-    let mut rng = rand::thread_rng();
-    let y: T = rng.gen_range(min..max); // generates a float between min and max
-    y
-}
-// generate random number between given min and max
-pub fn random_f64_range(min: f64, max: f64) -> f64 {
-    let mut rng = rand::thread_rng();
-    let y: f64 = rng.gen_range(min..max); // generates a float between min and max
-    y
+// the bin of `cdf` that `u` falls in and where in the bin, skipping empty bins
+pub fn sample_cdf(cdf: &[f64], u: f64) -> (usize, f64) {
+    let bin = (cdf.partition_point(|&c| c <= u).max(1) - 1).min(cdf.len() - 2);
+    let width = cdf[bin + 1] - cdf[bin];
+    let offset = if width > 0.0 {
+        ((u - cdf[bin]) / width).clamp(0.0, 1.0)
+    } else {
+        0.5
+    };
+    (bin, offset)
 }
@@ -1,4 +1,5 @@
-use crate::utils::random_f64_range;
+use crate::traits::Sampler;
+use crate::utils::PI;
 use std::fmt;
 use std::ops;
 
@@ -66,46 +67,53 @@ impl Vec3 {
         v / v.length()
     }
 
-    // generate a random Vec3 that is within unit disk, i.e., with length < 1
-    pub fn random_in_unit_disk() -> Self {
-        loop {
-            let p = Vec3 {
-                x: random_f64_range(-1.0, 1.0),
-                y: random_f64_range(-1.0, 1.0),
-                z: random_f64_range(-1.0, 1.0),
-            };
-            if p.length() < 1.0 {
-                return p;
-            }
+    // generate a random Vec3 that is within unit disk in the xy plane, i.e., with length < 1.
+    // Uses the concentric mapping, which keeps well distributed sample points well distributed
+    pub fn random_in_unit_disk(sampler: &mut dyn Sampler) -> Self {
+        let (u, v) = sampler.get_2d();
+        let (a, b) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+        if a == 0.0 && b == 0.0 {
+            return Vec3::default();
         }
+        let (r, theta) = if a.abs() > b.abs() {
+            (a, PI / 4.0 * (b / a))
+        } else {
+            (b, PI / 2.0 - PI / 4.0 * (a / b))
+        };
+        Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
     }
     // generate a random Vec3 where each element is between min and max
-    pub fn random(min: f64, max: f64) -> Self {
+    pub fn random(min: f64, max: f64, sampler: &mut dyn Sampler) -> Self {
         Vec3 {
-            x: random_f64_range(min, max),
-            y: random_f64_range(min, max),
-            z: random_f64_range(min, max),
+            x: sampler.random_f64_range(min, max),
+            y: sampler.random_f64_range(min, max),
+            z: sampler.random_f64_range(min, max),
         }
     }
 
-    pub fn random_in_unit_sphere() -> Self {
+    pub fn random_in_unit_sphere(sampler: &mut dyn Sampler) -> Self {
         loop {
-            let p = Self::random(-1.0, 1.0);
+            let p = Self::random(-1.0, 1.0, sampler);
             if p.length_squared() < 1.0 {
                 return p;
             }
         }
     }
-    pub fn random_unit_vec3() -> Self {
-        return Self::unit_vector(&Self::random_in_unit_sphere());
+    // uniformly distributed direction, from two sampler dimensions
+    pub fn random_unit_vec3(sampler: &mut dyn Sampler) -> Self {
+        let (u, v) = sampler.get_2d();
+        let z = 1.0 - 2.0 * u;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * v;
+        Vec3::new(r * phi.cos(), r * phi.sin(), z)
     }
-    pub fn random_unit_on_hemisphere(normal: &Vec3) -> Self {
-        let on_unit_sphere = Self::random_unit_vec3();
+    pub fn random_unit_on_hemisphere(normal: &Vec3, sampler: &mut dyn Sampler) -> Self {
+        let on_unit_sphere = Self::random_unit_vec3(sampler);
         if on_unit_sphere.dot(normal) > 0.0 {
             // in the same hemisphere as the normal
-            return on_unit_sphere;
+            on_unit_sphere
         } else {
-            return on_unit_sphere * -1.0;
+            on_unit_sphere * -1.0
         }
     }
 
@@ -116,7 +124,7 @@ impl Vec3 {
 
     // reflection of light
     pub fn reflect(v: &Vec3, n: &Vec3) -> Self {
-        let tmplength = 2.0 * v.dot(&n);
+        let tmplength = 2.0 * v.dot(n);
         v - &(n * tmplength)
     }
 
@@ -142,6 +150,12 @@ impl fmt::Display for Vec3 {
     }
 }
 
+impl From<[f64; 3]> for Vec3 {
+    fn from([x, y, z]: [f64; 3]) -> Self {
+        Vec3::new(x, y, z)
+    }
+}
+
 impl ops::Add<Vec3> for Vec3 {
     type Output = Vec3;
 
@@ -377,9 +391,20 @@ impl ops::IndexMut<usize> for Vec3 {
     }
 }
 #[cfg(test)]
+#[allow(unused_mut)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_random_directions() {
+        let mut sampler = crate::RandomSampler::new(1);
+        for _ in 0..100 {
+            assert!((Vec3::random_unit_vec3(&mut sampler).length() - 1.0).abs() < 1e-9);
+            let p = Vec3::random_in_unit_disk(&mut sampler);
+            assert!(p.length() <= 1.0 && p.z() == 0.0);
+        }
+    }
+
     #[test]
     fn test_default() {
         let v = Vec3::default();